@@ -1,15 +1,34 @@
 use crate::animation::*;
 use crate::bag::*;
+use crate::buoyancy::*;
 use crate::collision::*;
 use crate::drag::Drag;
-use crate::equipment::EquippedCylinderJumpEvent;
+use crate::equipment::*;
 use crate::health::*;
 use crate::position::*;
 use crate::projectile::*;
+use crate::respiration::air_sharing::*;
+use crate::respiration::bcd::*;
+use crate::respiration::breathing::*;
+use crate::respiration::chamber::ChamberTreatment;
+use crate::respiration::circulation::decompression::DecompressionSickness;
+use crate::respiration::circulation::impairment::Impairment;
+use crate::respiration::circulation::oxygen::Convulsing;
+use crate::respiration::ear::*;
+use crate::respiration::freediving::*;
+use crate::respiration::gas_mix::GasMix;
 use crate::respiration::inhalation::*;
+use crate::respiration::lungs::*;
+use crate::respiration::rebreather::*;
+use crate::respiration::regulator::{toggle_valve, Regulator, ToggleValve};
+use crate::respiration::stress::*;
+use crate::respiration::surface_interval::DiveSession;
+use crate::respiration::thermal::{CoreTemperature, Drysuit, EquippedDrysuit};
 use crate::states::*;
+use crate::wallet::Wallet;
 use crate::BreatherBundle;
 use crate::CursorPosition;
+use crate::DecompressionBundle;
 use crate::Spritesheets;
 use bevy::prelude::*;
 
@@ -26,11 +45,19 @@ const SPEAR_FIRE_RADIUS: f32 = 10.;
 
 const DIVER_INITIAL_AMMO: u32 = 3;
 const DIVER_INITIAL_BAG_SPACE: usize = 4;
+const DIVER_INITIAL_FUNDS: f32 = 100.;
 
-pub const DIVER_TANK_CAPACITY: f32 = 1000.;
-const DIVER_TANK_AMOUNT_REMAINING: f32 = 800.;
-const DIVER_TANK_OXYGEN: f32 = 0.21;
-const DIVER_TANK_NITROGEN: f32 = 0.78;
+pub const DIVER_TANK_WATER_VOLUME: f32 = 10.;
+pub const DIVER_TANK_WORKING_PRESSURE: f32 = 200.;
+const DIVER_TANK_PRESSURE: f32 = 160.;
+
+// lift in kilograms of the diver's body and gear, and the lead on their weight belt
+const DIVER_BODY_BUOYANCY: f32 = 2.;
+const DIVER_GEAR_BUOYANCY: f32 = -2.;
+const DIVER_WEIGHTS: f32 = 5.;
+const DIVER_WETSUIT_BUOYANCY: f32 = 5.;
+// millimetres of neoprene
+const DIVER_WETSUIT_THICKNESS: f32 = 5.;
 
 const DIVER_BLOODSTREAM_CAPACITY: f32 = 100.;
 const DIVER_BLOODSTREAM_AMOUNT_REMAINING: f32 = 50.;
@@ -39,6 +66,22 @@ const DIVER_BLOODSTREAM_AMOUNT_REMAINING: f32 = 50.;
 #[reflect(Component)]
 pub struct Diver;
 
+/// Divers that can't control their own movement.
+type Incapacitated = Or<(
+    With<Convulsing>,
+    With<Unconscious>,
+    With<ChamberTreatment>,
+    With<Entangled>,
+)>;
+
+/// Conditions that throw off where the diver is trying to swim.
+type Afflictions = (
+    Option<&'static DecompressionSickness>,
+    Option<&'static CoreTemperature>,
+    Option<&'static Stress>,
+    Has<Panicking>,
+);
+
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct EquippedAmmo(pub Entity);
@@ -54,23 +97,41 @@ pub struct DiverBundle {
     health: Health,
     velocity: Velocity,
     drag: Drag,
-    equipped_tank: EquippedTank,
     equipped_ammo: EquippedAmmo,
     swimming: Swimming,
+    middle_ear: MiddleEar,
+    buoyancy: Buoyancy,
+    ballast: Ballast,
+    wetsuit: Wetsuit,
+    bcd: Bcd,
+    wallet: Wallet,
     breather_bundle: BreatherBundle,
+    decompression_bundle: DecompressionBundle,
 }
 
 impl DiverBundle {
-    fn new(tank: Entity, ammo: Entity) -> Self {
+    fn new(ammo: Entity) -> Self {
         Self {
             diver: Diver,
             hitbox: RectangularHitbox(Rectangle::new(DIVER_WIDTH, DIVER_HEIGHT)),
             health: Health(100.),
             velocity: Velocity(Vec3::new(0., 0., 0.)),
             drag: Drag(DIVER_DRAG),
-            equipped_tank: EquippedTank(tank),
             equipped_ammo: EquippedAmmo(ammo),
             swimming: Swimming(Vec3::ZERO),
+            middle_ear: MiddleEar::default(),
+            buoyancy: Buoyancy::default(),
+            ballast: Ballast {
+                body: DIVER_BODY_BUOYANCY,
+                gear: DIVER_GEAR_BUOYANCY,
+                weights: DIVER_WEIGHTS,
+            },
+            wetsuit: Wetsuit {
+                surface_buoyancy: DIVER_WETSUIT_BUOYANCY,
+                thickness: DIVER_WETSUIT_THICKNESS,
+            },
+            bcd: Bcd::default(),
+            wallet: Wallet(DIVER_INITIAL_FUNDS),
             breather_bundle: BreatherBundle {
                 bloodstream_content: BloodstreamContent {
                     capacity: DIVER_BLOODSTREAM_CAPACITY,
@@ -79,6 +140,7 @@ impl DiverBundle {
                 },
                 ..default()
             },
+            decompression_bundle: DecompressionBundle::default(),
         }
     }
 }
@@ -93,14 +155,35 @@ pub fn diver_plugin(app: &mut App) {
                 .before(fire_projectile)
                 .after(update_position)
                 .after(crate::update_cursor),
-            player_inhale.before(inhalation).after(update_position),
+            player_inhale
+                .before(inhalation)
+                .before(rebreather_inhalation)
+                .before(surface_inhalation)
+                .after(update_position),
+            player_exhale.before(empty_lungs),
+            player_valsalva.before(equalize_ears),
+            player_control_bcd.before(inflate_bcd).before(dump_bcd),
+            player_toggle_valve.before(toggle_valve),
+            player_cut_free.before(cut_free),
+            player_share_air.before(share_air).before(stop_sharing_air),
+            player_rest,
+            player_toggle_breathing_assist,
+            player_hold_breath,
             player_gather,
             player_jump_cylinder,
+            player_unequip_cylinder,
+            player_toggle_rebreather,
+            player_toggle_drysuit,
         )
             .in_set(RunningStateSet)
             .in_set(NoMenuStateSet),
     );
-    app.add_systems(FixedUpdate, set_velocity_of_swimmer.before(update_position));
+    app.add_systems(
+        FixedUpdate,
+        set_velocity_of_swimmer
+            .before(apply_buoyancy)
+            .before(update_position),
+    );
     app.register_type::<Diver>();
     app.register_type::<EquippedAmmo>();
     app.register_type::<Swimming>();
@@ -111,14 +194,15 @@ pub fn spawn_diver(mut commands: Commands, spritesheets: Res<Spritesheets>) {
 
     let cylinder_id = commands
         .spawn((
-            DivingCylinder {
-                capacity: DIVER_TANK_CAPACITY,
-                amount_remaining: DIVER_TANK_AMOUNT_REMAINING,
-                proportion_of_oxygen: DIVER_TANK_OXYGEN,
-                proportion_of_nitrogen: DIVER_TANK_NITROGEN,
-            },
+            DivingCylinder::new(
+                DIVER_TANK_WATER_VOLUME,
+                DIVER_TANK_WORKING_PRESSURE,
+                DIVER_TANK_PRESSURE,
+                GasMix::AIR,
+            ),
+            Regulator::default(),
             Collectible,
-            Name::new("21%O2 78%N tank"),
+            Name::new(format!("{} tank", GasMix::AIR.name())),
         ))
         .id();
 
@@ -134,7 +218,8 @@ pub fn spawn_diver(mut commands: Commands, spritesheets: Res<Spritesheets>) {
     let animation_indices = AnimationIndices { first: 0, last: 1 };
     let diver_id = commands
         .spawn((
-            DiverBundle::new(cylinder_id, ammo_id),
+            DiverBundle::new(ammo_id),
+            EquippedTank(cylinder_id),
             Bag {
                 collectibles: vec![cylinder_id, ammo_id],
                 capacity: DIVER_INITIAL_BAG_SPACE,
@@ -163,9 +248,18 @@ pub fn spawn_diver(mut commands: Commands, spritesheets: Res<Spritesheets>) {
 
 pub fn player_control_swim(
     buttons: Res<ButtonInput<KeyCode>>,
-    mut diver: Query<&mut Swimming, With<Diver>>,
+    mut diver: Query<(Entity, &mut Swimming), With<Diver>>,
+    incapacitated: Query<(), Incapacitated>,
+    ear_pain: Query<(), With<EarPain>>,
+    vertigo: Query<&Vertigo>,
+    impairments: Query<&Impairment>,
+    afflictions: Query<Afflictions>,
 ) {
-    if let Ok(mut swimming) = diver.get_single_mut() {
+    if let Ok((diver_entity, mut swimming)) = diver.get_single_mut() {
+        if incapacitated.contains(diver_entity) {
+            *swimming = Swimming(Vec3::ZERO);
+            return;
+        }
         let vertical = if buttons.pressed(KeyCode::ArrowUp) {
             Vec3::new(0., DIVER_SPEED, 0.)
         } else if buttons.pressed(KeyCode::ArrowDown) {
@@ -182,10 +276,138 @@ pub fn player_control_swim(
             Vec3::ZERO
         };
 
-        *swimming = Swimming((horizontal + vertical).normalize_or_zero() * DIVER_SPEED);
+        let mut intended = (horizontal + vertical).normalize_or_zero() * DIVER_SPEED;
+        let (dcs, core, stress, panicking) = afflictions.get(diver_entity).unwrap_or_default();
+        // a panicking diver bolts for the surface whatever they try to do
+        if panicking {
+            intended = Vec3::new(0., DIVER_SPEED, 0.);
+        }
+        if let Ok(vertigo) = vertigo.get(diver_entity) {
+            intended = vertigo.disturb(intended);
+        }
+        if ear_pain.contains(diver_entity) {
+            intended = slow_descent(intended);
+        }
+        if let Some(dcs) = dcs {
+            intended = dcs.affect_swimming(intended);
+        }
+        if let Some(core) = core {
+            intended = core.shiver(intended, rand::random::<f32>() * 2. - 1.);
+        }
+        if let Some(stress) = stress {
+            intended = stress.agitate(intended, rand::random::<f32>() * 2. - 1.);
+        }
+        if let Ok(impairment) = impairments.get(diver_entity) {
+            intended = impairment.jitter(
+                impairment.lag(swimming.0, intended),
+                rand::random::<f32>() * 2. - 1.,
+            );
+        }
+        *swimming = Swimming(intended);
     }
 }
 
+#[test]
+fn convulsing_cannot_swim() {
+    let mut app = App::new();
+    app.add_systems(Update, player_control_swim);
+    let mut keys = ButtonInput::<KeyCode>::default();
+    keys.press(KeyCode::ArrowUp);
+    app.insert_resource(keys);
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            Diver,
+            Swimming(Vec3::new(1., 0., 0.)),
+            Convulsing(Timer::from_seconds(1., TimerMode::Once)),
+        ))
+        .id();
+    app.update();
+    let swimming = app.world().get::<Swimming>(diver_id).unwrap();
+    assert_eq!(swimming.0, Vec3::ZERO);
+}
+
+#[test]
+fn unconscious_cannot_swim() {
+    let mut app = App::new();
+    app.add_systems(Update, player_control_swim);
+    let mut keys = ButtonInput::<KeyCode>::default();
+    keys.press(KeyCode::ArrowUp);
+    app.insert_resource(keys);
+    let diver_id = app
+        .world_mut()
+        .spawn((Diver, Swimming(Vec3::new(1., 0., 0.)), Unconscious))
+        .id();
+    app.update();
+    let swimming = app.world().get::<Swimming>(diver_id).unwrap();
+    assert_eq!(swimming.0, Vec3::ZERO);
+}
+
+#[test]
+fn ear_pain_slows_descent() {
+    let mut app = App::new();
+    app.add_systems(Update, player_control_swim);
+    let mut keys = ButtonInput::<KeyCode>::default();
+    keys.press(KeyCode::ArrowDown);
+    app.insert_resource(keys);
+    let diver_id = app
+        .world_mut()
+        .spawn((Diver, Swimming(Vec3::ZERO), EarPain))
+        .id();
+    app.update();
+    let swimming = app.world().get::<Swimming>(diver_id).unwrap();
+    assert!(swimming.0.y < 0.);
+    assert!(swimming.0.y > -DIVER_SPEED);
+}
+
+#[test]
+fn narcosis_lags_swimming() {
+    let mut app = App::new();
+    app.add_systems(Update, player_control_swim);
+    let mut keys = ButtonInput::<KeyCode>::default();
+    keys.press(KeyCode::ArrowUp);
+    app.insert_resource(keys);
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            Diver,
+            Swimming(Vec3::new(DIVER_SPEED, 0., 0.)),
+            Impairment {
+                narcosis: 1.,
+                hypoxia: 0.,
+            },
+        ))
+        .id();
+    app.update();
+    // should still be heading mostly the way it was
+    let swimming = app.world().get::<Swimming>(diver_id).unwrap();
+    assert!(swimming.0.x > swimming.0.y.abs());
+}
+
+#[test]
+fn panicking_bolts_for_surface() {
+    let mut app = App::new();
+    app.add_systems(Update, player_control_swim);
+    let mut keys = ButtonInput::<KeyCode>::default();
+    keys.press(KeyCode::ArrowDown);
+    app.insert_resource(keys);
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            Diver,
+            Swimming(Vec3::ZERO),
+            Stress {
+                level: 1.,
+                ..default()
+            },
+            Panicking,
+        ))
+        .id();
+    app.update();
+    let swimming = app.world().get::<Swimming>(diver_id).unwrap();
+    assert!(swimming.0.y > 0.);
+}
+
 pub fn set_velocity_of_swimmer(mut swimmers: Query<(&mut Velocity, &Swimming)>) {
     for (mut velocity, swimming) in &mut swimmers {
         if swimming.0 != Vec3::ZERO {
@@ -223,13 +445,23 @@ fn did_not_set_velocity() {
 pub fn fire_speargun(
     buttons: Res<ButtonInput<MouseButton>>,
     cursor_position: Res<CursorPosition>,
-    diver: Query<(&Transform, &Velocity, &EquippedAmmo), With<Diver>>,
+    diver: Query<(Entity, &Transform, &Velocity, &EquippedAmmo), With<Diver>>,
+    impairments: Query<&Impairment>,
     mut fire_events: EventWriter<FireProjectile>,
 ) {
-    if let Ok((transform, velocity, ammo)) = diver.get_single() {
+    if let Ok((diver_entity, transform, velocity, ammo)) = diver.get_single() {
         if buttons.just_pressed(MouseButton::Left) {
             let diver_position = Vec2::new(transform.translation.x, transform.translation.y);
-            if let Some(direction) = (cursor_position.0 - diver_position).try_normalize() {
+            let aim = (cursor_position.0 - diver_position).extend(0.);
+            // a narced diver is slow to turn away from where they are swimming, and shaky
+            let aim = match impairments.get(diver_entity) {
+                Ok(impairment) => impairment.jitter(
+                    impairment.lag(velocity.0.normalize_or_zero() * aim.length(), aim),
+                    rand::random::<f32>() * 2. - 1.,
+                ),
+                _ => aim,
+            };
+            if let Some(direction) = aim.truncate().try_normalize() {
                 let spawn_position = diver_position + SPEAR_FIRE_RADIUS * direction;
                 fire_events.send(FireProjectile {
                     translation: Vec3::new(spawn_position.x, spawn_position.y, 0.),
@@ -296,6 +528,151 @@ pub fn player_inhale(
     }
 }
 
+pub fn player_exhale(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<Entity, With<Diver>>,
+    mut exhales: EventWriter<BreathExhaled>,
+) {
+    if let Ok(diver_entity) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyX) {
+            exhales.send(BreathExhaled {
+                entity: diver_entity,
+            });
+        }
+    }
+}
+
+pub fn player_valsalva(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<Entity, With<Diver>>,
+    mut valsalvas: EventWriter<Valsalva>,
+) {
+    if let Ok(diver_entity) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyE) {
+            valsalvas.send(Valsalva {
+                entity: diver_entity,
+            });
+        }
+    }
+}
+
+pub fn player_control_bcd(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<Entity, With<Diver>>,
+    mut inflations: EventWriter<InflateBcd>,
+    mut dumps: EventWriter<DumpBcd>,
+) {
+    if let Ok(diver_entity) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyB) {
+            inflations.send(InflateBcd {
+                entity: diver_entity,
+            });
+        } else if buttons.just_pressed(KeyCode::KeyV) {
+            dumps.send(DumpBcd {
+                entity: diver_entity,
+            });
+        }
+    }
+}
+
+pub fn player_toggle_valve(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<Entity, With<Diver>>,
+    mut toggles: EventWriter<ToggleValve>,
+) {
+    if let Ok(diver_entity) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyT) {
+            toggles.send(ToggleValve {
+                entity: diver_entity,
+            });
+        }
+    }
+}
+
+pub fn player_cut_free(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<Entity, With<Diver>>,
+    mut cut_free_events: EventWriter<CutFree>,
+) {
+    if let Ok(diver_entity) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyK) {
+            cut_free_events.send(CutFree {
+                entity: diver_entity,
+            });
+        }
+    }
+}
+
+/// Takes the octopus of the nearest diver in reach, or hands it back.
+pub fn player_share_air(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<(Entity, &Transform, Has<SharingAir>), With<Diver>>,
+    donors: Query<(Entity, &Transform), With<EquippedTank>>,
+    mut shares: EventWriter<ShareAir>,
+    mut stops: EventWriter<StopSharingAir>,
+) {
+    if !buttons.just_pressed(KeyCode::KeyO) {
+        return;
+    }
+    if let Ok((diver_entity, diver_transform, sharing)) = diver.get_single() {
+        if sharing {
+            stops.send(StopSharingAir {
+                receiver: diver_entity,
+            });
+            return;
+        }
+        let nearest = donors
+            .iter()
+            .filter(|(donor, _)| *donor != diver_entity)
+            .map(|(donor, transform)| {
+                (
+                    donor,
+                    transform.translation.distance(diver_transform.translation),
+                )
+            })
+            .filter(|(_, distance)| *distance <= SHARE_AIR_RANGE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((donor, _)) = nearest {
+            shares.send(ShareAir {
+                receiver: diver_entity,
+                donor,
+            });
+        }
+    }
+}
+
+/// Holding the rest key at the surface passes the surface interval quickly.
+pub fn player_rest(
+    buttons: Res<ButtonInput<KeyCode>>,
+    mut diver: Query<&mut DiveSession, With<Diver>>,
+) {
+    if let Ok(mut session) = diver.get_single_mut() {
+        session.resting = buttons.pressed(KeyCode::KeyZ);
+    }
+}
+
+pub fn player_toggle_breathing_assist(
+    buttons: Res<ButtonInput<KeyCode>>,
+    mut assist: ResMut<BreathingAssist>,
+) {
+    if buttons.just_pressed(KeyCode::KeyA) {
+        *assist = match *assist {
+            BreathingAssist::Manual => BreathingAssist::Automatic,
+            BreathingAssist::Automatic => BreathingAssist::Manual,
+        };
+    }
+}
+
+/// With the breathing assist on, holding the key holds the breath in.
+pub fn player_hold_breath(
+    buttons: Res<ButtonInput<KeyCode>>,
+    mut diver: Query<&mut Breathing, With<Diver>>,
+) {
+    if let Ok(mut breathing) = diver.get_single_mut() {
+        breathing.holding = buttons.pressed(KeyCode::KeyH);
+    }
+}
+
 pub fn player_gather(
     mut commands: Commands,
     buttons: Res<ButtonInput<KeyCode>>,
@@ -331,3 +708,69 @@ pub fn player_jump_cylinder(
         }
     }
 }
+
+pub fn player_unequip_cylinder(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<Entity, (With<Diver>, With<EquippedTank>)>,
+    mut unequip_events: EventWriter<CylinderUnequipEvent>,
+) {
+    if let Ok(diver_entity) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyU) {
+            unequip_events.send(CylinderUnequipEvent {
+                wearer: diver_entity,
+            });
+        }
+    }
+}
+
+pub fn player_toggle_rebreather(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<(Entity, &Bag, Option<&EquippedRebreather>), With<Diver>>,
+    rebreathers: Query<Entity, With<Rebreather>>,
+    mut equip_events: EventWriter<RebreatherEquipEvent>,
+    mut unequip_events: EventWriter<RebreatherUnequipEvent>,
+) {
+    if let Ok((diver_entity, bag, equipped_rebreather)) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyR) {
+            if equipped_rebreather.is_some() {
+                unequip_events.send(RebreatherUnequipEvent {
+                    wearer: diver_entity,
+                });
+            } else if let Some(rebreather) = bag
+                .collectibles
+                .iter()
+                .find(|e| rebreathers.get(**e).is_ok())
+            {
+                equip_events.send(RebreatherEquipEvent {
+                    item: *rebreather,
+                    wearer: diver_entity,
+                });
+            }
+        }
+    }
+}
+
+pub fn player_toggle_drysuit(
+    buttons: Res<ButtonInput<KeyCode>>,
+    diver: Query<(Entity, &Bag, Option<&EquippedDrysuit>), With<Diver>>,
+    drysuits: Query<Entity, With<Drysuit>>,
+    mut equip_events: EventWriter<DrysuitEquipEvent>,
+    mut unequip_events: EventWriter<DrysuitUnequipEvent>,
+) {
+    if let Ok((diver_entity, bag, equipped_drysuit)) = diver.get_single() {
+        if buttons.just_pressed(KeyCode::KeyD) {
+            if equipped_drysuit.is_some() {
+                unequip_events.send(DrysuitUnequipEvent {
+                    wearer: diver_entity,
+                });
+            } else if let Some(drysuit) =
+                bag.collectibles.iter().find(|e| drysuits.get(**e).is_ok())
+            {
+                equip_events.send(DrysuitEquipEvent {
+                    item: *drysuit,
+                    wearer: diver_entity,
+                });
+            }
+        }
+    }
+}
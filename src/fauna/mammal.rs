@@ -0,0 +1,334 @@
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+
+use crate::drag::Drag;
+use crate::position::*;
+use crate::respiration::breathing::Breathing;
+use crate::respiration::circulation::oxygen::OxygenHazard;
+use crate::respiration::freediving::{BlackoutPo2, Unconscious};
+use crate::respiration::inhalation::BloodstreamContent;
+use crate::respiration::thermal::Blubber;
+use crate::states::RunningStateSet;
+use crate::BreatherBundle;
+use crate::Dead;
+use crate::Health;
+#[cfg(test)]
+use crate::{
+    environment::DiveSiteEnvironment,
+    respiration::breathing::breathe,
+    respiration::circulation::decompression::{decompression_sickness, load_tissues, PendingDcs},
+    respiration::circulation::equalization::equalize_gases,
+    respiration::circulation::CirculateGas,
+    respiration::freediving::surface_inhalation,
+    respiration::inhalation::BreathTaken,
+    respiration::lungs::BreathExhaled,
+    respiration::HazardRng,
+    DecompressionBundle,
+};
+
+const MAMMAL_SPEED: f32 = 0.4;
+const MAMMAL_DRAG: f32 = 0.95;
+const MAMMAL_HEALTH: f32 = 60.;
+const MAMMAL_BLOODSTREAM_CAPACITY: f32 = 100.;
+// breaths per minute at the surface
+const MAMMAL_BREATHING_RATE: f32 = 20.;
+// marine mammals carry on well below the PO2 that would knock out a human
+const MAMMAL_MIN_PO2: f32 = 0.08;
+const MAMMAL_BLACKOUT_PO2: f32 = 0.05;
+const MAMMAL_HYPOXIA_DAMAGE: f32 = 10.;
+const MAMMAL_BLUBBER: f32 = 10.;
+// proportion of oxygen left in the lungs where a mammal heads back up for air, and where it's
+// ready to dive again
+const SURFACING_OXYGEN: f32 = 0.14;
+const DIVING_OXYGEN: f32 = 0.19;
+// how far, in pixels, a mammal swims either side of where it was spawned
+const PATROL_RANGE: f32 = 150.;
+
+type Diving<'a> = (
+    &'a mut MarineMammal,
+    &'a mut Velocity,
+    &'a mut Breathing,
+    &'a Transform,
+    &'a Depth,
+    &'a BloodstreamContent,
+);
+
+/// An air-breathing animal that forages at `forage_depth` and has to come up to breathe.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct MarineMammal {
+    pub forage_depth: f32,
+    pub surfacing: bool,
+    /// Horizontal position the mammal patrols around.
+    pub home: f32,
+    /// 1 when swimming east, -1 when swimming west.
+    pub heading: f32,
+}
+
+#[derive(Bundle)]
+pub struct MarineMammalBundle {
+    marine_mammal: MarineMammal,
+    health: Health,
+    velocity: Velocity,
+    drag: Drag,
+    breathing: Breathing,
+    blubber: Blubber,
+    breather_bundle: BreatherBundle,
+}
+
+impl MarineMammalBundle {
+    fn new(x: f32, forage_depth: f32) -> Self {
+        Self {
+            marine_mammal: MarineMammal {
+                forage_depth,
+                surfacing: false,
+                home: x,
+                heading: 1.,
+            },
+            health: Health(MAMMAL_HEALTH),
+            velocity: Velocity(Vec3::ZERO),
+            drag: Drag(MAMMAL_DRAG),
+            breathing: Breathing {
+                rate: MAMMAL_BREATHING_RATE,
+                ..default()
+            },
+            blubber: Blubber(MAMMAL_BLUBBER),
+            breather_bundle: BreatherBundle {
+                bloodstream_content: BloodstreamContent {
+                    capacity: MAMMAL_BLOODSTREAM_CAPACITY,
+                    amount_remaining: MAMMAL_BLOODSTREAM_CAPACITY,
+                    ..default()
+                },
+                oxygen_hazard: OxygenHazard::tolerating(MAMMAL_MIN_PO2, MAMMAL_HYPOXIA_DAMAGE),
+                blackout_po2: BlackoutPo2(MAMMAL_BLACKOUT_PO2),
+                ..default()
+            },
+        }
+    }
+}
+
+pub fn mammal_plugin(app: &mut App) {
+    app.add_systems(Startup, spawn_mammals);
+    app.add_systems(
+        FixedUpdate,
+        mammal_dive.before(update_position).in_set(RunningStateSet),
+    );
+    app.register_type::<MarineMammal>();
+}
+
+pub fn spawn_mammals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut spawn_mammal = |name: &str, x: f32, forage_depth: f32, size: Vec2, colour: Srgba| {
+        let mesh = Mesh::from(Rectangle::new(size.x, size.y));
+        let material = ColorMaterial::from_color(colour);
+        let mesh_handle = meshes.add(mesh);
+        let material_handle = materials.add(material);
+        commands.spawn((
+            MarineMammalBundle::new(x, forage_depth),
+            MaterialMesh2dBundle {
+                mesh: mesh_handle.into(),
+                material: material_handle,
+                transform: Transform::from_translation(Vec3::new(x, SEA_LEVEL, 0.)),
+                ..default()
+            },
+            Name::new(name.to_string()),
+            crate::PIXEL_PERFECT_LAYERS,
+        ));
+    };
+
+    spawn_mammal(
+        "Seal",
+        150.,
+        20.,
+        Vec2::new(12., 5.),
+        Srgba::rgb(0.4, 0.4, 0.35),
+    );
+    spawn_mammal(
+        "Dolphin",
+        300.,
+        30.,
+        Vec2::new(16., 5.),
+        Srgba::rgb(0.5, 0.6, 0.7),
+    );
+}
+
+/// Mammals head for the surface once they're running low on oxygen, breathe there until they've
+/// caught their breath, then go back down to forage, holding their breath the whole way.
+pub fn mammal_dive(mut mammals: Query<Diving, (Without<Unconscious>, Without<Dead>)>) {
+    for (mut mammal, mut velocity, mut breathing, transform, depth, bloodstream_content) in
+        &mut mammals
+    {
+        let oxygen = bloodstream_content.mix.oxygen;
+        if !mammal.surfacing && oxygen < SURFACING_OXYGEN {
+            mammal.surfacing = true;
+        } else if mammal.surfacing && depth.0 <= 0. && oxygen >= DIVING_OXYGEN {
+            mammal.surfacing = false;
+        }
+        breathing.holding = depth.0 > 0.;
+
+        let offset = transform.translation.x - mammal.home;
+        if offset.abs() > PATROL_RANGE && offset.signum() == mammal.heading {
+            mammal.heading = -mammal.heading;
+        }
+        let vertical = if mammal.surfacing {
+            if transform.translation.y < SEA_LEVEL {
+                1.
+            } else {
+                0.
+            }
+        } else if depth.0 < mammal.forage_depth {
+            -1.
+        } else {
+            0.
+        };
+        velocity.0 = Vec3::new(mammal.heading, vertical, 0.).normalize() * MAMMAL_SPEED;
+    }
+}
+
+#[test]
+fn did_surface_for_air() {
+    let mut app = App::new();
+    app.add_systems(Update, mammal_dive);
+    let mut foraging = MarineMammalBundle::new(0., 20.);
+    foraging.breather_bundle.bloodstream_content.mix.oxygen = 0.2;
+    foraging.breather_bundle.depth = Depth(10.);
+    let foraging_id = app
+        .world_mut()
+        .spawn((
+            foraging,
+            Transform::from_translation(Vec3::new(0., SEA_LEVEL - 100., 0.)),
+        ))
+        .id();
+    let mut breathless = MarineMammalBundle::new(0., 20.);
+    breathless.breather_bundle.bloodstream_content.mix.oxygen = SURFACING_OXYGEN / 2.;
+    breathless.breather_bundle.depth = Depth(10.);
+    let breathless_id = app
+        .world_mut()
+        .spawn((
+            breathless,
+            Transform::from_translation(Vec3::new(0., SEA_LEVEL - 100., 0.)),
+        ))
+        .id();
+    let mut recovering = MarineMammalBundle::new(0., 20.);
+    recovering.breather_bundle.bloodstream_content.mix.oxygen = SURFACING_OXYGEN;
+    recovering.marine_mammal.surfacing = true;
+    let recovering_id = app
+        .world_mut()
+        .spawn((
+            recovering,
+            Transform::from_translation(Vec3::new(0., SEA_LEVEL, 0.)),
+        ))
+        .id();
+    let mut recovered = MarineMammalBundle::new(0., 20.);
+    recovered.breather_bundle.bloodstream_content.mix.oxygen = DIVING_OXYGEN;
+    recovered.marine_mammal.surfacing = true;
+    let recovered_id = app
+        .world_mut()
+        .spawn((
+            recovered,
+            Transform::from_translation(Vec3::new(0., SEA_LEVEL, 0.)),
+        ))
+        .id();
+    app.update();
+    let mammal = |id: Entity| {
+        (
+            app.world().get::<MarineMammal>(id).unwrap().surfacing,
+            app.world().get::<Velocity>(id).unwrap().0.y,
+            app.world().get::<Breathing>(id).unwrap().holding,
+        )
+    };
+    let (surfacing, vertical, holding) = mammal(foraging_id);
+    assert!(!surfacing && vertical < 0. && holding);
+    let (surfacing, vertical, holding) = mammal(breathless_id);
+    assert!(surfacing && vertical > 0. && holding);
+    // breathing at the surface until the oxygen is back up
+    let (surfacing, vertical, holding) = mammal(recovering_id);
+    assert!(surfacing && vertical == 0. && !holding);
+    let (surfacing, vertical, _) = mammal(recovered_id);
+    assert!(!surfacing && vertical < 0.);
+}
+
+#[test]
+fn did_patrol() {
+    let mut app = App::new();
+    app.add_systems(Update, mammal_dive);
+    let mammal_id = app
+        .world_mut()
+        .spawn((
+            MarineMammalBundle::new(0., 20.),
+            Transform::from_translation(Vec3::new(PATROL_RANGE * 2., -200., 0.)),
+        ))
+        .id();
+    app.update();
+    let velocity = app.world().get::<Velocity>(mammal_id).unwrap();
+    assert!(velocity.0.x < 0.);
+}
+
+#[test]
+fn breathes_like_a_diver() {
+    // the same breathing and circulation systems that keep the diver alive work for a mammal
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<BreathTaken>();
+    app.add_event::<BreathExhaled>();
+    app.add_event::<CirculateGas>();
+    app.add_systems(
+        Update,
+        (
+            breathe,
+            surface_inhalation.after(breathe),
+            equalize_gases.after(surface_inhalation),
+        ),
+    );
+    let mut bundle = MarineMammalBundle::new(0., 20.);
+    bundle.breather_bundle.bloodstream_content.amount_remaining = MAMMAL_BLOODSTREAM_CAPACITY / 2.;
+    bundle.breather_bundle.bloodstream_content.mix.oxygen = SURFACING_OXYGEN;
+    let mammal_id = app.world_mut().spawn(bundle).id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(5));
+    app.update();
+    let bloodstream_content = app.world().get::<BloodstreamContent>(mammal_id).unwrap();
+    assert!(bloodstream_content.mix.oxygen > SURFACING_OXYGEN);
+}
+
+#[test]
+fn did_not_get_bent() {
+    // a day of foraging dives that would bend a diver doing the same profile
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.init_resource::<HazardRng>();
+    app.add_systems(
+        Update,
+        (load_tissues, decompression_sickness.after(load_tissues)),
+    );
+    let mammal_id = app.world_mut().spawn(MarineMammalBundle::new(0., 30.)).id();
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            BreatherBundle::default(),
+            DecompressionBundle::default(),
+            Health(100.),
+        ))
+        .id();
+    for _ in 0..20 {
+        for (depth, seconds) in [(30., 180), (0., 60)] {
+            for id in [mammal_id, diver_id] {
+                app.world_mut().get_mut::<Depth>(id).unwrap().0 = depth;
+            }
+            for _ in 0..seconds {
+                app.world_mut()
+                    .resource_mut::<Time>()
+                    .advance_by(std::time::Duration::from_secs(1));
+                app.update();
+            }
+        }
+    }
+    let bent = |id: Entity| app.world().get::<PendingDcs>(id).is_some();
+    assert!(bent(diver_id));
+    assert!(!bent(mammal_id));
+}
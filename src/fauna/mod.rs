@@ -1,8 +1,10 @@
 use bevy::prelude::*;
 use enemy::*;
+use mammal::*;
 
 pub mod enemy;
+pub mod mammal;
 
 pub fn fauna_plugin(app: &mut App) {
-    app.add_plugins(enemy_plugin);
+    app.add_plugins((enemy_plugin, mammal_plugin));
 }
@@ -2,10 +2,19 @@ use bevy::prelude::*;
 
 use crate::bag::Bag;
 use crate::diver::Diver;
+use crate::environment::DiveSiteEnvironment;
 use crate::inhalation::*;
 use crate::inventory::inventory_menu::*;
+use crate::position::Depth;
+#[cfg(test)]
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::rebreather::*;
+use crate::respiration::thermal::*;
 use crate::states::*;
 
+// seconds a switch to an unsafe cylinder waits to be confirmed before it's forgotten
+const GAS_SWITCH_CONFIRM_SECONDS: f32 = 10.;
+
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct Equippable;
@@ -18,6 +27,33 @@ pub struct Equipped(pub Entity);
 #[reflect(Component)]
 pub struct EquipmentMenu;
 
+/// A switch to a cylinder that isn't safe to breathe at the wearer's depth, waiting for the same
+/// switch to be asked for again before `expiry` runs out.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct PendingGasSwitch {
+    pub cylinder: Entity,
+    pub expiry: Timer,
+}
+
+impl PendingGasSwitch {
+    pub fn new(cylinder: Entity) -> Self {
+        Self {
+            cylinder,
+            expiry: Timer::from_seconds(GAS_SWITCH_CONFIRM_SECONDS, TimerMode::Once),
+        }
+    }
+}
+
+/// How switches to a cylinder outside its operating depths are treated, as a difficulty setting.
+#[derive(Resource, Default, Reflect)]
+#[reflect(Resource)]
+pub enum GasSwitchPolicy {
+    #[default]
+    Confirm,
+    Block,
+}
+
 #[derive(Event)]
 pub struct CylinderEquipEvent {
     pub item: Entity,
@@ -29,6 +65,28 @@ pub struct CylinderUnequipEvent {
     pub wearer: Entity,
 }
 
+#[derive(Event)]
+pub struct RebreatherEquipEvent {
+    pub item: Entity,
+    pub wearer: Entity,
+}
+
+#[derive(Event)]
+pub struct RebreatherUnequipEvent {
+    pub wearer: Entity,
+}
+
+#[derive(Event)]
+pub struct DrysuitEquipEvent {
+    pub item: Entity,
+    pub wearer: Entity,
+}
+
+#[derive(Event)]
+pub struct DrysuitUnequipEvent {
+    pub wearer: Entity,
+}
+
 #[derive(Event)]
 pub struct EquippedCylinderJumpEvent {
     pub i: i32,
@@ -38,10 +96,25 @@ pub struct EquippedCylinderJumpEvent {
 pub fn equipment_plugin(app: &mut App) {
     app.add_event::<CylinderEquipEvent>();
     app.add_event::<CylinderUnequipEvent>();
+    app.add_event::<RebreatherEquipEvent>();
+    app.add_event::<RebreatherUnequipEvent>();
+    app.add_event::<DrysuitEquipEvent>();
+    app.add_event::<DrysuitUnequipEvent>();
     app.add_event::<EquippedCylinderJumpEvent>();
+    app.init_resource::<GasSwitchPolicy>();
     app.add_systems(
         FixedUpdate,
-        (equip_cylinder, unequip_cylinder, equipped_cylinder_jump).in_set(RunningStateSet),
+        (
+            equip_cylinder,
+            unequip_cylinder,
+            equip_rebreather,
+            unequip_rebreather,
+            equip_drysuit,
+            unequip_drysuit,
+            equipped_cylinder_jump,
+            expire_gas_switch.after(equip_cylinder),
+        )
+            .in_set(RunningStateSet),
     );
     app.add_systems(Update, toggle_inventory);
     app.add_systems(
@@ -51,14 +124,52 @@ pub fn equipment_plugin(app: &mut App) {
     app.register_type::<Equippable>();
     app.register_type::<Equipped>();
     app.register_type::<EquipmentMenu>();
+    app.register_type::<PendingGasSwitch>();
+    app.register_type::<GasSwitchPolicy>();
 }
 
+/// Switching to a gas that's unsafe at the wearer's depth has to be asked for twice, or isn't
+/// allowed at all, depending on the `GasSwitchPolicy`.
 pub fn equip_cylinder(
     mut commands: Commands,
+    policy: Res<GasSwitchPolicy>,
+    environment: Res<DiveSiteEnvironment>,
     mut wearers: Query<&mut EquippedTank>,
+    switchers: Query<(Option<&Depth>, Option<&PendingGasSwitch>)>,
+    cylinders: Query<&DivingCylinder>,
     mut cylinder_equip_events: EventReader<CylinderEquipEvent>,
 ) {
     for cylinder_equip_event in cylinder_equip_events.read() {
+        let (depth, pending) = switchers
+            .get(cylinder_equip_event.wearer)
+            .unwrap_or((None, None));
+        let suitability = match (depth, cylinders.get(cylinder_equip_event.item)) {
+            (Some(depth), Ok(cylinder)) => cylinder.suitability(&environment, depth.0),
+            _ => GasSuitability::Breathable,
+        };
+        if !suitability.is_safe() {
+            let confirmed =
+                pending.is_some_and(|pending| pending.cylinder == cylinder_equip_event.item);
+            match *policy {
+                GasSwitchPolicy::Block => {
+                    println!("gas switch blocked: {:?}", suitability);
+                    continue;
+                }
+                GasSwitchPolicy::Confirm if !confirmed => {
+                    println!("gas switch needs confirming: {:?}", suitability);
+                    commands
+                        .entity(cylinder_equip_event.wearer)
+                        .insert(PendingGasSwitch::new(cylinder_equip_event.item));
+                    continue;
+                }
+                GasSwitchPolicy::Confirm => {}
+            }
+        }
+        if pending.is_some() {
+            commands
+                .entity(cylinder_equip_event.wearer)
+                .remove::<PendingGasSwitch>();
+        }
         if let Ok(mut equipped_tank) = wearers.get_mut(cylinder_equip_event.wearer) {
             commands.entity(equipped_tank.0).remove::<Equipped>();
             equipped_tank.0 = cylinder_equip_event.item;
@@ -77,16 +188,16 @@ pub fn equip_cylinder(
 fn did_equip_cylinder() {
     let mut app = App::new();
     app.add_event::<CylinderEquipEvent>();
+    app.init_resource::<GasSwitchPolicy>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, equip_cylinder);
     let cylinder_id = app
         .world_mut()
         .spawn((
             Equippable,
             DivingCylinder {
-                capacity: 0.,
                 amount_remaining: 0.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                ..default()
             },
         ))
         .id();
@@ -108,16 +219,16 @@ fn did_equip_cylinder() {
 fn did_replace_cylinder() {
     let mut app = App::new();
     app.add_event::<CylinderEquipEvent>();
+    app.init_resource::<GasSwitchPolicy>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, equip_cylinder);
     let cylinder_1_id = app
         .world_mut()
         .spawn((
             Equippable,
             DivingCylinder {
-                capacity: 0.,
                 amount_remaining: 0.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                ..default()
             },
         ))
         .id();
@@ -126,10 +237,8 @@ fn did_replace_cylinder() {
         .spawn((
             Equippable,
             DivingCylinder {
-                capacity: 0.,
                 amount_remaining: 0.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                ..default()
             },
         ))
         .id();
@@ -154,6 +263,142 @@ fn did_replace_cylinder() {
     assert!(app.world().get::<Equipped>(cylinder_1_id).is_none());
 }
 
+#[test]
+fn did_confirm_unsafe_switch() {
+    let mut app = App::new();
+    app.add_event::<CylinderEquipEvent>();
+    app.init_resource::<GasSwitchPolicy>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, equip_cylinder);
+    let air_id = app
+        .world_mut()
+        .spawn((Equippable, DivingCylinder::default()))
+        .id();
+    let deco_id = app
+        .world_mut()
+        .spawn((
+            Equippable,
+            DivingCylinder {
+                mix: GasMix::nitrox(0.5).unwrap(),
+                ..default()
+            },
+        ))
+        .id();
+    let wearer_id = app
+        .world_mut()
+        .spawn((EquippedTank(air_id), Depth(40.)))
+        .id();
+    let switch_to_deco = |app: &mut App| {
+        app.world_mut()
+            .resource_mut::<Events<CylinderEquipEvent>>()
+            .send(CylinderEquipEvent {
+                item: deco_id,
+                wearer: wearer_id,
+            });
+        app.update();
+    };
+    // EAN50 at 40 metres needs asking for twice
+    switch_to_deco(&mut app);
+    assert_eq!(
+        app.world().get::<EquippedTank>(wearer_id).unwrap().0,
+        air_id
+    );
+    assert_eq!(
+        app.world()
+            .get::<PendingGasSwitch>(wearer_id)
+            .unwrap()
+            .cylinder,
+        deco_id
+    );
+    switch_to_deco(&mut app);
+    assert_eq!(
+        app.world().get::<EquippedTank>(wearer_id).unwrap().0,
+        deco_id
+    );
+    assert!(app.world().get::<PendingGasSwitch>(wearer_id).is_none());
+}
+
+#[test]
+fn did_block_unsafe_switch() {
+    let mut app = App::new();
+    app.add_event::<CylinderEquipEvent>();
+    app.insert_resource(GasSwitchPolicy::Block);
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, equip_cylinder);
+    let air_id = app
+        .world_mut()
+        .spawn((Equippable, DivingCylinder::default()))
+        .id();
+    let deco_id = app
+        .world_mut()
+        .spawn((
+            Equippable,
+            DivingCylinder {
+                mix: GasMix::nitrox(0.5).unwrap(),
+                ..default()
+            },
+        ))
+        .id();
+    let wearer_id = app
+        .world_mut()
+        .spawn((EquippedTank(air_id), Depth(40.)))
+        .id();
+    for _ in 0..2 {
+        app.world_mut()
+            .resource_mut::<Events<CylinderEquipEvent>>()
+            .send(CylinderEquipEvent {
+                item: deco_id,
+                wearer: wearer_id,
+            });
+        app.update();
+    }
+    assert_eq!(
+        app.world().get::<EquippedTank>(wearer_id).unwrap().0,
+        air_id
+    );
+}
+
+/// An unconfirmed switch is dropped after a while, so a confirmation much later, or at another
+/// depth, starts over with a fresh warning.
+pub fn expire_gas_switch(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut switchers: Query<(Entity, &mut PendingGasSwitch)>,
+) {
+    for (entity, mut pending) in &mut switchers {
+        pending.expiry.tick(time.delta());
+        if pending.expiry.finished() {
+            commands.entity(entity).remove::<PendingGasSwitch>();
+        }
+    }
+}
+
+#[test]
+fn did_expire_gas_switch() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, expire_gas_switch);
+    let cylinder_id = app.world_mut().spawn(DivingCylinder::default()).id();
+    let wearer_id = app
+        .world_mut()
+        .spawn(PendingGasSwitch::new(cylinder_id))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs_f32(
+            GAS_SWITCH_CONFIRM_SECONDS / 2.,
+        ));
+    app.update();
+    assert!(app.world().get::<PendingGasSwitch>(wearer_id).is_some());
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs_f32(
+            GAS_SWITCH_CONFIRM_SECONDS / 2.,
+        ));
+    app.update();
+    assert!(app.world().get::<PendingGasSwitch>(wearer_id).is_none());
+}
+
 pub fn unequip_cylinder(
     mut commands: Commands,
     equipped_cylinders: Query<&EquippedTank>,
@@ -181,10 +426,8 @@ fn did_unequip_cylinder() {
         .spawn((
             Equippable,
             DivingCylinder {
-                capacity: 0.,
                 amount_remaining: 0.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                ..default()
             },
         ))
         .id();
@@ -203,38 +446,216 @@ fn did_unequip_cylinder() {
     assert!(worn_cylinder.is_none());
 }
 
+pub fn equip_rebreather(
+    mut commands: Commands,
+    mut wearers: Query<&mut EquippedRebreather>,
+    mut rebreather_equip_events: EventReader<RebreatherEquipEvent>,
+) {
+    for rebreather_equip_event in rebreather_equip_events.read() {
+        if let Ok(mut equipped_rebreather) = wearers.get_mut(rebreather_equip_event.wearer) {
+            commands.entity(equipped_rebreather.0).remove::<Equipped>();
+            equipped_rebreather.0 = rebreather_equip_event.item;
+        } else {
+            commands
+                .entity(rebreather_equip_event.wearer)
+                .insert(EquippedRebreather(rebreather_equip_event.item));
+        }
+        commands
+            .entity(rebreather_equip_event.item)
+            .insert(Equipped(rebreather_equip_event.wearer));
+    }
+}
+
+#[test]
+fn did_equip_rebreather() {
+    let mut app = App::new();
+    app.add_event::<RebreatherEquipEvent>();
+    app.add_systems(Update, equip_rebreather);
+    let rebreather_id = app
+        .world_mut()
+        .spawn((
+            Equippable,
+            Rebreather::new(Entity::PLACEHOLDER, Entity::PLACEHOLDER),
+        ))
+        .id();
+    let wearer_id = app.world_mut().spawn(()).id();
+    app.world_mut()
+        .resource_mut::<Events<RebreatherEquipEvent>>()
+        .send(RebreatherEquipEvent {
+            item: rebreather_id,
+            wearer: wearer_id,
+        });
+    app.update();
+    let equipped = app.world().get::<Equipped>(rebreather_id).unwrap();
+    assert_eq!(equipped.0, wearer_id);
+    let equipped_rebreather = app.world().get::<EquippedRebreather>(wearer_id).unwrap();
+    assert_eq!(equipped_rebreather.0, rebreather_id);
+}
+
+pub fn unequip_rebreather(
+    mut commands: Commands,
+    equipped_rebreathers: Query<&EquippedRebreather>,
+    mut rebreather_unequip_events: EventReader<RebreatherUnequipEvent>,
+) {
+    for rebreather_unequip_event in rebreather_unequip_events.read() {
+        if let Ok(equipped_rebreather) = equipped_rebreathers.get(rebreather_unequip_event.wearer) {
+            if let Some(mut rebreather_entity) = commands.get_entity(equipped_rebreather.0) {
+                rebreather_entity.remove::<Equipped>();
+            }
+        }
+        if let Some(mut wearer_entity) = commands.get_entity(rebreather_unequip_event.wearer) {
+            wearer_entity.remove::<EquippedRebreather>();
+        }
+    }
+}
+
+#[test]
+fn did_unequip_rebreather() {
+    let mut app = App::new();
+    app.add_event::<RebreatherUnequipEvent>();
+    app.add_systems(Update, unequip_rebreather);
+    let rebreather_id = app
+        .world_mut()
+        .spawn((
+            Equippable,
+            Rebreather::new(Entity::PLACEHOLDER, Entity::PLACEHOLDER),
+        ))
+        .id();
+    let wearer_id = app
+        .world_mut()
+        .spawn(EquippedRebreather(rebreather_id))
+        .id();
+    app.world_mut()
+        .get_entity_mut(rebreather_id)
+        .unwrap()
+        .insert(Equipped(wearer_id));
+    app.world_mut()
+        .resource_mut::<Events<RebreatherUnequipEvent>>()
+        .send(RebreatherUnequipEvent { wearer: wearer_id });
+    app.update();
+    assert!(app.world().get::<EquippedRebreather>(wearer_id).is_none());
+    assert!(app.world().get::<Equipped>(rebreather_id).is_none());
+}
+
+pub fn equip_drysuit(
+    mut commands: Commands,
+    mut wearers: Query<&mut EquippedDrysuit>,
+    mut drysuit_equip_events: EventReader<DrysuitEquipEvent>,
+) {
+    for drysuit_equip_event in drysuit_equip_events.read() {
+        if let Ok(mut equipped_drysuit) = wearers.get_mut(drysuit_equip_event.wearer) {
+            commands.entity(equipped_drysuit.0).remove::<Equipped>();
+            equipped_drysuit.0 = drysuit_equip_event.item;
+        } else {
+            commands
+                .entity(drysuit_equip_event.wearer)
+                .insert(EquippedDrysuit(drysuit_equip_event.item));
+        }
+        commands
+            .entity(drysuit_equip_event.item)
+            .insert(Equipped(drysuit_equip_event.wearer));
+    }
+}
+
+#[test]
+fn did_equip_drysuit() {
+    let mut app = App::new();
+    app.add_event::<DrysuitEquipEvent>();
+    app.add_systems(Update, equip_drysuit);
+    let drysuit_id = app.world_mut().spawn((Equippable, Drysuit)).id();
+    let wearer_id = app.world_mut().spawn(()).id();
+    app.world_mut()
+        .resource_mut::<Events<DrysuitEquipEvent>>()
+        .send(DrysuitEquipEvent {
+            item: drysuit_id,
+            wearer: wearer_id,
+        });
+    app.update();
+    let equipped = app.world().get::<Equipped>(drysuit_id).unwrap();
+    assert_eq!(equipped.0, wearer_id);
+    let equipped_drysuit = app.world().get::<EquippedDrysuit>(wearer_id).unwrap();
+    assert_eq!(equipped_drysuit.0, drysuit_id);
+}
+
+pub fn unequip_drysuit(
+    mut commands: Commands,
+    equipped_drysuits: Query<&EquippedDrysuit>,
+    mut drysuit_unequip_events: EventReader<DrysuitUnequipEvent>,
+) {
+    for drysuit_unequip_event in drysuit_unequip_events.read() {
+        if let Ok(equipped_drysuit) = equipped_drysuits.get(drysuit_unequip_event.wearer) {
+            if let Some(mut drysuit_entity) = commands.get_entity(equipped_drysuit.0) {
+                drysuit_entity.remove::<Equipped>();
+            }
+        }
+        if let Some(mut wearer_entity) = commands.get_entity(drysuit_unequip_event.wearer) {
+            wearer_entity.remove::<EquippedDrysuit>();
+        }
+    }
+}
+
+#[test]
+fn did_unequip_drysuit() {
+    let mut app = App::new();
+    app.add_event::<DrysuitUnequipEvent>();
+    app.add_systems(Update, unequip_drysuit);
+    let drysuit_id = app.world_mut().spawn((Equippable, Drysuit)).id();
+    let wearer_id = app.world_mut().spawn(EquippedDrysuit(drysuit_id)).id();
+    app.world_mut()
+        .get_entity_mut(drysuit_id)
+        .unwrap()
+        .insert(Equipped(wearer_id));
+    app.world_mut()
+        .resource_mut::<Events<DrysuitUnequipEvent>>()
+        .send(DrysuitUnequipEvent { wearer: wearer_id });
+    app.update();
+    assert!(app.world().get::<EquippedDrysuit>(wearer_id).is_none());
+    assert!(app.world().get::<Equipped>(drysuit_id).is_none());
+}
+
+/// Under `GasSwitchPolicy::Block`, cylinders that are unsafe at the wearer's depth are skipped
+/// over, so they don't stand in the way of the ones behind them.
 pub fn equipped_cylinder_jump(
-    bags: Query<&Bag>,
+    policy: Res<GasSwitchPolicy>,
+    environment: Res<DiveSiteEnvironment>,
+    bags: Query<(&Bag, Option<&Depth>)>,
     cylinders: Query<&DivingCylinder>,
     equipped_items: Query<&Equipped>,
     mut jump_events: EventReader<EquippedCylinderJumpEvent>,
     mut equip_events: EventWriter<CylinderEquipEvent>,
 ) {
     for jump_event in jump_events.read() {
-        if let Ok(bag) = bags.get(jump_event.wearer) {
+        if let Ok((bag, depth)) = bags.get(jump_event.wearer) {
             let collected_cylinders: Vec<&Entity> = bag
                 .collectibles
                 .iter()
                 .filter(|e| cylinders.get(**e).is_ok())
                 .collect();
-            if let Some(equipped_index) = collected_cylinders
+            let reachable = |index: usize| match (&*policy, depth) {
+                (GasSwitchPolicy::Block, Some(depth)) => cylinders
+                    .get(*collected_cylinders[index])
+                    .is_ok_and(|cylinder| cylinder.suitability(&environment, depth.0).is_safe()),
+                _ => true,
+            };
+            let count = collected_cylinders.len() as i32;
+            let (start, step) = match collected_cylinders
                 .iter()
                 .position(|e| equipped_items.get(**e).is_ok())
             {
-                let new_equipped_index = (equipped_index as i32 + jump_event.i)
-                    .rem_euclid(collected_cylinders.len() as i32)
-                    as usize;
+                Some(equipped_index) => (
+                    equipped_index as i32 + jump_event.i,
+                    if jump_event.i < 0 { -1 } else { 1 },
+                ),
+                None => (0, 1),
+            };
+            if let Some(new_equipped_index) = (0..count)
+                .map(|skipped| (start + skipped * step).rem_euclid(count) as usize)
+                .find(|index| reachable(*index))
+            {
                 equip_events.send(CylinderEquipEvent {
                     item: *collected_cylinders[new_equipped_index],
                     wearer: jump_event.wearer,
                 });
-            } else {
-                if collected_cylinders.len() > 0 {
-                    equip_events.send(CylinderEquipEvent {
-                        item: *collected_cylinders[0],
-                        wearer: jump_event.wearer,
-                    });
-                }
             }
         }
     }
@@ -245,32 +666,28 @@ fn did_jump() {
     let mut app = App::new();
     app.add_event::<EquippedCylinderJumpEvent>();
     app.add_event::<CylinderEquipEvent>();
+    app.init_resource::<GasSwitchPolicy>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, equipped_cylinder_jump);
     let cylinder_1_id = app
         .world_mut()
         .spawn(DivingCylinder {
-            capacity: 0.,
             amount_remaining: 0.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            ..default()
         })
         .id();
     let cylinder_2_id = app
         .world_mut()
         .spawn(DivingCylinder {
-            capacity: 0.,
             amount_remaining: 0.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            ..default()
         })
         .id();
     let cylinder_3_id = app
         .world_mut()
         .spawn(DivingCylinder {
-            capacity: 0.,
             amount_remaining: 0.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            ..default()
         })
         .id();
     let wearer_id = app
@@ -398,3 +815,48 @@ pub fn spawn_equipment_menu(
             .push_children(&[container_id]);
     }
 }
+
+#[test]
+fn did_jump_past_blocked_cylinder() {
+    let mut app = App::new();
+    app.add_event::<EquippedCylinderJumpEvent>();
+    app.add_event::<CylinderEquipEvent>();
+    app.insert_resource(GasSwitchPolicy::Block);
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, equipped_cylinder_jump);
+    let air_id = app.world_mut().spawn(DivingCylinder::default()).id();
+    let deco_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            mix: GasMix::nitrox(0.5).unwrap(),
+            ..default()
+        })
+        .id();
+    let spare_id = app.world_mut().spawn(DivingCylinder::default()).id();
+    let wearer_id = app
+        .world_mut()
+        .spawn((
+            Bag {
+                collectibles: vec![air_id, deco_id, spare_id],
+                capacity: 3,
+            },
+            EquippedTank(air_id),
+            Depth(40.),
+        ))
+        .id();
+    app.world_mut()
+        .entity_mut(air_id)
+        .insert(Equipped(wearer_id));
+    // EAN50 can't be breathed at 40 metres, so the jump goes on to the spare
+    app.world_mut()
+        .resource_mut::<Events<EquippedCylinderJumpEvent>>()
+        .send(EquippedCylinderJumpEvent {
+            i: 1,
+            wearer: wearer_id,
+        });
+    app.update();
+    let equip_events = app.world().resource::<Events<CylinderEquipEvent>>();
+    let mut equip_reader = equip_events.get_reader();
+    let equip_cylinder = equip_reader.read(equip_events).next().unwrap();
+    assert_eq!(equip_cylinder.item, spare_id);
+}
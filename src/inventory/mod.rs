@@ -3,11 +3,18 @@ use bevy::prelude::*;
 use crate::inventory::bag::bag_plugin;
 use crate::inventory::equipment::equipment_plugin;
 use crate::inventory::inventory_menu::inventory_menu_plugin;
+use crate::inventory::wallet::wallet_plugin;
 
 pub mod bag;
 pub mod equipment;
 pub mod inventory_menu;
+pub mod wallet;
 
 pub fn inventory_plugin(app: &mut App) {
-    app.add_plugins((bag_plugin, equipment_plugin, inventory_menu_plugin));
+    app.add_plugins((
+        bag_plugin,
+        equipment_plugin,
+        inventory_menu_plugin,
+        wallet_plugin,
+    ));
 }
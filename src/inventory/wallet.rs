@@ -0,0 +1,10 @@
+use bevy::prelude::*;
+
+/// Money carried by a diver, to spend at the surface.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Wallet(pub f32);
+
+pub fn wallet_plugin(app: &mut App) {
+    app.register_type::<Wallet>();
+}
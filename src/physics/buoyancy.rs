@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+
+use crate::position::{update_position, Depth, Velocity};
+use crate::states::RunningStateSet;
+
+// change in vertical velocity each tick for each kilogram of lift
+const BUOYANCY_ACCELERATION: f32 = 0.005;
+
+/// Net lift in kilograms, positive floats and negative sinks.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Buoyancy(pub f32);
+
+pub fn buoyancy_plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        apply_buoyancy
+            .before(update_position)
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Buoyancy>();
+}
+
+/// Lift only acts underwater, so floating things settle at the surface.
+pub fn apply_buoyancy(mut movables: Query<(&Buoyancy, &Depth, &mut Velocity)>) {
+    for (buoyancy, depth, mut velocity) in &mut movables {
+        if buoyancy.0 < 0. || depth.0 > 0. {
+            velocity.0.y += BUOYANCY_ACCELERATION * buoyancy.0;
+        }
+    }
+}
+
+#[test]
+fn did_apply_buoyancy() {
+    let mut app = App::new();
+    app.add_systems(Update, apply_buoyancy);
+    let sinking_id = app
+        .world_mut()
+        .spawn((Buoyancy(-2.), Depth(10.), Velocity(Vec3::ZERO)))
+        .id();
+    let floating_id = app
+        .world_mut()
+        .spawn((Buoyancy(2.), Depth(10.), Velocity(Vec3::ZERO)))
+        .id();
+    let surfaced_id = app
+        .world_mut()
+        .spawn((Buoyancy(2.), Depth(0.), Velocity(Vec3::ZERO)))
+        .id();
+    app.update();
+    let sinking = app.world().get::<Velocity>(sinking_id).unwrap();
+    assert_eq!(sinking.0.y, -2. * BUOYANCY_ACCELERATION);
+    let floating = app.world().get::<Velocity>(floating_id).unwrap();
+    assert_eq!(floating.0.y, 2. * BUOYANCY_ACCELERATION);
+    let surfaced = app.world().get::<Velocity>(surfaced_id).unwrap();
+    assert_eq!(surfaced.0.y, 0.);
+}
@@ -2,12 +2,15 @@ use crate::health::*;
 use crate::inventory::bag::*;
 use crate::position::*;
 use crate::projectile::*;
+use crate::respiration::stress::{Entangled, Stress};
 use crate::states::RunningStateSet;
 use bevy::prelude::*;
 use bevy::sprite::MaterialMesh2dBundle;
 
 const OBSTACLE_WIDTH: f32 = 40.;
 const OBSTACLE_HEIGHT: f32 = 10.;
+const NETTING_WIDTH: f32 = 30.;
+const NETTING_HEIGHT: f32 = 20.;
 
 #[derive(Component, Reflect)]
 #[reflect(Component)]
@@ -17,19 +20,58 @@ pub struct RectangularHitbox(pub Rectangle);
 #[reflect(Component)]
 pub struct Obstacle;
 
+/// Lost fishing net that snags divers swimming into it until they cut themselves free.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Netting;
+
+#[derive(Event)]
+pub struct CutFree {
+    pub entity: Entity,
+}
+
 pub fn collision_plugin(app: &mut App) {
-    app.add_systems(Startup, spawn_obstacles);
+    app.add_event::<CutFree>();
+    app.add_systems(Startup, (spawn_obstacles, spawn_netting));
     app.add_systems(
         FixedUpdate,
         (
             projectile_collision.after(update_position),
             obstacle_collision.after(update_position),
             gatherer_item_collision.after(update_position),
+            entangle.after(update_position),
+            cut_free.after(entangle),
         )
             .in_set(RunningStateSet),
     );
     app.register_type::<RectangularHitbox>();
     app.register_type::<Obstacle>();
+    app.register_type::<Netting>();
+}
+
+pub fn spawn_netting(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mesh = Mesh::from(Rectangle::new(NETTING_WIDTH, NETTING_HEIGHT));
+    let material = ColorMaterial::from_color(Srgba::rgba_u8(200, 200, 180, 120));
+
+    let mesh_handle = meshes.add(mesh);
+    let material_handle = materials.add(material);
+
+    commands.spawn((
+        Netting,
+        RectangularHitbox(Rectangle::new(NETTING_WIDTH, NETTING_HEIGHT)),
+        MaterialMesh2dBundle {
+            mesh: mesh_handle.into(),
+            material: material_handle,
+            transform: Transform::from_translation(Vec3::new(-60., -120., 0.)),
+            ..default()
+        },
+        Name::new("Netting"),
+        crate::PIXEL_PERFECT_LAYERS,
+    ));
 }
 
 pub fn spawn_obstacles(
@@ -256,3 +298,113 @@ pub fn gatherer_item_collision(
         }
     }
 }
+
+/// Swimmers that can get caught up in netting.
+type Snaggable = (With<Stress>, Without<Entangled>);
+
+pub fn entangle(
+    mut commands: Commands,
+    swimmers: Query<(Entity, &Transform, &RectangularHitbox), Snaggable>,
+    nettings: Query<(&Transform, &RectangularHitbox), With<Netting>>,
+) {
+    for (swimmer_entity, swimmer_transform, swimmer_hitbox) in &swimmers {
+        for (netting_transform, netting_hitbox) in &nettings {
+            if get_collision_data(
+                &swimmer_transform.translation,
+                swimmer_hitbox,
+                &netting_transform.translation,
+                netting_hitbox,
+            )
+            .is_some()
+            {
+                println!("entangled in netting");
+                commands.entity(swimmer_entity).insert(Entangled);
+            }
+        }
+    }
+}
+
+#[test]
+fn did_entangle() {
+    let mut app = App::new();
+    app.add_systems(Update, entangle);
+    app.world_mut().spawn((
+        Netting,
+        Transform::default(),
+        RectangularHitbox(Rectangle::new(10., 10.)),
+    ));
+    let caught_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::from_translation(Vec3::new(5., 0., 0.)),
+            RectangularHitbox(Rectangle::new(4., 4.)),
+        ))
+        .id();
+    let clear_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::from_translation(Vec3::new(50., 0., 0.)),
+            RectangularHitbox(Rectangle::new(4., 4.)),
+        ))
+        .id();
+    app.update();
+    assert!(app.world().get::<Entangled>(caught_id).is_some());
+    assert!(app.world().get::<Entangled>(clear_id).is_none());
+}
+
+/// Cuts through any netting around the swimmer, freeing them from it for good.
+pub fn cut_free(
+    mut commands: Commands,
+    swimmers: Query<(&Transform, &RectangularHitbox), With<Entangled>>,
+    nettings: Query<(Entity, &Transform, &RectangularHitbox), With<Netting>>,
+    mut cut_free_events: EventReader<CutFree>,
+) {
+    for cut_free_event in cut_free_events.read() {
+        if let Ok((swimmer_transform, swimmer_hitbox)) = swimmers.get(cut_free_event.entity) {
+            for (netting_entity, netting_transform, netting_hitbox) in &nettings {
+                if get_collision_data(
+                    &swimmer_transform.translation,
+                    swimmer_hitbox,
+                    &netting_transform.translation,
+                    netting_hitbox,
+                )
+                .is_some()
+                {
+                    commands.entity(netting_entity).despawn();
+                }
+            }
+            commands.entity(cut_free_event.entity).remove::<Entangled>();
+        }
+    }
+}
+
+#[test]
+fn did_cut_free() {
+    let mut app = App::new();
+    app.add_event::<CutFree>();
+    app.add_systems(Update, cut_free);
+    let netting_id = app
+        .world_mut()
+        .spawn((
+            Netting,
+            Transform::default(),
+            RectangularHitbox(Rectangle::new(10., 10.)),
+        ))
+        .id();
+    let swimmer_id = app
+        .world_mut()
+        .spawn((
+            Entangled,
+            Transform::default(),
+            RectangularHitbox(Rectangle::new(4., 4.)),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Events<CutFree>>()
+        .send(CutFree { entity: swimmer_id });
+    app.update();
+    assert!(app.world().get::<Entangled>(swimmer_id).is_none());
+    assert!(app.world().get_entity(netting_id).is_none());
+}
@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+// pressure, in bar, of the atmosphere at sea level, which surface litres of gas are measured at
+pub const STANDARD_PRESSURE: f32 = 1.;
+// kilograms per litre
+pub const SEAWATER_DENSITY: f32 = 1.025;
+pub const FRESHWATER_DENSITY: f32 = 1.;
+// bar added by each metre of seawater
+const SEAWATER_PRESSURE_PER_METRE: f32 = 1. / 10.;
+// metres of altitude over which the atmospheric pressure falls by a factor of e
+const ATMOSPHERE_SCALE_HEIGHT: f32 = 8400.;
+
+/// Physical conditions at the level's dive site, which every conversion between depth and
+/// pressure goes through.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct DiveSiteEnvironment {
+    /// Atmospheric pressure at the water's surface, in bar.
+    pub surface_pressure: f32,
+    /// Density of the water, in kilograms per litre.
+    pub water_density: f32,
+}
+
+impl Default for DiveSiteEnvironment {
+    fn default() -> Self {
+        Self::SEA
+    }
+}
+
+impl DiveSiteEnvironment {
+    pub const SEA: Self = Self {
+        surface_pressure: STANDARD_PRESSURE,
+        water_density: SEAWATER_DENSITY,
+    };
+
+    /// A freshwater lake or quarry `altitude` metres above sea level.
+    pub fn lake(altitude: f32) -> Self {
+        Self {
+            surface_pressure: STANDARD_PRESSURE * (-altitude / ATMOSPHERE_SCALE_HEIGHT).exp(),
+            water_density: FRESHWATER_DENSITY,
+        }
+    }
+
+    /// Bar of pressure added by each metre of water.
+    pub fn pressure_per_metre(&self) -> f32 {
+        SEAWATER_PRESSURE_PER_METRE * self.water_density / SEAWATER_DENSITY
+    }
+
+    pub fn ambient_pressure(&self, depth: f32) -> f32 {
+        self.surface_pressure + self.pressure_per_metre() * depth
+    }
+
+    pub fn depth_at_pressure(&self, pressure: f32) -> f32 {
+        (pressure - self.surface_pressure) / self.pressure_per_metre()
+    }
+}
+
+pub fn environment_plugin(app: &mut App) {
+    app.init_resource::<DiveSiteEnvironment>();
+    app.register_type::<DiveSiteEnvironment>();
+}
+
+#[test]
+fn site_pressures() {
+    let sea = DiveSiteEnvironment::SEA;
+    assert_eq!(sea.ambient_pressure(10.), 2.);
+    assert_eq!(sea.depth_at_pressure(4.), 30.);
+    // fresh water is lighter, so it takes more of it to add a bar
+    let quarry = DiveSiteEnvironment::lake(0.);
+    assert!(quarry.depth_at_pressure(2.) > 10.);
+    // and there's less atmosphere on top of a mountain lake
+    let mountain_lake = DiveSiteEnvironment::lake(3000.);
+    assert!((mountain_lake.surface_pressure - 0.7).abs() < 0.01);
+    assert!(mountain_lake.ambient_pressure(10.) < quarry.ambient_pressure(10.));
+}
@@ -1,13 +1,23 @@
 use bevy::prelude::*;
 
+use buoyancy::*;
 use collision::*;
 use drag::*;
+use environment::*;
 use position::*;
 
+pub mod buoyancy;
 pub mod collision;
 pub mod drag;
+pub mod environment;
 pub mod position;
 
 pub fn physics_plugin(app: &mut App) {
-    app.add_plugins((drag_plugin, collision_plugin, position_plugin));
+    app.add_plugins((
+        buoyancy_plugin,
+        drag_plugin,
+        environment_plugin,
+        collision_plugin,
+        position_plugin,
+    ));
 }
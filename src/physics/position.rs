@@ -4,6 +4,52 @@ use bevy::prelude::*;
 pub const SEA_LEVEL: f32 = 0.;
 const METERS_TRANSLATION_RATIO: f32 = 10.;
 
+/// A layer where the water temperature drops by `drop` degrees over `thickness` metres, starting
+/// at `depth`.
+pub struct Thermocline {
+    pub depth: f32,
+    pub thickness: f32,
+    pub drop: f32,
+}
+
+/// Water temperatures east of `west_edge`, until the next region.
+pub struct ThermalRegion {
+    pub west_edge: f32,
+    pub surface_temperature: f32,
+    pub thermoclines: &'static [Thermocline],
+}
+
+// sorted from west to east
+const THERMAL_REGIONS: [ThermalRegion; 2] = [
+    // the sheltered bay around the surface hub
+    ThermalRegion {
+        west_edge: f32::NEG_INFINITY,
+        surface_temperature: 24.,
+        thermoclines: &[Thermocline {
+            depth: 10.,
+            thickness: 3.,
+            drop: 4.,
+        }],
+    },
+    // open water, with a second, deeper thermocline
+    ThermalRegion {
+        west_edge: 200.,
+        surface_temperature: 20.,
+        thermoclines: &[
+            Thermocline {
+                depth: 15.,
+                thickness: 5.,
+                drop: 6.,
+            },
+            Thermocline {
+                depth: 40.,
+                thickness: 10.,
+                drop: 4.,
+            },
+        ],
+    },
+];
+
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct Velocity(pub Vec3);
@@ -12,13 +58,39 @@ pub struct Velocity(pub Vec3);
 #[reflect(Component)]
 pub struct Depth(pub f32);
 
+/// Temperature of the surrounding water, in degrees Celsius.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct WaterTemperature(pub f32);
+
+/// Temperature of the water `depth` metres down at horizontal position `x`.
+pub fn water_temperature(x: f32, depth: f32) -> f32 {
+    let region = THERMAL_REGIONS
+        .iter()
+        .rev()
+        .find(|region| region.west_edge <= x)
+        .unwrap_or(&THERMAL_REGIONS[0]);
+    region.surface_temperature
+        - region
+            .thermoclines
+            .iter()
+            .map(|layer| layer.drop * ((depth - layer.depth) / layer.thickness).clamp(0., 1.))
+            .sum::<f32>()
+}
+
 pub fn position_plugin(app: &mut App) {
     app.add_systems(
         FixedUpdate,
-        (update_position, update_depth.after(update_position)).in_set(RunningStateSet),
+        (
+            update_position,
+            update_depth.after(update_position),
+            update_water_temperature.after(update_depth),
+        )
+            .in_set(RunningStateSet),
     );
     app.register_type::<Velocity>();
     app.register_type::<Depth>();
+    app.register_type::<WaterTemperature>();
 }
 
 pub fn update_position(mut movables: Query<(&mut Transform, &Velocity)>) {
@@ -93,3 +165,49 @@ fn depth_above_sea_level() {
 
     assert!(new_depth == 0.);
 }
+
+pub fn update_water_temperature(
+    mut submerged_objects: Query<(&mut WaterTemperature, &Depth, &Transform)>,
+) {
+    for (mut temperature, depth, transform) in &mut submerged_objects {
+        temperature.0 = water_temperature(transform.translation.x, depth.0);
+    }
+}
+
+#[test]
+fn did_update_water_temperature() {
+    let mut app = App::new();
+    app.add_systems(Update, update_water_temperature);
+
+    let shallow_id = app
+        .world_mut()
+        .spawn((
+            Transform::from_translation(Vec3::ZERO),
+            Depth(5.),
+            WaterTemperature::default(),
+        ))
+        .id();
+    let deep_id = app
+        .world_mut()
+        .spawn((
+            Transform::from_translation(Vec3::ZERO),
+            Depth(30.),
+            WaterTemperature::default(),
+        ))
+        .id();
+
+    app.update();
+    let shallow = app.world().get::<WaterTemperature>(shallow_id).unwrap().0;
+    let deep = app.world().get::<WaterTemperature>(deep_id).unwrap().0;
+
+    assert!(shallow == 24.);
+    assert!(deep == 20.);
+}
+
+#[test]
+fn thermocline_layers() {
+    // halfway through the first layer
+    assert!(water_temperature(300., 17.5) == 17.);
+    // below both layers
+    assert!(water_temperature(300., 60.) == 10.);
+}
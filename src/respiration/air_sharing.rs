@@ -0,0 +1,216 @@
+use crate::position::{update_depth, update_position};
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::inhalation::*;
+use crate::respiration::regulator::Regulator;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+
+// how close, in pixels, a donor has to be to hand over their octopus
+pub const SHARE_AIR_RANGE: f32 = 15.;
+// length of the octopus hose, in pixels
+const HOSE_LENGTH: f32 = 12.;
+const BUDDY_WIDTH: f32 = 5.;
+const BUDDY_HEIGHT: f32 = 13.;
+
+/// A diver waiting on station with a full tank, for the player to share air with.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Buddy;
+
+/// Breathing from the `donor`'s `EquippedTank` through their octopus second stage.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct SharingAir {
+    pub donor: Entity,
+}
+
+#[derive(Event)]
+pub struct ShareAir {
+    pub receiver: Entity,
+    pub donor: Entity,
+}
+
+#[derive(Event)]
+pub struct StopSharingAir {
+    pub receiver: Entity,
+}
+
+pub fn air_sharing_plugin(app: &mut App) {
+    app.add_event::<ShareAir>();
+    app.add_event::<StopSharingAir>();
+    app.add_systems(Startup, spawn_buddy);
+    app.add_systems(
+        FixedUpdate,
+        (
+            share_air.before(inhalation),
+            stop_sharing_air.before(inhalation),
+            tether_air_sharers
+                .after(update_position)
+                .before(update_depth),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Buddy>();
+    app.register_type::<SharingAir>();
+}
+
+pub fn spawn_buddy(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mesh = Mesh::from(Rectangle::new(BUDDY_WIDTH, BUDDY_HEIGHT));
+    let material = ColorMaterial::from_color(Srgba::rgb(0.9, 0.5, 0.1));
+
+    let mesh_handle = meshes.add(mesh);
+    let material_handle = materials.add(material);
+
+    let cylinder_id = commands
+        .spawn((
+            DivingCylinder::new(
+                crate::diver::DIVER_TANK_WATER_VOLUME,
+                crate::diver::DIVER_TANK_WORKING_PRESSURE,
+                crate::diver::DIVER_TANK_WORKING_PRESSURE,
+                GasMix::AIR,
+            ),
+            Regulator::default(),
+            Name::new("Buddy's tank"),
+        ))
+        .id();
+    commands.spawn((
+        Buddy,
+        EquippedTank(cylinder_id),
+        MaterialMesh2dBundle {
+            mesh: mesh_handle.into(),
+            material: material_handle,
+            transform: Transform::from_translation(Vec3::new(20., -40., 0.)),
+            ..default()
+        },
+        Name::new("Buddy"),
+        crate::PIXEL_PERFECT_LAYERS,
+    ));
+}
+
+/// The tank whatever `entity` breathes from, which is its buddy's while sharing air.
+pub fn supplying_tank(
+    entity: Entity,
+    equipped_tanks: &Query<&EquippedTank>,
+    sharers: &Query<&SharingAir>,
+) -> Option<Entity> {
+    let wearer = sharers.get(entity).map_or(entity, |sharing| sharing.donor);
+    equipped_tanks.get(wearer).ok().map(|tank| tank.0)
+}
+
+/// Only a donor with a tank, close enough to reach, can share.
+pub fn share_air(
+    mut commands: Commands,
+    positions: Query<&Transform>,
+    donors: Query<(), With<EquippedTank>>,
+    mut requests: EventReader<ShareAir>,
+) {
+    for request in requests.read() {
+        if request.receiver == request.donor || !donors.contains(request.donor) {
+            continue;
+        }
+        if let (Ok(receiver), Ok(donor)) = (
+            positions.get(request.receiver),
+            positions.get(request.donor),
+        ) {
+            if receiver.translation.distance(donor.translation) <= SHARE_AIR_RANGE {
+                println!("sharing air");
+                commands.entity(request.receiver).insert(SharingAir {
+                    donor: request.donor,
+                });
+            }
+        }
+    }
+}
+
+#[test]
+fn did_share_air() {
+    let mut app = App::new();
+    app.add_event::<ShareAir>();
+    app.add_systems(Update, share_air);
+    let cylinder_id = app.world_mut().spawn(DivingCylinder::default()).id();
+    let donor_id = app
+        .world_mut()
+        .spawn((Transform::default(), EquippedTank(cylinder_id)))
+        .id();
+    let receiver_id = app
+        .world_mut()
+        .spawn(Transform::from_translation(Vec3::new(
+            SHARE_AIR_RANGE / 2.,
+            0.,
+            0.,
+        )))
+        .id();
+    let distant_id = app
+        .world_mut()
+        .spawn(Transform::from_translation(Vec3::new(
+            SHARE_AIR_RANGE * 2.,
+            0.,
+            0.,
+        )))
+        .id();
+    let mut requests = app.world_mut().resource_mut::<Events<ShareAir>>();
+    requests.send(ShareAir {
+        receiver: receiver_id,
+        donor: donor_id,
+    });
+    requests.send(ShareAir {
+        receiver: distant_id,
+        donor: donor_id,
+    });
+    app.update();
+    let sharing = app.world().get::<SharingAir>(receiver_id).unwrap();
+    assert_eq!(sharing.donor, donor_id);
+    assert!(app.world().get::<SharingAir>(distant_id).is_none());
+}
+
+pub fn stop_sharing_air(mut commands: Commands, mut requests: EventReader<StopSharingAir>) {
+    for request in requests.read() {
+        if let Some(mut receiver) = commands.get_entity(request.receiver) {
+            receiver.remove::<SharingAir>();
+        }
+    }
+}
+
+/// The hose keeps the receiver within reach of the donor, dragging them along if need be.
+pub fn tether_air_sharers(
+    mut commands: Commands,
+    mut receivers: Query<(Entity, &mut Transform, &SharingAir)>,
+    donors: Query<&Transform, Without<SharingAir>>,
+) {
+    for (entity, mut transform, sharing) in &mut receivers {
+        if let Ok(donor) = donors.get(sharing.donor) {
+            let offset = transform.translation - donor.translation;
+            if offset.length() > HOSE_LENGTH {
+                transform.translation = donor.translation + offset.normalize() * HOSE_LENGTH;
+            }
+        } else {
+            commands.entity(entity).remove::<SharingAir>();
+        }
+    }
+}
+
+#[test]
+fn did_tether_air_sharers() {
+    let mut app = App::new();
+    app.add_systems(Update, tether_air_sharers);
+    let donor_id = app.world_mut().spawn(Transform::default()).id();
+    let receiver_id = app
+        .world_mut()
+        .spawn((
+            Transform::from_translation(Vec3::new(0., -HOSE_LENGTH * 3., 0.)),
+            SharingAir { donor: donor_id },
+        ))
+        .id();
+    app.update();
+    let receiver = app.world().get::<Transform>(receiver_id).unwrap();
+    assert_eq!(receiver.translation, Vec3::new(0., -HOSE_LENGTH, 0.));
+    // there's nothing to breathe from once the donor is gone
+    app.world_mut().despawn(donor_id);
+    app.update();
+    assert!(app.world().get::<SharingAir>(receiver_id).is_none());
+}
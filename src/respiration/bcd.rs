@@ -0,0 +1,299 @@
+use crate::buoyancy::*;
+use crate::environment::*;
+use crate::position::Depth;
+use crate::respiration::circulation::carbon_dioxide::relative_density;
+#[cfg(test)]
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::inhalation::*;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+
+const DEFAULT_BCD_MAX_VOLUME: f32 = 15.;
+// litres at depth added or released by each press of the inflator or dump valve
+const BCD_PUFF: f32 = 1.;
+// kilograms per surface litre of air
+const AIR_DENSITY: f32 = 0.001225;
+// lift of a cylinder with no gas in it, in kilograms
+const EMPTY_CYLINDER_BUOYANCY: f32 = 1.;
+// insulation given by each millimetre of uncompressed neoprene
+const WETSUIT_INSULATION_PER_MM: f32 = 0.4;
+
+/// Fixed sources of lift, in kilograms. `weights` is the lead on the weight belt.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Ballast {
+    pub body: f32,
+    pub gear: f32,
+    pub weights: f32,
+}
+
+/// Neoprene full of gas cells that are crushed with depth. `thickness` is in millimetres.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Wetsuit {
+    pub surface_buoyancy: f32,
+    pub thickness: f32,
+}
+
+impl Wetsuit {
+    pub fn buoyancy(&self, environment: &DiveSiteEnvironment, depth: f32) -> f32 {
+        self.surface_buoyancy * STANDARD_PRESSURE / environment.ambient_pressure(depth)
+    }
+
+    /// The same crushed gas cells keep less heat in the deeper the suit goes.
+    pub fn insulation(&self, environment: &DiveSiteEnvironment, depth: f32) -> f32 {
+        WETSUIT_INSULATION_PER_MM * self.thickness * STANDARD_PRESSURE
+            / environment.ambient_pressure(depth)
+    }
+}
+
+/// A buoyancy control device, holding `gas` surface litres in a bladder of up to `max_volume`
+/// litres.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Bcd {
+    pub gas: f32,
+    pub max_volume: f32,
+}
+
+impl Default for Bcd {
+    fn default() -> Self {
+        Self {
+            gas: 0.,
+            max_volume: DEFAULT_BCD_MAX_VOLUME,
+        }
+    }
+}
+
+impl Bcd {
+    /// Volume of the bladder at `depth`, in litres, which is also its lift in kilograms.
+    pub fn volume(&self, environment: &DiveSiteEnvironment, depth: f32) -> f32 {
+        self.gas * STANDARD_PRESSURE / environment.ambient_pressure(depth)
+    }
+}
+
+/// A cylinder gets lighter, and so more buoyant, as its gas is used.
+pub fn cylinder_buoyancy(cylinder: &DivingCylinder) -> f32 {
+    let density = AIR_DENSITY * relative_density(&cylinder.mix, STANDARD_PRESSURE);
+    EMPTY_CYLINDER_BUOYANCY - cylinder.amount_remaining * density
+}
+
+#[derive(Event)]
+pub struct InflateBcd {
+    pub entity: Entity,
+}
+
+#[derive(Event)]
+pub struct DumpBcd {
+    pub entity: Entity,
+}
+
+pub fn bcd_plugin(app: &mut App) {
+    app.add_event::<InflateBcd>();
+    app.add_event::<DumpBcd>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            inflate_bcd,
+            dump_bcd,
+            vent_bcd.after(inflate_bcd),
+            update_buoyancy
+                .after(vent_bcd)
+                .after(dump_bcd)
+                .before(apply_buoyancy),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Ballast>();
+    app.register_type::<Wetsuit>();
+    app.register_type::<Bcd>();
+}
+
+/// The inflator is fed from the equipped cylinder, so there is nothing to inflate with without
+/// one.
+pub fn inflate_bcd(
+    environment: Res<DiveSiteEnvironment>,
+    mut wearers: Query<(&mut Bcd, &EquippedTank, &Depth)>,
+    mut cylinders: Query<&mut DivingCylinder>,
+    mut inflations: EventReader<InflateBcd>,
+) {
+    for inflation in inflations.read() {
+        if let Ok((mut bcd, equipped_tank, depth)) = wearers.get_mut(inflation.entity) {
+            if let Ok(mut cylinder) = cylinders.get_mut(equipped_tank.0) {
+                let amount = (BCD_PUFF * environment.ambient_pressure(depth.0))
+                    .min(cylinder.amount_remaining);
+                cylinder.amount_remaining -= amount;
+                bcd.gas += amount;
+            }
+        }
+    }
+}
+
+#[test]
+fn did_inflate_bcd() {
+    let mut app = App::new();
+    app.add_event::<InflateBcd>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, inflate_bcd);
+    let cylinder_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            amount_remaining: 100.,
+            ..default()
+        })
+        .id();
+    let wearer_id = app
+        .world_mut()
+        .spawn((Bcd::default(), EquippedTank(cylinder_id), Depth(10.)))
+        .id();
+    app.world_mut()
+        .resource_mut::<Events<InflateBcd>>()
+        .send(InflateBcd { entity: wearer_id });
+    app.update();
+    // a litre at 10 metres takes two surface litres
+    let bcd = app.world().get::<Bcd>(wearer_id).unwrap();
+    assert_eq!(bcd.gas, 2.);
+    assert_eq!(bcd.volume(&DiveSiteEnvironment::SEA, 10.), 1.);
+    let cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert_eq!(cylinder.amount_remaining, 98.);
+}
+
+pub fn dump_bcd(
+    environment: Res<DiveSiteEnvironment>,
+    mut wearers: Query<(&mut Bcd, &Depth)>,
+    mut dumps: EventReader<DumpBcd>,
+) {
+    for dump in dumps.read() {
+        if let Ok((mut bcd, depth)) = wearers.get_mut(dump.entity) {
+            bcd.gas = (bcd.gas - BCD_PUFF * environment.ambient_pressure(depth.0)).max(0.);
+        }
+    }
+}
+
+#[test]
+fn did_dump_bcd() {
+    let mut app = App::new();
+    app.add_event::<DumpBcd>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, dump_bcd);
+    let wearer_id = app
+        .world_mut()
+        .spawn((
+            Bcd {
+                gas: 3.,
+                ..default()
+            },
+            Depth(10.),
+        ))
+        .id();
+    let mut dumps = app.world_mut().resource_mut::<Events<DumpBcd>>();
+    dumps.send(DumpBcd { entity: wearer_id });
+    dumps.send(DumpBcd { entity: wearer_id });
+    app.update();
+    let bcd = app.world().get::<Bcd>(wearer_id).unwrap();
+    assert_eq!(bcd.gas, 0.);
+}
+
+/// The overpressure valve lets gas out once the expanding bladder is full.
+pub fn vent_bcd(environment: Res<DiveSiteEnvironment>, mut wearers: Query<(&mut Bcd, &Depth)>) {
+    for (mut bcd, depth) in &mut wearers {
+        let max_gas = bcd.max_volume * environment.ambient_pressure(depth.0) / STANDARD_PRESSURE;
+        bcd.gas = bcd.gas.min(max_gas);
+    }
+}
+
+#[test]
+fn did_vent_bcd() {
+    let mut app = App::new();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, vent_bcd);
+    let wearer_id = app
+        .world_mut()
+        .spawn((
+            Bcd {
+                gas: 30.,
+                max_volume: 15.,
+            },
+            Depth(0.),
+        ))
+        .id();
+    app.update();
+    let bcd = app.world().get::<Bcd>(wearer_id).unwrap();
+    assert_eq!(bcd.gas, 15.);
+}
+
+pub fn update_buoyancy(
+    environment: Res<DiveSiteEnvironment>,
+    mut divers: Query<(Entity, &mut Buoyancy, &Ballast, &Wetsuit, &Bcd, &Depth)>,
+    equipped_tanks: Query<&EquippedTank>,
+    cylinders: Query<&DivingCylinder>,
+) {
+    for (entity, mut buoyancy, ballast, wetsuit, bcd, depth) in &mut divers {
+        let cylinder = equipped_tanks
+            .get(entity)
+            .ok()
+            .and_then(|tank| cylinders.get(tank.0).ok());
+        buoyancy.0 = ballast.body + ballast.gear - ballast.weights
+            + wetsuit.buoyancy(&environment, depth.0)
+            + bcd.volume(&environment, depth.0)
+            + cylinder.map_or(0., cylinder_buoyancy);
+    }
+}
+
+#[test]
+fn did_update_buoyancy() {
+    let mut app = App::new();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, update_buoyancy);
+    let cylinder_id = app.world_mut().spawn(DivingCylinder::default()).id();
+    let surface_id = app
+        .world_mut()
+        .spawn((
+            Buoyancy::default(),
+            Ballast {
+                body: 2.,
+                gear: -2.,
+                weights: 4.,
+            },
+            Wetsuit {
+                surface_buoyancy: 4.,
+                ..default()
+            },
+            Bcd::default(),
+            EquippedTank(cylinder_id),
+            Depth(0.),
+        ))
+        .id();
+    let deep_id = app
+        .world_mut()
+        .spawn((
+            Buoyancy::default(),
+            Ballast {
+                body: 2.,
+                gear: -2.,
+                weights: 4.,
+            },
+            Wetsuit {
+                surface_buoyancy: 4.,
+                ..default()
+            },
+            Bcd::default(),
+            EquippedTank(cylinder_id),
+            Depth(30.),
+        ))
+        .id();
+    app.update();
+    let surface = app.world().get::<Buoyancy>(surface_id).unwrap();
+    assert_eq!(surface.0, EMPTY_CYLINDER_BUOYANCY);
+    // the wetsuit is crushed to a quarter at 30 metres
+    let deep = app.world().get::<Buoyancy>(deep_id).unwrap();
+    assert_eq!(deep.0, EMPTY_CYLINDER_BUOYANCY - 3.);
+}
+
+#[test]
+fn cylinder_lightens() {
+    let full = DivingCylinder::new(10., 200., 200., GasMix::AIR);
+    let empty = DivingCylinder::default();
+    assert!(cylinder_buoyancy(&full) < 0.);
+    assert_eq!(cylinder_buoyancy(&empty), EMPTY_CYLINDER_BUOYANCY);
+}
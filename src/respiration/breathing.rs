@@ -0,0 +1,212 @@
+use crate::diver::Diver;
+use crate::respiration::circulation::usage::*;
+use crate::respiration::freediving::surface_inhalation;
+use crate::respiration::inhalation::*;
+use crate::respiration::lungs::*;
+use crate::respiration::rebreather::rebreather_inhalation;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+
+// breaths per minute at rest
+const DEFAULT_BREATHING_RATE: f32 = 12.;
+
+/// Breathes on a rhythm instead of on key presses, for AI breathers and players who turn on the
+/// `BreathingAssist`. The `rate` is sped up by the `BreathingDemand`.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Breathing {
+    /// Breaths per minute at rest.
+    pub rate: f32,
+    /// Holds the current breath in, pausing the rhythm.
+    pub holding: bool,
+    /// Breaths left out after each one taken, holding it in between to save gas.
+    pub skip: u32,
+    /// Seconds into the current breath.
+    pub elapsed: f32,
+    pub exhaled: bool,
+}
+
+impl Default for Breathing {
+    fn default() -> Self {
+        Self {
+            rate: DEFAULT_BREATHING_RATE,
+            holding: false,
+            skip: 0,
+            elapsed: 0.,
+            exhaled: true,
+        }
+    }
+}
+
+impl Breathing {
+    /// Seconds a single breath takes at the given `BreathingDemand`.
+    pub fn interval(&self, demand: f32) -> f32 {
+        60. / (self.rate * demand)
+    }
+
+    /// Seconds between one breath and the next, skipped ones included.
+    pub fn cycle(&self, demand: f32) -> f32 {
+        self.interval(demand) * (self.skip + 1) as f32
+    }
+}
+
+/// Whether the player breathes by pressing a key for each breath or leaves it to a `Breathing`
+/// rhythm, as an accessibility setting.
+#[derive(Resource, Default, Reflect, PartialEq)]
+#[reflect(Resource)]
+pub enum BreathingAssist {
+    #[default]
+    Manual,
+    Automatic,
+}
+
+pub fn breathing_plugin(app: &mut App) {
+    app.init_resource::<BreathingAssist>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            apply_breathing_assist.before(breathe),
+            breathe
+                .after(usage)
+                .before(inhalation)
+                .before(rebreather_inhalation)
+                .before(surface_inhalation)
+                .before(empty_lungs),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Breathing>();
+    app.register_type::<BreathingAssist>();
+}
+
+/// Gives the diver a `Breathing` rhythm while the assist is on, and takes it away when it's off.
+pub fn apply_breathing_assist(
+    mut commands: Commands,
+    assist: Res<BreathingAssist>,
+    divers: Query<(Entity, Has<Breathing>), With<Diver>>,
+) {
+    for (entity, breathing) in &divers {
+        match (assist.as_ref(), breathing) {
+            (BreathingAssist::Automatic, false) => {
+                commands.entity(entity).insert(Breathing::default());
+            }
+            (BreathingAssist::Manual, true) => {
+                commands.entity(entity).remove::<Breathing>();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn did_apply_breathing_assist() {
+    let mut app = App::new();
+    app.insert_resource(BreathingAssist::Automatic);
+    app.add_systems(Update, apply_breathing_assist);
+    let diver_id = app.world_mut().spawn(Diver).id();
+    app.update();
+    assert!(app.world().get::<Breathing>(diver_id).is_some());
+    app.insert_resource(BreathingAssist::Manual);
+    app.update();
+    assert!(app.world().get::<Breathing>(diver_id).is_none());
+}
+
+/// Takes a breath at the start of each cycle and breathes it out half a breath before the next,
+/// so a skipped breath is held in for the whole of the pause.
+pub fn breathe(
+    time: Res<Time>,
+    mut breathers: Query<(Entity, &mut Breathing, Option<&BreathingDemand>)>,
+    mut breaths: EventWriter<BreathTaken>,
+    mut exhales: EventWriter<BreathExhaled>,
+) {
+    for (entity, mut breathing, breathing_demand) in &mut breathers {
+        if breathing.holding {
+            continue;
+        }
+        let demand = breathing_demand.map_or(1., |breathing_demand| breathing_demand.0);
+        let cycle = breathing.cycle(demand);
+        let exhale_at = cycle - breathing.interval(demand) / 2.;
+        breathing.elapsed += time.delta_seconds();
+        if breathing.elapsed >= cycle {
+            breathing.elapsed = 0.;
+            breathing.exhaled = false;
+            breaths.send(BreathTaken { entity });
+        } else if !breathing.exhaled && breathing.elapsed >= exhale_at {
+            breathing.exhaled = true;
+            exhales.send(BreathExhaled { entity });
+        }
+    }
+}
+
+#[test]
+fn did_breathe() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<BreathTaken>();
+    app.add_event::<BreathExhaled>();
+    app.add_systems(Update, breathe);
+    let breather_id = app.world_mut().spawn(Breathing::default()).id();
+    let mut advance = |seconds: f32| {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(std::time::Duration::from_secs_f32(seconds));
+        app.update();
+        let breaths = app.world().resource::<Events<BreathTaken>>();
+        let exhales = app.world().resource::<Events<BreathExhaled>>();
+        (
+            breaths.get_reader().read(breaths).count(),
+            exhales.get_reader().read(exhales).count(),
+        )
+    };
+    let interval = 60. / DEFAULT_BREATHING_RATE;
+    assert_eq!(advance(interval / 4.), (0, 0));
+    assert_eq!(advance(interval).0, 1);
+    // breathed out halfway to the next breath
+    assert_eq!(advance(interval / 2.).1, 1);
+    let breathing = app.world().get::<Breathing>(breather_id).unwrap();
+    assert!(breathing.exhaled);
+}
+
+#[test]
+fn breathing_modifiers() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<BreathTaken>();
+    app.add_event::<BreathExhaled>();
+    app.add_systems(Update, breathe);
+    let interval = 60. / DEFAULT_BREATHING_RATE;
+    let holding_id = app
+        .world_mut()
+        .spawn(Breathing {
+            holding: true,
+            ..default()
+        })
+        .id();
+    let skipping_id = app
+        .world_mut()
+        .spawn(Breathing {
+            skip: 1,
+            ..default()
+        })
+        .id();
+    let exerted_id = app
+        .world_mut()
+        .spawn((Breathing::default(), BreathingDemand(2.)))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs_f32(interval));
+    app.update();
+    let breaths = app.world().resource::<Events<BreathTaken>>();
+    let breathed: Vec<Entity> = breaths
+        .get_reader()
+        .read(breaths)
+        .map(|breath| breath.entity)
+        .collect();
+    // only the hard-working breather is due a breath yet
+    assert_eq!(breathed, vec![exerted_id]);
+    let holding = app.world().get::<Breathing>(holding_id).unwrap();
+    assert_eq!(holding.elapsed, 0.);
+    let skipping = app.world().get::<Breathing>(skipping_id).unwrap();
+    assert_eq!(skipping.cycle(1.), 2. * interval);
+}
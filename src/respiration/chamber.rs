@@ -0,0 +1,193 @@
+use crate::diver::Diver;
+use crate::environment::DiveSiteEnvironment;
+use crate::position::Depth;
+use crate::respiration::circulation::decompression::*;
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::lungs::ArterialGasEmbolism;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+
+const CHAMBER_WIDTH: f32 = 10.;
+const CHAMBER_HEIGHT: f32 = 6.;
+// how close, in pixels, the diver has to be to climb into the chamber
+const CHAMBER_RANGE: f32 = 20.;
+// minutes of treatment that pass for each second of game time
+const TREATMENT_TIME_SCALE: f32 = 60.;
+// a shortened US Navy Table 6 on oxygen, as (depth in metres of seawater, minutes)
+const TREATMENT_TABLE: [(f32, f32); 3] = [(18., 75.), (9., 150.), (0., 30.)];
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct RecompressionChamber;
+
+/// A diver sealed in the chamber, working through `TREATMENT_TABLE`.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct ChamberTreatment {
+    pub stage: usize,
+    pub elapsed_minutes: f32,
+}
+
+impl ChamberTreatment {
+    /// Chamber depth at the current stage, or `None` once the table is finished.
+    pub fn depth(&self) -> Option<f32> {
+        TREATMENT_TABLE.get(self.stage).map(|(depth, _)| *depth)
+    }
+
+    pub fn remaining_minutes(&self) -> f32 {
+        TREATMENT_TABLE
+            .iter()
+            .skip(self.stage)
+            .map(|(_, minutes)| minutes)
+            .sum::<f32>()
+            - self.elapsed_minutes
+    }
+}
+
+pub fn chamber_plugin(app: &mut App) {
+    app.add_systems(Startup, spawn_chambers);
+    app.add_systems(Update, enter_chamber.in_set(RunningStateSet));
+    app.add_systems(
+        FixedUpdate,
+        treat_in_chamber
+            .after(load_tissues)
+            .after(decompression_sickness)
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<RecompressionChamber>();
+    app.register_type::<ChamberTreatment>();
+}
+
+pub fn spawn_chambers(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    println!("Spawning recompression chambers");
+
+    let mesh = Mesh::from(Rectangle::new(CHAMBER_WIDTH, CHAMBER_HEIGHT));
+    let material = ColorMaterial::from_color(Srgba::rgb(0.7, 0.7, 0.7));
+
+    commands.spawn((
+        RecompressionChamber,
+        MaterialMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            material: materials.add(material),
+            transform: Transform::from_translation(Vec3::new(-120., 0., 0.)),
+            ..default()
+        },
+        Name::new("Recompression chamber"),
+        crate::PIXEL_PERFECT_LAYERS,
+    ));
+}
+
+/// Divers with symptoms, or ones on the way, who aren't already being treated.
+type Untreated = (
+    With<Diver>,
+    Or<(With<DecompressionSickness>, With<PendingDcs>)>,
+    Without<ChamberTreatment>,
+);
+
+/// The chamber is only worth climbing into with symptoms, or ones on the way.
+pub fn enter_chamber(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    diver: Query<(Entity, &Transform, &Depth), Untreated>,
+    chambers: Query<&Transform, With<RecompressionChamber>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyC) {
+        return;
+    }
+    if let Ok((entity, diver_transform, depth)) = diver.get_single() {
+        if depth.0 <= 0.
+            && chambers.iter().any(|transform| {
+                transform.translation.distance(diver_transform.translation) < CHAMBER_RANGE
+            })
+        {
+            println!("starting recompression treatment");
+            commands.entity(entity).insert(ChamberTreatment::default());
+        }
+    }
+}
+
+/// Breathing oxygen under pressure shrinks the bubbles and washes out the inert gas. Finishing
+/// the table cures the diver.
+pub fn treat_in_chamber(
+    mut commands: Commands,
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    mut patients: Query<(
+        Entity,
+        &mut ChamberTreatment,
+        &mut TissueCompartments,
+        Option<&mut DcsRisk>,
+    )>,
+) {
+    for (entity, mut treatment, mut tissues, dcs_risk) in &mut patients {
+        let minutes = time.delta_seconds() * TREATMENT_TIME_SCALE;
+        if let Some(depth) = treatment.depth() {
+            // chamber gauges read in metres of seawater above the site's own surface pressure
+            let pressure = environment.surface_pressure
+                + DiveSiteEnvironment::SEA.pressure_per_metre() * depth;
+            tissues.load(pressure, &GasMix::OXYGEN, minutes * 60.);
+            treatment.elapsed_minutes += minutes;
+            let (_, stage_minutes) = TREATMENT_TABLE[treatment.stage];
+            if treatment.elapsed_minutes >= stage_minutes {
+                treatment.stage += 1;
+                treatment.elapsed_minutes = 0.;
+            }
+        } else {
+            println!("recompression treatment finished");
+            commands.entity(entity).remove::<(
+                ChamberTreatment,
+                DecompressionSickness,
+                PendingDcs,
+                ArterialGasEmbolism,
+            )>();
+            if let Some(mut dcs_risk) = dcs_risk {
+                dcs_risk.0 = 0.;
+            }
+        }
+    }
+}
+
+#[test]
+fn did_treat_in_chamber() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, treat_in_chamber);
+    let sea = DiveSiteEnvironment::SEA;
+    let mut tissues = TissueCompartments::default();
+    tissues.load(sea.ambient_pressure(40.), &GasMix::AIR, 30. * 60.);
+    let patient_id = app
+        .world_mut()
+        .spawn((
+            ChamberTreatment::default(),
+            tissues,
+            DcsRisk(1.),
+            DecompressionSickness {
+                dcs_type: DcsType::TypeII,
+                severity: 1.,
+            },
+        ))
+        .id();
+    let total_minutes = ChamberTreatment::default().remaining_minutes();
+    let steps = (total_minutes / TREATMENT_TIME_SCALE).ceil() as u32 + TREATMENT_TABLE.len() as u32;
+    for _ in 0..steps {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(std::time::Duration::from_secs(1));
+        app.update();
+    }
+    assert!(app.world().get::<ChamberTreatment>(patient_id).is_none());
+    assert!(app
+        .world()
+        .get::<DecompressionSickness>(patient_id)
+        .is_none());
+    assert_eq!(app.world().get::<DcsRisk>(patient_id).unwrap().0, 0.);
+    // the inert gas has been washed out
+    let tissues = app.world().get::<TissueCompartments>(patient_id).unwrap();
+    assert!(tissues.supersaturation(sea.surface_pressure) <= 0.);
+}
@@ -0,0 +1,272 @@
+use bevy::prelude::*;
+
+use crate::diver::Swimming;
+use crate::environment::DiveSiteEnvironment;
+use crate::position::{Depth, Velocity};
+use crate::respiration::circulation::*;
+use crate::respiration::inhalation::BreathTaken;
+use crate::respiration::BloodstreamContent;
+use crate::states::RunningStateSet;
+
+// arterial partial pressures, in mmHg
+const NORMAL_PACO2: f32 = 40.;
+// the lowest a breath can bring the level down to
+const MIN_PACO2: f32 = 35.;
+const HYPERCAPNIA_THRESHOLD: f32 = 45.;
+// production at rest, and extra production for each unit of swimming and velocity, in mmHg/s.
+// Breathing by hand about every 45 seconds holds the level near normal.
+const RESTING_PRODUCTION: f32 = 0.045;
+const EXERTION_PRODUCTION: f32 = 0.045;
+// proportion of the level above `MIN_PACO2` that a breath of surface air removes
+const BREATH_ELIMINATION: f32 = 0.4;
+// how much each unit of gas density above surface air reduces elimination
+const DENSITY_RESISTANCE: f32 = 0.1;
+// extra breathing demand and narcosis for each mmHg above the hypercapnia threshold
+const DEMAND_PER_MMHG: f32 = 0.05;
+const NARCOSIS_PER_MMHG: f32 = 0.02;
+// molar masses in g/mol
+const OXYGEN_MOLAR_MASS: f32 = 32.;
+const NITROGEN_MOLAR_MASS: f32 = 28.;
+const HELIUM_MOLAR_MASS: f32 = 4.;
+
+/// Arterial partial pressure of carbon dioxide, in mmHg.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct CarbonDioxide(pub f32);
+
+impl Default for CarbonDioxide {
+    fn default() -> Self {
+        Self(NORMAL_PACO2)
+    }
+}
+
+impl CarbonDioxide {
+    fn excess(&self) -> f32 {
+        (self.0 - HYPERCAPNIA_THRESHOLD).max(0.)
+    }
+
+    pub fn is_hypercapnic(&self) -> bool {
+        self.0 > HYPERCAPNIA_THRESHOLD
+    }
+
+    /// Factor applied to the narcotic pressure of the breathing mix.
+    pub fn narcosis_multiplier(&self) -> f32 {
+        1. + NARCOSIS_PER_MMHG * self.excess()
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Headache;
+
+pub fn carbon_dioxide_plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (
+            produce_carbon_dioxide,
+            exhale_carbon_dioxide.after(produce_carbon_dioxide),
+            hypercapnia
+                .after(exhale_carbon_dioxide)
+                .after(reset_breathing_demand)
+                .before(usage),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<CarbonDioxide>();
+    app.register_type::<Headache>();
+}
+
+/// Density of the mix at `ambient_pressure`, relative to air at the surface.
+pub fn relative_density(mix: &GasMix, ambient_pressure: f32) -> f32 {
+    let molar_mass = |mix: &GasMix| {
+        mix.oxygen * OXYGEN_MOLAR_MASS
+            + mix.nitrogen * NITROGEN_MOLAR_MASS
+            + mix.helium * HELIUM_MOLAR_MASS
+    };
+    ambient_pressure * molar_mass(mix) / molar_mass(&GasMix::AIR)
+}
+
+pub fn produce_carbon_dioxide(
+    time: Res<Time>,
+    mut breathers: Query<(&mut CarbonDioxide, Option<&Swimming>, Option<&Velocity>)>,
+) {
+    for (mut carbon_dioxide, swimming, velocity) in &mut breathers {
+        carbon_dioxide.0 += (RESTING_PRODUCTION
+            + EXERTION_PRODUCTION * exertion(swimming, velocity))
+            * time.delta_seconds();
+    }
+}
+
+#[test]
+fn did_produce_with_exertion() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, produce_carbon_dioxide);
+    let resting_id = app.world_mut().spawn(CarbonDioxide::default()).id();
+    let swimming_id = app
+        .world_mut()
+        .spawn((
+            CarbonDioxide::default(),
+            Swimming(Vec3::new(1., 0., 0.)),
+            Velocity(Vec3::new(0., 1., 0.)),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(2));
+    app.update();
+    let resting = app.world().get::<CarbonDioxide>(resting_id).unwrap();
+    assert_eq!(resting.0, NORMAL_PACO2 + 2. * RESTING_PRODUCTION);
+    let swimming = app.world().get::<CarbonDioxide>(swimming_id).unwrap();
+    assert_eq!(
+        swimming.0,
+        NORMAL_PACO2 + 2. * (RESTING_PRODUCTION + 2. * EXERTION_PRODUCTION)
+    );
+}
+
+pub fn exhale_carbon_dioxide(
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<(&mut CarbonDioxide, &BloodstreamContent, &Depth)>,
+    mut breaths: EventReader<BreathTaken>,
+) {
+    for breath in breaths.read() {
+        if let Ok((mut carbon_dioxide, bloodstream_content, depth)) =
+            breathers.get_mut(breath.entity)
+        {
+            // denser gas is harder to move, so each breath clears less
+            let density = relative_density(
+                &bloodstream_content.mix,
+                environment.ambient_pressure(depth.0),
+            );
+            let elimination =
+                BREATH_ELIMINATION / (1. + DENSITY_RESISTANCE * (density - 1.).max(0.));
+            let removed = elimination * (carbon_dioxide.0 - MIN_PACO2).max(0.);
+            carbon_dioxide.0 -= removed;
+        }
+    }
+}
+
+#[test]
+fn did_exhale() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, exhale_carbon_dioxide);
+    let surface_id = app
+        .world_mut()
+        .spawn((CarbonDioxide(45.), BloodstreamContent::default(), Depth(0.)))
+        .id();
+    let deep_id = app
+        .world_mut()
+        .spawn((
+            CarbonDioxide(45.),
+            BloodstreamContent::default(),
+            Depth(40.),
+        ))
+        .id();
+    let mut breaths = app.world_mut().resource_mut::<Events<BreathTaken>>();
+    breaths.send(BreathTaken { entity: surface_id });
+    breaths.send(BreathTaken { entity: deep_id });
+    app.update();
+    let surface = app.world().get::<CarbonDioxide>(surface_id).unwrap();
+    assert_eq!(surface.0, 45. - BREATH_ELIMINATION * 10.);
+    // the same breath at depth should clear less
+    let deep = app.world().get::<CarbonDioxide>(deep_id).unwrap();
+    assert!(deep.0 > surface.0);
+    assert!(deep.0 < 45.);
+}
+
+#[test]
+fn did_retain_while_skip_breathing() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<BreathTaken>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(
+        Update,
+        (
+            produce_carbon_dioxide,
+            exhale_carbon_dioxide.after(produce_carbon_dioxide),
+        ),
+    );
+    let regular_id = app
+        .world_mut()
+        .spawn((
+            CarbonDioxide::default(),
+            BloodstreamContent::default(),
+            Depth(0.),
+        ))
+        .id();
+    let skipping_id = app
+        .world_mut()
+        .spawn((
+            CarbonDioxide::default(),
+            BloodstreamContent::default(),
+            Depth(0.),
+        ))
+        .id();
+    // breathe at a manual pace for 15 minutes, skipping all but every fourth breath
+    for i in 0..20 {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(std::time::Duration::from_secs(45));
+        let mut breaths = app.world_mut().resource_mut::<Events<BreathTaken>>();
+        breaths.send(BreathTaken { entity: regular_id });
+        if i % 4 == 0 {
+            breaths.send(BreathTaken {
+                entity: skipping_id,
+            });
+        }
+        app.update();
+    }
+    let regular = app.world().get::<CarbonDioxide>(regular_id).unwrap();
+    assert!((regular.0 - NORMAL_PACO2).abs() < 3.);
+    let skipping = app.world().get::<CarbonDioxide>(skipping_id).unwrap();
+    assert!(skipping.is_hypercapnic());
+}
+
+pub fn hypercapnia(
+    mut commands: Commands,
+    mut breathers: Query<(
+        Entity,
+        &CarbonDioxide,
+        &mut BreathingDemand,
+        Option<&Headache>,
+    )>,
+) {
+    for (entity, carbon_dioxide, mut breathing_demand, headache) in &mut breathers {
+        breathing_demand.0 *= 1. + DEMAND_PER_MMHG * carbon_dioxide.excess();
+        if carbon_dioxide.is_hypercapnic() && headache.is_none() {
+            commands.entity(entity).insert(Headache);
+        } else if !carbon_dioxide.is_hypercapnic() && headache.is_some() {
+            commands.entity(entity).remove::<Headache>();
+        }
+    }
+}
+
+#[test]
+fn did_hypercapnia() {
+    let mut app = App::new();
+    app.add_systems(Update, hypercapnia);
+    let breather_id = app
+        .world_mut()
+        .spawn((CarbonDioxide(55.), BreathingDemand::default()))
+        .id();
+    app.update();
+    assert!(app.world().get::<Headache>(breather_id).is_some());
+    let breathing_demand = app.world().get::<BreathingDemand>(breather_id).unwrap();
+    assert_eq!(breathing_demand.0, 1. + DEMAND_PER_MMHG * 10.);
+    // should recover once the level is back to normal
+    app.world_mut()
+        .get_mut::<CarbonDioxide>(breather_id)
+        .unwrap()
+        .0 = NORMAL_PACO2;
+    app.world_mut()
+        .get_mut::<BreathingDemand>(breather_id)
+        .unwrap()
+        .0 = 1.;
+    app.update();
+    assert!(app.world().get::<Headache>(breather_id).is_none());
+    let breathing_demand = app.world().get::<BreathingDemand>(breather_id).unwrap();
+    assert_eq!(breathing_demand.0, 1.);
+}
@@ -1,163 +1,621 @@
-use crate::circulation::*;
+use crate::environment::*;
 use crate::health::*;
+use crate::position::*;
+use crate::respiration::chamber::ChamberTreatment;
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::BloodstreamContent;
+use crate::respiration::HazardRng;
 use crate::states::RunningStateSet;
 use bevy::prelude::*;
+#[cfg(test)]
+use rand::rngs::StdRng;
+use rand::Rng;
+#[cfg(test)]
+use rand::SeedableRng;
 
-const DEFAULT_LOAD: f32 = 1.;
-const ASCENT_RATE: f32 = 1. / (64. * 60.);
+pub const COMPARTMENT_COUNT: usize = 16;
+pub const WATER_VAPOUR_PRESSURE_BAR: f32 = 0.0627;
+const DEFAULT_DAMAGE_FACTOR: f32 = 0.1;
+// seconds after the tissues first go over their M-values before symptoms show
+const MIN_DCS_ONSET: f32 = 20.;
+const MAX_DCS_ONSET: f32 = 120.;
+// depth, in metres, shallower than which bubbles come out of the tissues
+const DCS_DEPTH: f32 = 3.;
+// supersaturation, in bar, beyond which bubbles reach the nervous system, and then paralyse
+const TYPE_II_SEVERITY: f32 = 0.5;
+const PARALYSIS_SEVERITY: f32 = 1.5;
+const TYPE_II_DAMAGE_FACTOR: f32 = 5.;
+// proportion of swimming speed left with joint pain
+const JOINT_PAIN_SPEED_FACTOR: f32 = 0.6;
 
-#[derive(Component, Reflect)]
+/// Half-time (in minutes) and M-value coefficients of a single Bühlmann compartment.
+pub struct CompartmentCoefficients {
+    pub half_time: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+const fn compartment(half_time: f32, a: f32, b: f32) -> CompartmentCoefficients {
+    CompartmentCoefficients { half_time, a, b }
+}
+
+/// Nitrogen coefficients of the Bühlmann ZHL-16C model.
+pub const ZHL16C_NITROGEN: [CompartmentCoefficients; COMPARTMENT_COUNT] = [
+    compartment(5.0, 1.1696, 0.5578),
+    compartment(8.0, 1.0, 0.6514),
+    compartment(12.5, 0.8618, 0.7222),
+    compartment(18.5, 0.7562, 0.7825),
+    compartment(27.0, 0.62, 0.8126),
+    compartment(38.3, 0.5043, 0.8434),
+    compartment(54.3, 0.441, 0.8693),
+    compartment(77.0, 0.4, 0.891),
+    compartment(109.0, 0.375, 0.9092),
+    compartment(146.0, 0.35, 0.9222),
+    compartment(187.0, 0.3295, 0.9319),
+    compartment(239.0, 0.3065, 0.9403),
+    compartment(305.0, 0.2835, 0.9477),
+    compartment(390.0, 0.261, 0.9544),
+    compartment(498.0, 0.248, 0.9602),
+    compartment(635.0, 0.2327, 0.9653),
+];
+
+/// Helium coefficients of the Bühlmann ZHL-16C model.
+pub const ZHL16C_HELIUM: [CompartmentCoefficients; COMPARTMENT_COUNT] = [
+    compartment(1.88, 1.6189, 0.477),
+    compartment(3.02, 1.383, 0.5747),
+    compartment(4.72, 1.1919, 0.6527),
+    compartment(6.99, 1.0458, 0.7223),
+    compartment(10.21, 0.922, 0.7582),
+    compartment(14.48, 0.8205, 0.7957),
+    compartment(20.53, 0.7305, 0.8279),
+    compartment(29.11, 0.6502, 0.8553),
+    compartment(41.2, 0.595, 0.8757),
+    compartment(55.19, 0.5545, 0.8903),
+    compartment(70.69, 0.5333, 0.8997),
+    compartment(90.34, 0.5189, 0.9073),
+    compartment(115.29, 0.5181, 0.9122),
+    compartment(147.42, 0.5176, 0.9171),
+    compartment(188.24, 0.5172, 0.9217),
+    compartment(240.03, 0.5119, 0.9267),
+];
+
+/// Inert gas tension (in bar) of each tissue compartment. Tensions are kept in double
+/// precision because the slow compartments change by less than an `f32` ulp per tick.
+#[derive(Component, Reflect, Clone)]
 #[reflect(Component)]
-pub struct GasExchangeInLungs {
-    pub max_load: f32,
-    pub load: f32,
-    pub recovery_rate: f32,
+pub struct TissueCompartments {
+    pub nitrogen: [f64; COMPARTMENT_COUNT],
+    pub helium: [f64; COMPARTMENT_COUNT],
+    pub damage_factor: f32,
 }
 
-impl Default for GasExchangeInLungs {
+/// Extra risk of decompression sickness from ascending faster than the tissues allow, such as by
+/// skipping stops. 0 is no extra risk.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct DcsRisk(pub f32);
+
+impl Default for TissueCompartments {
+    /// Saturated with air at sea level, where the diver has travelled from.
     fn default() -> Self {
+        Self::saturated(STANDARD_PRESSURE, &GasMix::AIR)
+    }
+}
+
+impl TissueCompartments {
+    /// Tissues that have been breathing the given mix at the given pressure indefinitely.
+    pub fn saturated(ambient_pressure: f32, mix: &GasMix) -> Self {
         Self {
-            max_load: DEFAULT_LOAD,
-            load: 0.,
-            recovery_rate: ASCENT_RATE,
+            nitrogen: [inspired_pressure(ambient_pressure, mix.nitrogen); COMPARTMENT_COUNT],
+            helium: [inspired_pressure(ambient_pressure, mix.helium); COMPARTMENT_COUNT],
+            damage_factor: DEFAULT_DAMAGE_FACTOR,
         }
     }
+
+    /// Applies the Haldane equation to every compartment for a constant ambient pressure.
+    pub fn load(&mut self, ambient_pressure: f32, mix: &GasMix, seconds: f32) {
+        load_gas(
+            &mut self.nitrogen,
+            &ZHL16C_NITROGEN,
+            inspired_pressure(ambient_pressure, mix.nitrogen),
+            seconds,
+        );
+        load_gas(
+            &mut self.helium,
+            &ZHL16C_HELIUM,
+            inspired_pressure(ambient_pressure, mix.helium),
+            seconds,
+        );
+    }
+
+    /// Total inert gas tension of a compartment and its a/b coefficients, weighted by how much
+    /// of that tension comes from each gas.
+    pub fn compartment(&self, i: usize) -> (f32, f32, f32) {
+        let nitrogen = self.nitrogen[i] as f32;
+        let helium = self.helium[i] as f32;
+        let tension = nitrogen + helium;
+        if tension <= 0. {
+            return (0., ZHL16C_NITROGEN[i].a, ZHL16C_NITROGEN[i].b);
+        }
+        let weighted = |nitrogen_coefficient: f32, helium_coefficient: f32| {
+            (nitrogen_coefficient * nitrogen + helium_coefficient * helium) / tension
+        };
+        (
+            tension,
+            weighted(ZHL16C_NITROGEN[i].a, ZHL16C_HELIUM[i].a),
+            weighted(ZHL16C_NITROGEN[i].b, ZHL16C_HELIUM[i].b),
+        )
+    }
+
+    /// The lowest ambient pressure every compartment tolerates without exceeding its M-value.
+    pub fn tolerated_ambient_pressure(&self) -> f32 {
+        (0..COMPARTMENT_COUNT)
+            .map(|i| {
+                let (tension, a, b) = self.compartment(i);
+                (tension - a) * b
+            })
+            .fold(0., f32::max)
+    }
+
+    /// How far (in bar) the most loaded compartment is above its M-value at the given ambient
+    /// pressure. Negative values mean every compartment is within its limit.
+    pub fn supersaturation(&self, ambient_pressure: f32) -> f32 {
+        (0..COMPARTMENT_COUNT)
+            .map(|i| {
+                let (tension, a, b) = self.compartment(i);
+                tension - m_value(a, b, ambient_pressure)
+            })
+            .fold(f32::NEG_INFINITY, f32::max)
+    }
+}
+
+fn load_gas(
+    tensions: &mut [f64; COMPARTMENT_COUNT],
+    coefficients: &[CompartmentCoefficients; COMPARTMENT_COUNT],
+    inspired: f64,
+    seconds: f32,
+) {
+    for (tension, coefficients) in tensions.iter_mut().zip(coefficients.iter()) {
+        *tension += (inspired - *tension) * saturation_fraction(coefficients, seconds);
+    }
+}
+
+pub fn inspired_pressure(ambient_pressure: f32, proportion: f32) -> f64 {
+    ((ambient_pressure - WATER_VAPOUR_PRESSURE_BAR).max(0.) * proportion) as f64
+}
+
+pub fn m_value(a: f32, b: f32, ambient_pressure: f32) -> f32 {
+    a + ambient_pressure / b
+}
+
+fn saturation_fraction(coefficients: &CompartmentCoefficients, seconds: f32) -> f64 {
+    let rate = std::f64::consts::LN_2 / (coefficients.half_time as f64 * 60.);
+    -(-rate * seconds as f64).exp_m1()
 }
 
 pub fn decompression_plugin(app: &mut App) {
     app.add_systems(
         FixedUpdate,
         (
-            outgassing_load.after(equalize_pressure),
-            outgassing_deload.after(outgassing_load),
+            load_tissues.after(update_depth),
+            decompression_sickness.after(load_tissues),
+            dcs_onset.after(decompression_sickness),
+            dcs_symptoms,
         )
             .in_set(RunningStateSet),
     );
-    app.register_type::<GasExchangeInLungs>();
+    app.register_type::<TissueCompartments>();
+    app.register_type::<DcsRisk>();
+    app.register_type::<PendingDcs>();
+    app.register_type::<DecompressionSickness>();
 }
 
-pub fn outgassing_load(
-    mut breathers: Query<&mut GasExchangeInLungs, With<Health>>,
-    mut bloodstream_outgassings: EventReader<Outgassing>,
-    mut damage_events: EventWriter<DamageEvent>,
+#[test]
+fn saturated_at_surface() {
+    let tissues = TissueCompartments::default();
+    let expected = ((1. - WATER_VAPOUR_PRESSURE_BAR) * GasMix::AIR.nitrogen) as f64;
+    assert!(tissues.nitrogen.iter().all(|tension| *tension == expected));
+    assert!(tissues.helium.iter().all(|tension| *tension == 0.));
+    assert!(tissues.supersaturation(STANDARD_PRESSURE) < 0.);
+}
+
+#[test]
+fn surface_m_value() {
+    // published ZHL-16C surfacing M-value of the fastest compartment
+    let m0 = m_value(ZHL16C_NITROGEN[0].a, ZHL16C_NITROGEN[0].b, 1.);
+    assert!((m0 - 2.9624).abs() < 1e-4);
+}
+
+#[test]
+fn loaded_one_half_time() {
+    let mut tissues = TissueCompartments::default();
+    tissues.load(4., &GasMix::AIR, 5. * 60.);
+    let start = inspired_pressure(1., 0.79);
+    let target = inspired_pressure(4., 0.79);
+    assert!((tissues.nitrogen[0] - (start + target) / 2.).abs() < 1e-6);
+}
+
+#[test]
+fn reference_profile_30m_air() {
+    // 20 minutes at 30 metres on air, descent ignored
+    let mut tissues = TissueCompartments::default();
+    tissues.load(4., &GasMix::AIR, 20. * 60.);
+    let expected = [
+        2.9623, 2.6915, 2.3287, 1.9902, 1.6922, 1.4602, 1.2745, 1.131, 1.0235, 0.9551, 0.9098,
+        0.874, 0.8458, 0.8232, 0.8055, 0.7916,
+    ];
+    for (tension, expected) in tissues.nitrogen.iter().zip(expected) {
+        assert!((tension - expected).abs() < 1e-3);
+    }
+    assert!((tissues.tolerated_ambient_pressure() - 1.1018).abs() < 1e-3);
+    // an hour at the same depth puts the ceiling at roughly 6 metres
+    tissues.load(4., &GasMix::AIR, 40. * 60.);
+    assert!((tissues.tolerated_ambient_pressure() - 1.6464).abs() < 1e-3);
+}
+
+#[test]
+fn reference_profile_60m_trimix() {
+    // 20 minutes at 60 metres on 18/45 trimix, descent ignored
+    let mut tissues = TissueCompartments::default();
+    tissues.load(7., &GasMix::trimix(0.18, 0.45).unwrap(), 20. * 60.);
+    let expected = [
+        3.1198, 3.0901, 2.9563, 2.6922, 2.3188, 1.9233, 1.5327, 1.1828, 0.892, 0.6934, 0.5559,
+        0.4441, 0.3537, 0.2802, 0.2216, 0.1752,
+    ];
+    for (tension, expected) in tissues.helium.iter().zip(expected) {
+        assert!((tension - expected).abs() < 1e-3);
+    }
+    assert!((tissues.tolerated_ambient_pressure() - 2.6268).abs() < 1e-3);
+}
+
+#[test]
+fn loaded_per_tick() {
+    // many short steps should match one long step
+    let mut stepped = TissueCompartments::default();
+    for _ in 0..(64 * 60 * 10) {
+        stepped.load(3., &GasMix::AIR, 1. / 64.);
+    }
+    let mut single = TissueCompartments::default();
+    single.load(3., &GasMix::AIR, 10. * 60.);
+    for (stepped, single) in stepped.nitrogen.iter().zip(single.nitrogen.iter()) {
+        assert!((stepped - single).abs() < 1e-6);
+    }
+}
+
+pub fn load_tissues(
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<
+        (&mut TissueCompartments, &BloodstreamContent, &Depth),
+        Without<ChamberTreatment>,
+    >,
 ) {
-    for bloodstream_outgassing in bloodstream_outgassings.read() {
-        if let Ok(mut lungs) = breathers.get_mut(bloodstream_outgassing.entity) {
-            lungs.load += bloodstream_outgassing.amount;
-            if lungs.load > lungs.max_load {
-                damage_events.send(DamageEvent {
-                    target: bloodstream_outgassing.entity,
-                    damage: lungs.load - lungs.max_load,
-                });
-            }
-            lungs.load = lungs.load.min(lungs.max_load);
-        }
+    for (mut tissues, bloodstream_content, depth) in &mut breathers {
+        tissues.load(
+            environment.ambient_pressure(depth.0),
+            &bloodstream_content.mix,
+            time.delta_seconds(),
+        );
     }
 }
 
 #[test]
-fn harmful_outgassing() {
+fn did_load_tissues() {
     let mut app = App::new();
-    app.add_event::<Outgassing>();
-    app.add_event::<DamageEvent>();
-    app.add_systems(Update, outgassing_load);
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, load_tissues);
     let breather_id = app
         .world_mut()
         .spawn((
-            GasExchangeInLungs {
-                max_load: 2.,
-                load: 0.,
-                recovery_rate: 0.,
+            TissueCompartments::saturated(1., &GasMix::nitrox(0.5).unwrap()),
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 100.,
+                mix: GasMix {
+                    oxygen: 0.5,
+                    nitrogen: 0.5,
+                    helium: 0.,
+                },
             },
-            Health(100.),
+            Depth(30.),
         ))
         .id();
     app.world_mut()
-        .resource_mut::<Events<Outgassing>>()
-        .send(Outgassing {
-            entity: breather_id,
-            amount: 3.,
-        });
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(5 * 60));
     app.update();
-    let lungs = app.world().get::<GasExchangeInLungs>(breather_id).unwrap();
-    assert_eq!(lungs.load, 2.);
-    // should send a DamageEvent
-    let damage_events = app.world().resource::<Events<DamageEvent>>();
-    let mut damage_reader = damage_events.get_reader();
-    let damage = damage_reader.read(damage_events).next().unwrap();
-    assert_eq!(damage.target, breather_id);
-    assert_eq!(damage.damage, 1.);
+    let tissues = app.world().get::<TissueCompartments>(breather_id).unwrap();
+    let expected = (inspired_pressure(1., 0.5) + inspired_pressure(4., 0.5)) / 2.;
+    assert!((tissues.nitrogen[0] - expected).abs() < 1e-6);
+    assert!(tissues.nitrogen[15] < tissues.nitrogen[0]);
+}
+
+/// Marks the breather to come down with decompression sickness once `onset` finishes. Bubbles
+/// take a while to form, so symptoms usually show some time after surfacing.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct PendingDcs {
+    pub onset: Timer,
+    pub severity: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum DcsType {
+    /// Joint and muscle pain, the bends.
+    TypeI,
+    /// Bubbles in the spinal cord or brain.
+    TypeII,
+}
+
+impl DcsType {
+    pub fn from_severity(severity: f32) -> Self {
+        if severity > TYPE_II_SEVERITY {
+            Self::TypeII
+        } else {
+            Self::TypeI
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct DecompressionSickness {
+    pub dcs_type: DcsType,
+    pub severity: f32,
+}
+
+impl DecompressionSickness {
+    pub fn is_paralysed(&self) -> bool {
+        self.severity > PARALYSIS_SEVERITY
+    }
+
+    /// Joint pain slows swimming, while neurological symptoms swap left and right or stop the
+    /// legs working at all.
+    pub fn affect_swimming(&self, swimming: Vec3) -> Vec3 {
+        match self.dcs_type {
+            DcsType::TypeI => swimming * JOINT_PAIN_SPEED_FACTOR,
+            DcsType::TypeII if self.is_paralysed() => Vec3::ZERO,
+            DcsType::TypeII => Vec3::new(-swimming.x, swimming.y, swimming.z),
+        }
+    }
+}
+
+/// Breathers who could still come down with decompression sickness.
+type Susceptible = (
+    With<Health>,
+    Without<DecompressionSickness>,
+    Without<ChamberTreatment>,
+);
+
+/// Any supersaturation beyond the M-values on reaching the surface sets off decompression
+/// sickness. Its severity is the worst supersaturation reached before the symptoms show, made
+/// worse by skipped stops.
+pub fn decompression_sickness(
+    mut commands: Commands,
+    environment: Res<DiveSiteEnvironment>,
+    mut rng: ResMut<HazardRng>,
+    breathers: Query<(Entity, &TissueCompartments, &Depth, Option<&DcsRisk>), Susceptible>,
+    mut pending: Query<&mut PendingDcs>,
+) {
+    for (entity, tissues, depth, dcs_risk) in &breathers {
+        let supersaturation = tissues.supersaturation(environment.ambient_pressure(depth.0));
+        if depth.0 > DCS_DEPTH || supersaturation <= 0. {
+            continue;
+        }
+        let severity = supersaturation * (1. + dcs_risk.map_or(0., |dcs_risk| dcs_risk.0));
+        if let Ok(mut pending_dcs) = pending.get_mut(entity) {
+            pending_dcs.severity = pending_dcs.severity.max(severity);
+        } else {
+            println!(
+                "depth: {}, supersaturation: {}, decompression sickness pending",
+                depth.0, supersaturation
+            );
+            let delay = MIN_DCS_ONSET + rng.0.gen::<f32>() * (MAX_DCS_ONSET - MIN_DCS_ONSET);
+            commands.entity(entity).insert(PendingDcs {
+                onset: Timer::from_seconds(delay, TimerMode::Once),
+                severity,
+            });
+        }
+    }
 }
 
 #[test]
-fn harmless_outgassing() {
+fn harmful_supersaturation() {
     let mut app = App::new();
-    app.add_event::<Outgassing>();
-    app.add_event::<DamageEvent>();
-    app.add_systems(Update, outgassing_load);
+    app.init_resource::<DiveSiteEnvironment>();
+    app.insert_resource(HazardRng(StdRng::seed_from_u64(0)));
+    app.add_systems(Update, decompression_sickness);
+    let mut tissues = TissueCompartments::default();
+    tissues.nitrogen[0] = (m_value(ZHL16C_NITROGEN[0].a, ZHL16C_NITROGEN[0].b, 1.) + 1.) as f64;
     let breather_id = app
         .world_mut()
-        .spawn((
-            GasExchangeInLungs {
-                max_load: 2.,
-                load: 0.,
-                recovery_rate: 0.,
-            },
-            Health(100.),
-        ))
+        .spawn((tissues, Depth(0.), Health(100.)))
         .id();
-    app.world_mut()
-        .resource_mut::<Events<Outgassing>>()
-        .send(Outgassing {
-            entity: breather_id,
-            amount: 1.,
-        });
     app.update();
-    let lungs = app.world().get::<GasExchangeInLungs>(breather_id).unwrap();
-    assert_eq!(lungs.load, 1.);
-    // should not send a DamageEvent
-    let damage_events = app.world().resource::<Events<DamageEvent>>();
-    let mut damage_reader = damage_events.get_reader();
-    let damage = damage_reader.read(damage_events).next();
-    assert!(damage.is_none());
+    // should be coming down with it, but not yet
+    let pending_dcs = app.world().get::<PendingDcs>(breather_id).unwrap();
+    assert!((pending_dcs.severity - 1.).abs() < 1e-4);
+    assert!(pending_dcs.onset.duration().as_secs_f32() >= MIN_DCS_ONSET);
+    assert!(app
+        .world()
+        .get::<DecompressionSickness>(breather_id)
+        .is_none());
 }
 
-pub fn outgassing_deload(mut gas_exchangers: Query<&mut GasExchangeInLungs>) {
-    for mut gas_exchanger in &mut gas_exchangers {
-        gas_exchanger.load = (gas_exchanger.load - gas_exchanger.recovery_rate).max(0.);
-    }
+#[test]
+fn risky_supersaturation() {
+    let mut app = App::new();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.insert_resource(HazardRng(StdRng::seed_from_u64(0)));
+    app.add_systems(Update, decompression_sickness);
+    let mut tissues = TissueCompartments::default();
+    tissues.nitrogen[0] = (m_value(ZHL16C_NITROGEN[0].a, ZHL16C_NITROGEN[0].b, 1.) + 1.) as f64;
+    let breather_id = app
+        .world_mut()
+        .spawn((tissues, Depth(0.), Health(100.), DcsRisk(1.)))
+        .id();
+    app.update();
+    // skipped stops should make the same supersaturation worse
+    let pending_dcs = app.world().get::<PendingDcs>(breather_id).unwrap();
+    assert!((pending_dcs.severity - 2.).abs() < 1e-4);
 }
 
 #[test]
-fn did_deload() {
+fn harmless_supersaturation() {
     let mut app = App::new();
-    app.add_systems(Update, outgassing_deload);
+    app.init_resource::<DiveSiteEnvironment>();
+    app.insert_resource(HazardRng(StdRng::seed_from_u64(0)));
+    app.add_systems(Update, decompression_sickness);
+    let mut tissues = TissueCompartments::default();
+    // over the surface M-value, but within the limit at 10 metres
+    tissues.nitrogen[0] = (m_value(ZHL16C_NITROGEN[0].a, ZHL16C_NITROGEN[0].b, 1.) + 0.5) as f64;
     let breather_id = app
         .world_mut()
-        .spawn(GasExchangeInLungs {
-            max_load: 2.,
-            load: 1.,
-            recovery_rate: 0.5,
-        })
+        .spawn((tissues, Depth(10.), Health(100.)))
         .id();
     app.update();
-    let lungs = app.world().get::<GasExchangeInLungs>(breather_id).unwrap();
-    assert_eq!(lungs.load, 0.5);
+    assert!(app.world().get::<PendingDcs>(breather_id).is_none());
 }
 
 #[test]
-fn fully_deloaded() {
+fn deep_supersaturation() {
     let mut app = App::new();
-    app.add_systems(Update, outgassing_deload);
+    app.init_resource::<DiveSiteEnvironment>();
+    app.insert_resource(HazardRng(StdRng::seed_from_u64(0)));
+    app.add_systems(Update, decompression_sickness);
+    let mut tissues = TissueCompartments::default();
+    tissues.nitrogen[0] = (m_value(ZHL16C_NITROGEN[0].a, ZHL16C_NITROGEN[0].b, 2.) + 1.) as f64;
     let breather_id = app
         .world_mut()
-        .spawn(GasExchangeInLungs {
-            max_load: 2.,
-            load: 0.,
-            recovery_rate: 0.5,
+        .spawn((tissues, Depth(10.), Health(100.)))
+        .id();
+    app.update();
+    // the bubbles only come out once the diver is nearly up
+    assert!(app.world().get::<PendingDcs>(breather_id).is_none());
+    app.world_mut().get_mut::<Depth>(breather_id).unwrap().0 = 0.;
+    app.update();
+    assert!(app.world().get::<PendingDcs>(breather_id).is_some());
+}
+
+pub fn dcs_onset(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pending: Query<(Entity, &mut PendingDcs)>,
+) {
+    for (entity, mut pending_dcs) in &mut pending {
+        pending_dcs.onset.tick(time.delta());
+        if pending_dcs.onset.finished() {
+            let dcs_type = DcsType::from_severity(pending_dcs.severity);
+            println!("decompression sickness: {:?}", dcs_type);
+            commands
+                .entity(entity)
+                .remove::<PendingDcs>()
+                .insert(DecompressionSickness {
+                    dcs_type,
+                    severity: pending_dcs.severity,
+                });
+        }
+    }
+}
+
+#[test]
+fn did_dcs_onset() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, dcs_onset);
+    let bent_id = app
+        .world_mut()
+        .spawn(PendingDcs {
+            onset: Timer::from_seconds(1., TimerMode::Once),
+            severity: TYPE_II_SEVERITY / 2.,
         })
         .id();
+    let numb_id = app
+        .world_mut()
+        .spawn(PendingDcs {
+            onset: Timer::from_seconds(1., TimerMode::Once),
+            severity: TYPE_II_SEVERITY * 2.,
+        })
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
     app.update();
-    let lungs = app.world().get::<GasExchangeInLungs>(breather_id).unwrap();
-    assert_eq!(lungs.load, 0.);
+    assert!(app.world().get::<PendingDcs>(bent_id).is_none());
+    let bent = app.world().get::<DecompressionSickness>(bent_id).unwrap();
+    assert_eq!(bent.dcs_type, DcsType::TypeI);
+    let numb = app.world().get::<DecompressionSickness>(numb_id).unwrap();
+    assert_eq!(numb.dcs_type, DcsType::TypeII);
+}
+
+pub fn dcs_symptoms(
+    time: Res<Time>,
+    sufferers: Query<(Entity, &DecompressionSickness, &TissueCompartments)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, dcs, tissues) in &sufferers {
+        let type_factor = match dcs.dcs_type {
+            DcsType::TypeI => 1.,
+            DcsType::TypeII => TYPE_II_DAMAGE_FACTOR,
+        };
+        damage_events.send(DamageEvent {
+            target: entity,
+            damage: tissues.damage_factor * dcs.severity * type_factor * time.delta_seconds(),
+        });
+    }
+}
+
+#[test]
+fn did_dcs_symptoms() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, dcs_symptoms);
+    let tissues = TissueCompartments {
+        damage_factor: 2.,
+        ..default()
+    };
+    let sufferer_id = app
+        .world_mut()
+        .spawn((
+            DecompressionSickness {
+                dcs_type: DcsType::TypeII,
+                severity: 1.,
+            },
+            tissues,
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
+    app.update();
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damage = damage_reader.read(damage_events).next().unwrap();
+    assert_eq!(damage.target, sufferer_id);
+    assert!((damage.damage - 2. * TYPE_II_DAMAGE_FACTOR).abs() < 1e-4);
+}
+
+#[test]
+fn dcs_affects_swimming() {
+    let swimming = Vec3::new(1., 1., 0.);
+    let bent = DecompressionSickness {
+        dcs_type: DcsType::TypeI,
+        severity: 0.1,
+    };
+    assert_eq!(
+        bent.affect_swimming(swimming),
+        swimming * JOINT_PAIN_SPEED_FACTOR
+    );
+    let numb = DecompressionSickness {
+        dcs_type: DcsType::TypeII,
+        severity: TYPE_II_SEVERITY,
+    };
+    assert_eq!(numb.affect_swimming(swimming), Vec3::new(-1., 1., 0.));
+    let paralysed = DecompressionSickness {
+        dcs_type: DcsType::TypeII,
+        severity: PARALYSIS_SEVERITY * 2.,
+    };
+    assert_eq!(paralysed.affect_swimming(swimming), Vec3::ZERO);
 }
@@ -1,12 +1,13 @@
 use bevy::prelude::*;
 
+use crate::environment::DiveSiteEnvironment;
 use crate::position::Depth;
 use crate::respiration::circulation::*;
 use crate::respiration::BloodstreamContent;
 use crate::states::RunningStateSet;
 
-const ATMOSPHERIC_PRESSURE_BAR: f32 = 1.;
-const BAR_MSW_RATIO: f32 = 1. / 10.;
+// seconds for the bloodstream to close half the gap to ambient pressure between breaths
+const BLOODSTREAM_HALF_TIME: f32 = 10.;
 
 #[derive(Component, Reflect)]
 #[reflect(Component)]
@@ -18,18 +19,14 @@ impl Default for BloodstreamPressure {
     }
 }
 
-#[derive(Event)]
-pub struct Outgassing {
-    pub entity: Entity,
-    pub amount: f32,
-}
-
 pub fn equalization_plugin(app: &mut App) {
-    app.add_event::<Outgassing>();
     app.add_systems(
         FixedUpdate,
-        (equalize_pressure, equalize_gases)
-            .before(crate::respiration::circulation::intake::intake_gas)
+        (
+            (equalize_pressure, equalize_gases)
+                .before(crate::respiration::circulation::intake::intake_gas),
+            exchange_pressure.after(equalize_pressure),
+        )
             .in_set(RunningStateSet),
     );
     app.register_type::<BloodstreamPressure>();
@@ -40,34 +37,20 @@ fn weighted_average(value_1: f32, weight_1: f32, value_2: f32, weight_2: f32) ->
 }
 
 pub fn equalize_pressure(
-    mut breathers: Query<(
-        Entity,
-        &mut BloodstreamPressure,
-        &BloodstreamContent,
-        &Depth,
-    )>,
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<(&mut BloodstreamPressure, &BloodstreamContent, &Depth)>,
     mut gases_to_circulate: EventReader<CirculateGas>,
-    mut outgassings: EventWriter<Outgassing>,
 ) {
     for gas_to_circulate in gases_to_circulate.read() {
-        if let Ok((breather_entity, mut bloodstream_pressure, bloodstream_content, depth)) =
+        if let Ok((mut bloodstream_pressure, bloodstream_content, depth)) =
             breathers.get_mut(gas_to_circulate.entity)
         {
-            let pressure_at_current_depth = ATMOSPHERIC_PRESSURE_BAR + BAR_MSW_RATIO * depth.0;
-            let new_bloodstream_pressure = weighted_average(
+            bloodstream_pressure.0 = weighted_average(
                 bloodstream_pressure.0,
                 bloodstream_content.amount_remaining,
-                pressure_at_current_depth,
+                environment.ambient_pressure(depth.0),
                 gas_to_circulate.amount,
             );
-            let delta = new_bloodstream_pressure - bloodstream_pressure.0;
-            if delta < 0. {
-                outgassings.send(Outgassing {
-                    entity: breather_entity,
-                    amount: -delta,
-                });
-            }
-            bloodstream_pressure.0 = new_bloodstream_pressure;
         }
     }
 }
@@ -76,7 +59,7 @@ pub fn equalize_pressure(
 fn did_equalize_pressure_absorption() {
     let mut app = App::new();
     app.add_event::<CirculateGas>();
-    app.add_event::<Outgassing>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, equalize_pressure);
     let breather_id = app
         .world_mut()
@@ -85,8 +68,11 @@ fn did_equalize_pressure_absorption() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 75.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
             },
             Depth(100.),
         ))
@@ -96,25 +82,23 @@ fn did_equalize_pressure_absorption() {
         .send(CirculateGas {
             entity: breather_id,
             amount: 25.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         });
     app.update();
     // should have equalized the pressure
     let new_bloodstream_pressure = app.world().get::<BloodstreamPressure>(breather_id).unwrap();
     assert_eq!(new_bloodstream_pressure.0, 2.75);
-    // should not have sent an outgassing event (gas should have been absorbed)
-    let outgassing_events = app.world().resource::<Events<Outgassing>>();
-    let mut outgassing_reader = outgassing_events.get_reader();
-    let outgassing = outgassing_reader.read(outgassing_events).next();
-    assert!(outgassing.is_none());
 }
 
 #[test]
-fn did_equalize_pressure_outgassing() {
+fn did_equalize_pressure_release() {
     let mut app = App::new();
     app.add_event::<CirculateGas>();
-    app.add_event::<Outgassing>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, equalize_pressure);
     let breather_id = app
         .world_mut()
@@ -123,8 +107,11 @@ fn did_equalize_pressure_outgassing() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 75.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
             },
             Depth(0.),
         ))
@@ -134,18 +121,60 @@ fn did_equalize_pressure_outgassing() {
         .send(CirculateGas {
             entity: breather_id,
             amount: 25.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         });
     app.update();
     let new_bloodstream_pressure = app.world().get::<BloodstreamPressure>(breather_id).unwrap();
     assert_eq!(new_bloodstream_pressure.0, 1.75);
-    // should have sent an outgassing event
-    let outgassing_events = app.world().resource::<Events<Outgassing>>();
-    let mut outgassing_reader = outgassing_events.get_reader();
-    let outgassing = outgassing_reader.read(outgassing_events).next().unwrap();
-    assert_eq!(outgassing.entity, breather_id);
-    assert_eq!(outgassing.amount, 0.25);
+}
+
+/// Gas keeps moving between the blood and the lungs whether or not a breath is taken, so the
+/// bloodstream follows ambient pressure on every tick.
+pub fn exchange_pressure(
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<(&mut BloodstreamPressure, &Depth)>,
+) {
+    for (mut bloodstream_pressure, depth) in &mut breathers {
+        let fraction = 1. - 0.5_f32.powf(time.delta_seconds() / BLOODSTREAM_HALF_TIME);
+        bloodstream_pressure.0 +=
+            (environment.ambient_pressure(depth.0) - bloodstream_pressure.0) * fraction;
+    }
+}
+
+#[test]
+fn did_exchange_pressure() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, exchange_pressure);
+    let descending_id = app
+        .world_mut()
+        .spawn((BloodstreamPressure(1.), Depth(10.)))
+        .id();
+    let ascending_id = app
+        .world_mut()
+        .spawn((BloodstreamPressure(3.), Depth(0.)))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs_f32(BLOODSTREAM_HALF_TIME));
+    app.update();
+    // no breaths taken, but still halfway to ambient
+    let descending = app
+        .world()
+        .get::<BloodstreamPressure>(descending_id)
+        .unwrap();
+    assert!((descending.0 - 1.5).abs() < 1e-5);
+    let ascending = app
+        .world()
+        .get::<BloodstreamPressure>(ascending_id)
+        .unwrap();
+    assert!((ascending.0 - 2.).abs() < 1e-5);
 }
 
 pub fn equalize_gases(
@@ -154,16 +183,24 @@ pub fn equalize_gases(
 ) {
     for gas_to_circulate in gases_to_circulate.read() {
         if let Ok(mut bloodstream_content) = breathers.get_mut(gas_to_circulate.entity) {
-            bloodstream_content.proportion_of_oxygen = weighted_average(
-                bloodstream_content.proportion_of_oxygen,
-                bloodstream_content.amount_remaining,
-                gas_to_circulate.proportion_of_oxygen,
+            let amount_remaining = bloodstream_content.amount_remaining;
+            let mix = &mut bloodstream_content.mix;
+            mix.oxygen = weighted_average(
+                mix.oxygen,
+                amount_remaining,
+                gas_to_circulate.mix.oxygen,
                 gas_to_circulate.amount,
             );
-            bloodstream_content.proportion_of_nitrogen = weighted_average(
-                bloodstream_content.proportion_of_nitrogen,
-                bloodstream_content.amount_remaining,
-                gas_to_circulate.proportion_of_nitrogen,
+            mix.nitrogen = weighted_average(
+                mix.nitrogen,
+                amount_remaining,
+                gas_to_circulate.mix.nitrogen,
+                gas_to_circulate.amount,
+            );
+            mix.helium = weighted_average(
+                mix.helium,
+                amount_remaining,
+                gas_to_circulate.mix.helium,
                 gas_to_circulate.amount,
             );
         }
@@ -180,8 +217,11 @@ fn did_equalize_gases() {
         .spawn(BloodstreamContent {
             capacity: 100.,
             amount_remaining: 50.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         })
         .id();
     app.world_mut()
@@ -189,11 +229,14 @@ fn did_equalize_gases() {
         .send(CirculateGas {
             entity: breather_id,
             amount: 50.,
-            proportion_of_oxygen: 0.5,
-            proportion_of_nitrogen: 0.5,
+            mix: GasMix {
+                oxygen: 0.5,
+                nitrogen: 0.5,
+                helium: 0.,
+            },
         });
     app.update();
     let new_bloodstream_content = app.world().get::<BloodstreamContent>(breather_id).unwrap();
-    assert_eq!(new_bloodstream_content.proportion_of_oxygen, 0.25);
-    assert_eq!(new_bloodstream_content.proportion_of_oxygen, 0.25);
+    assert_eq!(new_bloodstream_content.mix.oxygen, 0.25);
+    assert_eq!(new_bloodstream_content.mix.nitrogen, 0.25);
 }
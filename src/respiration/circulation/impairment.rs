@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+
+use crate::respiration::circulation::carbon_dioxide::CarbonDioxide;
+use crate::respiration::circulation::*;
+use crate::respiration::freediving::BLACKOUT_PO2;
+use crate::respiration::BloodstreamContent;
+use crate::states::RunningStateSet;
+
+// narcotic pressures where narcosis is first felt, around 20 metres on air, and where it is
+// complete, around 50 metres
+const NARCOSIS_ONSET: f32 = 0.79 * 3.;
+const NARCOSIS_FULL: f32 = 0.79 * 6.;
+// PO2 where hypoxia is first felt, it is complete at `BLACKOUT_PO2`
+const HYPOXIA_ONSET: f32 = 0.18;
+// proportion of the previous input kept each frame at full narcosis
+const INPUT_LAG: f32 = 0.9;
+// largest angle, in radians, inputs are thrown off by at full narcosis
+const MAX_JITTER: f32 = 0.5;
+// number of significant digits the HUD loses at full narcosis
+const BLUR_DIGITS: f32 = 2.;
+
+/// How impaired a breather is by narcosis and hypoxia, each from 0 (not at all) to 1 (fully).
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Impairment {
+    pub narcosis: f32,
+    pub hypoxia: f32,
+}
+
+impl Impairment {
+    /// Slowly turns `previous` towards `intended` instead of responding immediately.
+    pub fn lag(&self, previous: Vec3, intended: Vec3) -> Vec3 {
+        previous.lerp(intended, 1. - INPUT_LAG * self.narcosis)
+    }
+
+    /// Turns `direction` by up to `MAX_JITTER`, for `noise` between -1 and 1.
+    pub fn jitter(&self, direction: Vec3, noise: f32) -> Vec3 {
+        Quat::from_rotation_z(MAX_JITTER * self.narcosis * noise) * direction
+    }
+
+    /// Rounds `value` off more coarsely the more narcotic the breather is.
+    pub fn blur(&self, value: f32) -> f32 {
+        let step = 10_f32.powf((self.narcosis * BLUR_DIGITS).floor());
+        (value / step).round() * step
+    }
+}
+
+pub fn impairment_plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        update_impairment
+            .after(equalize_pressure)
+            .after(equalize_gases)
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Impairment>();
+}
+
+fn graded(value: f32, onset: f32, full: f32) -> f32 {
+    ((value - onset) / (full - onset)).clamp(0., 1.)
+}
+
+/// Impairment starts well before the pressures where narcosis and hypoxia do damage, so that
+/// the danger can be felt first.
+pub fn update_impairment(
+    mut breathers: Query<(
+        &mut Impairment,
+        &BloodstreamPressure,
+        &BloodstreamContent,
+        Option<&CarbonDioxide>,
+    )>,
+) {
+    for (mut impairment, bloodstream_pressure, bloodstream_content, carbon_dioxide) in
+        &mut breathers
+    {
+        let narcotic_pressure = narcotic_proportion(&bloodstream_content.mix)
+            * bloodstream_pressure.0
+            * carbon_dioxide.map_or(1., |carbon_dioxide| carbon_dioxide.narcosis_multiplier());
+        let oxygen_pressure = bloodstream_content.mix.oxygen * bloodstream_pressure.0;
+        impairment.narcosis = graded(narcotic_pressure, NARCOSIS_ONSET, NARCOSIS_FULL);
+        impairment.hypoxia = graded(-oxygen_pressure, -HYPOXIA_ONSET, -BLACKOUT_PO2);
+    }
+}
+
+#[test]
+fn did_update_impairment() {
+    let mut app = App::new();
+    app.add_systems(Update, update_impairment);
+    let shallow_id = app
+        .world_mut()
+        .spawn((
+            Impairment::default(),
+            BloodstreamPressure(1.5),
+            BloodstreamContent::default(),
+        ))
+        .id();
+    let deep_id = app
+        .world_mut()
+        .spawn((
+            Impairment::default(),
+            BloodstreamPressure(4.5),
+            BloodstreamContent::default(),
+        ))
+        .id();
+    let hypoxic_id = app
+        .world_mut()
+        .spawn((
+            Impairment::default(),
+            BloodstreamPressure(1.),
+            BloodstreamContent {
+                mix: GasMix {
+                    oxygen: 0.14,
+                    nitrogen: 0.79,
+                    helium: 0.,
+                },
+                ..default()
+            },
+        ))
+        .id();
+    app.update();
+    let shallow = app.world().get::<Impairment>(shallow_id).unwrap();
+    assert_eq!(shallow.narcosis, 0.);
+    assert_eq!(shallow.hypoxia, 0.);
+    // 35 metres on air is half way to full narcosis
+    let deep = app.world().get::<Impairment>(deep_id).unwrap();
+    assert!((deep.narcosis - 0.5).abs() < 1e-4);
+    let hypoxic = app.world().get::<Impairment>(hypoxic_id).unwrap();
+    assert_eq!(hypoxic.narcosis, 0.);
+    assert!((hypoxic.hypoxia - 0.5).abs() < 1e-4);
+}
+
+#[test]
+fn impaired_inputs() {
+    let sober = Impairment::default();
+    let narced = Impairment {
+        narcosis: 1.,
+        hypoxia: 0.,
+    };
+    let previous = Vec3::new(1., 0., 0.);
+    let intended = Vec3::new(0., 1., 0.);
+    assert_eq!(sober.lag(previous, intended), intended);
+    assert!(narced.lag(previous, intended).angle_between(previous) < 0.2);
+    assert_eq!(sober.jitter(intended, 1.), intended);
+    assert!((narced.jitter(intended, 1.).angle_between(intended) - MAX_JITTER).abs() < 1e-4);
+    assert_eq!(sober.blur(37.), 37.);
+    assert_eq!(narced.blur(37.), 0.);
+    assert_eq!(
+        Impairment {
+            narcosis: 0.5,
+            hypoxia: 0.
+        }
+        .blur(37.),
+        40.
+    );
+}
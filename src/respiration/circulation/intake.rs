@@ -31,8 +31,11 @@ fn did_intake_gas() {
         .spawn(BloodstreamContent {
             capacity: 100.,
             amount_remaining: 50.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         })
         .id();
     app.world_mut()
@@ -40,8 +43,11 @@ fn did_intake_gas() {
         .send(CirculateGas {
             entity: breather_id,
             amount: 50.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         });
     app.update();
     let new_bloodstream = app.world().get::<BloodstreamContent>(breather_id).unwrap();
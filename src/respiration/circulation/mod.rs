@@ -1,14 +1,19 @@
+use crate::respiration::gas_mix::GasMix;
 use bevy::prelude::*;
 
+pub mod carbon_dioxide;
 pub mod decompression;
 pub mod equalization;
+pub mod impairment;
 pub mod intake;
 pub mod nitrogen;
 pub mod oxygen;
 pub mod usage;
 
+use carbon_dioxide::*;
 use decompression::*;
 use equalization::*;
+use impairment::*;
 use intake::*;
 use nitrogen::*;
 use oxygen::*;
@@ -18,15 +23,16 @@ use usage::*;
 pub struct CirculateGas {
     pub entity: Entity,
     pub amount: f32,
-    pub proportion_of_oxygen: f32,
-    pub proportion_of_nitrogen: f32,
+    pub mix: GasMix,
 }
 
 pub fn circulation_plugin(app: &mut App) {
     app.add_event::<CirculateGas>();
     app.add_plugins((
+        carbon_dioxide_plugin,
         decompression_plugin,
         equalization_plugin,
+        impairment_plugin,
         intake_plugin,
         oxygen_plugin,
         nitrogen_plugin,
@@ -1,11 +1,17 @@
 use bevy::prelude::*;
 
+use crate::respiration::circulation::carbon_dioxide::CarbonDioxide;
 use crate::respiration::circulation::BloodstreamPressure;
+use crate::respiration::gas_mix::GasMix;
 use crate::respiration::BloodstreamContent;
 use crate::states::RunningStateSet;
 use crate::DamageEvent;
 
-const PN2_NARCOSIS_THRESHOLD: f32 = 0.78 * (30. / 10. + 1.);
+// nitrogen partial pressure, in bar, of air at 30 metres of seawater, where narcosis sets in.
+// It's the partial pressure that matters, so it holds at any dive site.
+const PN2_NARCOSIS_THRESHOLD: f32 = 3.16;
+// relative to nitrogen, from the lipid solubility of each gas
+const HELIUM_NARCOTIC_POTENCY: f32 = 0.23;
 
 #[derive(Component, Reflect)]
 #[reflect(Component)]
@@ -28,29 +34,40 @@ pub fn nitrogen_plugin(app: &mut App) {
     app.register_type::<NitrogenHazard>();
 }
 
+/// Proportion of nitrogen that would be as narcotic as the whole mix.
+pub fn narcotic_proportion(mix: &GasMix) -> f32 {
+    mix.nitrogen + HELIUM_NARCOTIC_POTENCY * mix.helium
+}
+
 pub fn nitrogen_narcosis(
     breathers: Query<(
         Entity,
         &NitrogenHazard,
         &BloodstreamPressure,
         &BloodstreamContent,
+        Option<&CarbonDioxide>,
     )>,
     mut damage_events: EventWriter<DamageEvent>,
 ) {
-    for (entity, nitrogen_hazard, bloodstream_pressure, bloodstream_content) in &breathers {
-        let pressure_from_nitrogen =
-            bloodstream_content.proportion_of_nitrogen * bloodstream_pressure.0;
-        if pressure_from_nitrogen > nitrogen_hazard.n2_upper {
+    for (entity, nitrogen_hazard, bloodstream_pressure, bloodstream_content, carbon_dioxide) in
+        &breathers
+    {
+        // carbon dioxide retention makes the same mix more narcotic
+        let narcotic_pressure = narcotic_proportion(&bloodstream_content.mix)
+            * bloodstream_pressure.0
+            * carbon_dioxide.map_or(1., |carbon_dioxide| carbon_dioxide.narcosis_multiplier());
+        if narcotic_pressure > nitrogen_hazard.n2_upper {
             println!(
-                "proportion of nitrogen: {}, pressure: {}, damage: {}",
-                bloodstream_content.proportion_of_nitrogen,
+                "proportion of nitrogen: {}, proportion of helium: {}, pressure: {}, damage: {}",
+                bloodstream_content.mix.nitrogen,
+                bloodstream_content.mix.helium,
                 bloodstream_pressure.0,
                 nitrogen_hazard.damage_factor
             );
             damage_events.send(DamageEvent {
                 target: entity,
                 damage: nitrogen_hazard.damage_factor
-                    * (pressure_from_nitrogen - nitrogen_hazard.n2_upper),
+                    * (narcotic_pressure - nitrogen_hazard.n2_upper),
             });
         }
     }
@@ -72,8 +89,11 @@ fn did_narcosis_damage() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 100.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 1.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 1.,
+                    helium: 0.,
+                },
             },
         ))
         .id();
@@ -99,8 +119,40 @@ fn did_no_narcosis_damage() {
         BloodstreamContent {
             capacity: 100.,
             amount_remaining: 100.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 1.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 1.,
+                helium: 0.,
+            },
+        },
+    ));
+    app.update();
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damage = damage_reader.read(damage_events).next();
+    assert!(damage.is_none());
+}
+
+#[test]
+fn helium_less_narcotic() {
+    let mut app = App::new();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, nitrogen_narcosis);
+    // the same pressure of nitrogen would be narcotic
+    app.world_mut().spawn((
+        NitrogenHazard {
+            n2_upper: 2.,
+            damage_factor: 1.,
+        },
+        BloodstreamPressure(3.),
+        BloodstreamContent {
+            capacity: 100.,
+            amount_remaining: 100.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 0.,
+                helium: 1.,
+            },
         },
     ));
     app.update();
@@ -109,3 +161,37 @@ fn did_no_narcosis_damage() {
     let damage = damage_reader.read(damage_events).next();
     assert!(damage.is_none());
 }
+
+#[test]
+fn carbon_dioxide_more_narcotic() {
+    let mut app = App::new();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, nitrogen_narcosis);
+    // harmless on its own, but retained carbon dioxide should tip it over
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            NitrogenHazard {
+                n2_upper: 2.,
+                damage_factor: 1.,
+            },
+            BloodstreamPressure(2.),
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 100.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 1.,
+                    helium: 0.,
+                },
+            },
+            CarbonDioxide(70.),
+        ))
+        .id();
+    app.update();
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damage = damage_reader.read(damage_events).next().unwrap();
+    assert_eq!(damage.target, breather_id);
+    assert!(damage.damage > 0.);
+}
@@ -1,13 +1,44 @@
 use bevy::prelude::*;
+#[cfg(test)]
+use rand::rngs::StdRng;
+use rand::Rng;
+#[cfg(test)]
+use rand::SeedableRng;
 
-use crate::respiration::circulation::BloodstreamPressure;
+use crate::position::Depth;
+use crate::respiration::circulation::*;
 use crate::respiration::BloodstreamContent;
+use crate::respiration::HazardRng;
 use crate::states::RunningStateSet;
 use crate::DamageEvent;
 
 const MAX_PO2_HUMAN: f32 = 1.4;
 const MIN_PO2_HUMAN: f32 = 0.16;
 
+// below this PO2 neither clock accumulates, and the CNS clock recovers at the surface
+const EXPOSURE_PO2_THRESHOLD: f32 = 0.5;
+const CNS_RECOVERY_HALF_TIME_MIN: f32 = 90.;
+// NOAA single exposure limits, in minutes, for each PO2
+const NOAA_CNS_LIMITS: [(f32, f32); 11] = [
+    (0.6, 720.),
+    (0.7, 570.),
+    (0.8, 450.),
+    (0.9, 360.),
+    (1.0, 300.),
+    (1.1, 240.),
+    (1.2, 210.),
+    (1.3, 180.),
+    (1.4, 150.),
+    (1.5, 120.),
+    (1.6, 45.),
+];
+const MIN_CNS_LIMIT_MIN: f32 = 1.;
+const OTU_EXPONENT: f32 = 0.83;
+// convulsions per minute for each 100% of CNS clock above the limit
+const CONVULSION_RATE: f32 = 0.1;
+const CONVULSION_DAMAGE: f32 = 20.;
+const CONVULSION_SECONDS: f32 = 10.;
+
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct OxygenHazard {
@@ -26,9 +57,50 @@ impl Default for OxygenHazard {
     }
 }
 
+impl OxygenHazard {
+    /// A breather that can go down to `po2_lower` before hypoxia harms them.
+    pub fn tolerating(po2_lower: f32, damage_factor: f32) -> Self {
+        Self {
+            po2_lower,
+            damage_factor,
+            ..default()
+        }
+    }
+}
+
+/// Time-integrated oxygen exposure. `cns` is the fraction of the NOAA limit used up (1 is
+/// 100%), `otu` is the pulmonary dose in oxygen tolerance units.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct OxygenExposure {
+    pub cns: f32,
+    pub otu: f32,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Convulsing(pub Timer);
+
+#[derive(Event)]
+pub struct OxygenConvulsion {
+    pub entity: Entity,
+}
+
 pub fn oxygen_plugin(app: &mut App) {
-    app.add_systems(FixedUpdate, oxygen_damage.in_set(RunningStateSet));
+    app.add_event::<OxygenConvulsion>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            oxygen_damage,
+            oxygen_exposure,
+            oxygen_convulsion.after(oxygen_exposure),
+            recover_from_convulsion,
+        )
+            .in_set(RunningStateSet),
+    );
     app.register_type::<OxygenHazard>();
+    app.register_type::<OxygenExposure>();
+    app.register_type::<Convulsing>();
 }
 
 pub fn oxygen_damage(
@@ -41,14 +113,11 @@ pub fn oxygen_damage(
     mut damage_events: EventWriter<DamageEvent>,
 ) {
     for (entity, toxicity, bloodstream_pressure, bloodstream_content) in &breathers {
-        let pressure_from_oxygen =
-            bloodstream_content.proportion_of_oxygen * bloodstream_pressure.0;
+        let pressure_from_oxygen = bloodstream_content.mix.oxygen * bloodstream_pressure.0;
         if pressure_from_oxygen > toxicity.po2_upper {
             println!(
-                "proportion_of_oxygen: {}, bloodstream_pressure: {}, toxicity damage: {}",
-                bloodstream_content.proportion_of_oxygen,
-                bloodstream_pressure.0,
-                toxicity.damage_factor
+                "proportion of oxygen: {}, bloodstream_pressure: {}, toxicity damage: {}",
+                bloodstream_content.mix.oxygen, bloodstream_pressure.0, toxicity.damage_factor
             );
             damage_events.send(DamageEvent {
                 target: entity,
@@ -56,10 +125,8 @@ pub fn oxygen_damage(
             });
         } else if pressure_from_oxygen < toxicity.po2_lower {
             println!(
-                "proportion_of_oxygen: {}, bloodstream_pressure: {}, hypoxia damage: {}",
-                bloodstream_content.proportion_of_oxygen,
-                bloodstream_pressure.0,
-                toxicity.damage_factor
+                "proportion of oxygen: {}, bloodstream_pressure: {}, hypoxia damage: {}",
+                bloodstream_content.mix.oxygen, bloodstream_pressure.0, toxicity.damage_factor
             );
             damage_events.send(DamageEvent {
                 target: entity,
@@ -86,8 +153,11 @@ fn did_toxicity_damage() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 100.,
-                proportion_of_oxygen: 1.,
-                proportion_of_nitrogen: 0.,
+                mix: GasMix {
+                    oxygen: 1.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
             },
         ))
         .id();
@@ -114,8 +184,11 @@ fn did_no_oxygen_damage() {
         BloodstreamContent {
             capacity: 100.,
             amount_remaining: 100.,
-            proportion_of_oxygen: 1.,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 1.,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         },
     ));
     app.update();
@@ -142,8 +215,11 @@ fn did_hypoxia_damage() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 100.,
-                proportion_of_oxygen: 1.,
-                proportion_of_nitrogen: 0.,
+                mix: GasMix {
+                    oxygen: 1.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
             },
         ))
         .id();
@@ -154,3 +230,229 @@ fn did_hypoxia_damage() {
     assert_eq!(damage.target, breather_id);
     assert_eq!(damage.damage, 1.);
 }
+
+/// Minutes of exposure to the given PO2 that use up the whole CNS clock.
+pub fn cns_limit(po2: f32) -> Option<f32> {
+    if po2 <= EXPOSURE_PO2_THRESHOLD {
+        return None;
+    }
+    let (first_po2, first_limit) = NOAA_CNS_LIMITS[0];
+    if po2 <= first_po2 {
+        return Some(first_limit);
+    }
+    let segment = NOAA_CNS_LIMITS
+        .windows(2)
+        .find(|segment| po2 <= segment[1].0)
+        .unwrap_or(&NOAA_CNS_LIMITS[NOAA_CNS_LIMITS.len() - 2..]);
+    let ((low_po2, low_limit), (high_po2, high_limit)) = (segment[0], segment[1]);
+    let limit = low_limit + (po2 - low_po2) / (high_po2 - low_po2) * (high_limit - low_limit);
+    Some(limit.max(MIN_CNS_LIMIT_MIN))
+}
+
+#[test]
+fn noaa_cns_limits() {
+    assert_eq!(cns_limit(0.4), None);
+    assert_eq!(cns_limit(0.55), Some(720.));
+    assert_eq!(cns_limit(1.4), Some(150.));
+    assert!((cns_limit(1.45).unwrap() - 135.).abs() < 1e-3);
+    // past the table the limit keeps shrinking
+    assert!(cns_limit(1.65).unwrap() < 45.);
+    assert_eq!(cns_limit(3.), Some(MIN_CNS_LIMIT_MIN));
+}
+
+pub fn oxygen_exposure(
+    time: Res<Time>,
+    mut breathers: Query<(
+        &mut OxygenExposure,
+        &BloodstreamPressure,
+        &BloodstreamContent,
+        &Depth,
+    )>,
+) {
+    let minutes = time.delta_seconds() / 60.;
+    for (mut exposure, bloodstream_pressure, bloodstream_content, depth) in &mut breathers {
+        let pressure_from_oxygen = bloodstream_content.mix.oxygen * bloodstream_pressure.0;
+        if let Some(limit) = cns_limit(pressure_from_oxygen) {
+            exposure.cns += minutes / limit;
+            exposure.otu += minutes
+                * ((pressure_from_oxygen - EXPOSURE_PO2_THRESHOLD) / EXPOSURE_PO2_THRESHOLD)
+                    .powf(OTU_EXPONENT);
+        } else if depth.0 <= 0. {
+            exposure.cns *= 0.5_f32.powf(minutes / CNS_RECOVERY_HALF_TIME_MIN);
+        }
+    }
+}
+
+#[test]
+fn did_accumulate_exposure() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, oxygen_exposure);
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            OxygenExposure::default(),
+            BloodstreamPressure(2.8),
+            Depth(18.),
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 100.,
+                mix: GasMix {
+                    oxygen: 0.5,
+                    nitrogen: 0.5,
+                    helium: 0.,
+                },
+            },
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(15 * 60));
+    app.update();
+    let exposure = app.world().get::<OxygenExposure>(breather_id).unwrap();
+    // 15 minutes of a 150 minute limit
+    assert!((exposure.cns - 0.1).abs() < 1e-4);
+    assert!((exposure.otu - 15. * 1.8_f32.powf(OTU_EXPONENT)).abs() < 1e-3);
+}
+
+#[test]
+fn did_recover_exposure() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, oxygen_exposure);
+    let surface_id = app
+        .world_mut()
+        .spawn((
+            OxygenExposure {
+                cns: 0.8,
+                otu: 100.,
+            },
+            BloodstreamPressure(1.),
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 100.,
+                mix: GasMix::AIR,
+            },
+            Depth(0.),
+        ))
+        .id();
+    let submerged_id = app
+        .world_mut()
+        .spawn((
+            OxygenExposure {
+                cns: 0.8,
+                otu: 100.,
+            },
+            BloodstreamPressure(2.),
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 100.,
+                mix: GasMix::AIR,
+            },
+            Depth(10.),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(90 * 60));
+    app.update();
+    let surface = app.world().get::<OxygenExposure>(surface_id).unwrap();
+    assert!((surface.cns - 0.4).abs() < 1e-4);
+    assert_eq!(surface.otu, 100.);
+    // breathing air at depth stops the clock without winding it back
+    let submerged = app.world().get::<OxygenExposure>(submerged_id).unwrap();
+    assert_eq!(submerged.cns, 0.8);
+}
+
+/// Chance of at least one convulsion in the given number of minutes.
+pub fn convulsion_chance(cns: f32, minutes: f32) -> f32 {
+    if cns <= 1. {
+        0.
+    } else {
+        1. - (-CONVULSION_RATE * (cns - 1.) * minutes).exp()
+    }
+}
+
+pub fn oxygen_convulsion(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rng: ResMut<HazardRng>,
+    breathers: Query<(Entity, &OxygenExposure), Without<Convulsing>>,
+    mut convulsions: EventWriter<OxygenConvulsion>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, exposure) in &breathers {
+        if rng.0.gen::<f32>() < convulsion_chance(exposure.cns, time.delta_seconds() / 60.) {
+            println!("oxygen convulsion, cns: {}", exposure.cns);
+            commands
+                .entity(entity)
+                .insert(Convulsing(Timer::from_seconds(
+                    CONVULSION_SECONDS,
+                    TimerMode::Once,
+                )));
+            convulsions.send(OxygenConvulsion { entity });
+            damage_events.send(DamageEvent {
+                target: entity,
+                damage: CONVULSION_DAMAGE,
+            });
+        }
+    }
+}
+
+#[test]
+fn did_convulse() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.insert_resource(HazardRng(StdRng::seed_from_u64(0)));
+    app.add_event::<OxygenConvulsion>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, oxygen_convulsion);
+    let breather_id = app
+        .world_mut()
+        .spawn(OxygenExposure { cns: 3., otu: 0. })
+        .id();
+    // long enough for a convulsion to be all but certain
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60 * 60));
+    app.update();
+    assert!(app.world().get::<Convulsing>(breather_id).is_some());
+    let convulsion_events = app.world().resource::<Events<OxygenConvulsion>>();
+    let mut convulsion_reader = convulsion_events.get_reader();
+    let convulsion = convulsion_reader.read(convulsion_events).next().unwrap();
+    assert_eq!(convulsion.entity, breather_id);
+}
+
+#[test]
+fn did_not_convulse() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.insert_resource(HazardRng(StdRng::seed_from_u64(0)));
+    app.add_event::<OxygenConvulsion>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, oxygen_convulsion);
+    let breather_id = app
+        .world_mut()
+        .spawn(OxygenExposure { cns: 0.9, otu: 0. })
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60 * 60));
+    app.update();
+    assert!(app.world().get::<Convulsing>(breather_id).is_none());
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    assert!(damage_events.is_empty());
+}
+
+pub fn recover_from_convulsion(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut convulsing: Query<(Entity, &mut Convulsing)>,
+) {
+    for (entity, mut convulsion) in &mut convulsing {
+        convulsion.0.tick(time.delta());
+        if convulsion.0.finished() {
+            commands.entity(entity).remove::<Convulsing>();
+        }
+    }
+}
@@ -1,10 +1,18 @@
-use crate::respiration::circulation::intake::*;
+use bevy::prelude::*;
+
+use crate::diver::Swimming;
+use crate::position::Velocity;
+use crate::respiration::circulation::*;
 use crate::respiration::BloodstreamContent;
 use crate::states::RunningStateSet;
-use bevy::prelude::*;
 
-const DEFAULT_USAGE_RATE: f32 = 1. / 64.;
+// litres per minute
+const DEFAULT_USAGE_RATE: f32 = 20.;
+// extra breathing demand for each unit of swimming and velocity
+const EXERTION_DEMAND: f32 = 1.;
 
+/// Surface air consumption in litres per minute at rest. Breathing at depth uses the same
+/// volume of denser gas, so the cylinder empties faster by the ambient pressure.
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct GasUsageRate(pub f32);
@@ -15,28 +23,93 @@ impl Default for GasUsageRate {
     }
 }
 
+/// Multiplier on `GasUsageRate`. It is reset to 1 every tick, and each physiological effect
+/// that raises the breathing rate multiplies it before `usage` runs.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct BreathingDemand(pub f32);
+
+impl Default for BreathingDemand {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
 pub fn usage_plugin(app: &mut App) {
     app.add_systems(
         FixedUpdate,
         (
-            usage.after(intake_gas),
+            reset_breathing_demand,
+            exertion_demand.after(reset_breathing_demand).before(usage),
+            usage.after(intake_gas).after(reset_breathing_demand),
             update_proportions_on_exhaust.after(usage),
         )
             .in_set(RunningStateSet),
     );
     app.register_type::<GasUsageRate>();
+    app.register_type::<BreathingDemand>();
+}
+
+pub fn reset_breathing_demand(mut breathing_demands: Query<&mut BreathingDemand>) {
+    for mut breathing_demand in &mut breathing_demands {
+        breathing_demand.0 = 1.;
+    }
+}
+
+/// How hard a breather is working, from how fast they are swimming and moving.
+pub fn exertion(swimming: Option<&Swimming>, velocity: Option<&Velocity>) -> f32 {
+    swimming.map_or(0., |swimming| swimming.0.length())
+        + velocity.map_or(0., |velocity| velocity.0.length())
+}
+
+pub fn exertion_demand(
+    mut breathers: Query<(&mut BreathingDemand, Option<&Swimming>, Option<&Velocity>)>,
+) {
+    for (mut breathing_demand, swimming, velocity) in &mut breathers {
+        breathing_demand.0 *= 1. + EXERTION_DEMAND * exertion(swimming, velocity);
+    }
+}
+
+#[test]
+fn did_exertion_demand() {
+    let mut app = App::new();
+    app.add_systems(Update, exertion_demand);
+    let resting_id = app.world_mut().spawn(BreathingDemand::default()).id();
+    let swimming_id = app
+        .world_mut()
+        .spawn((
+            BreathingDemand::default(),
+            Swimming(Vec3::new(0.5, 0., 0.)),
+            Velocity(Vec3::new(0., 0.5, 0.)),
+        ))
+        .id();
+    app.update();
+    let resting = app.world().get::<BreathingDemand>(resting_id).unwrap();
+    assert_eq!(resting.0, 1.);
+    let swimming = app.world().get::<BreathingDemand>(swimming_id).unwrap();
+    assert_eq!(swimming.0, 1. + EXERTION_DEMAND);
 }
 
-pub fn usage(mut gas_users: Query<(&GasUsageRate, &mut BloodstreamContent)>) {
-    for (gas_usage_rate, mut bloodstream_content) in &mut gas_users {
+pub fn usage(
+    time: Res<Time>,
+    mut gas_users: Query<(
+        &GasUsageRate,
+        &mut BloodstreamContent,
+        Option<&BreathingDemand>,
+    )>,
+) {
+    for (gas_usage_rate, mut bloodstream_content, breathing_demand) in &mut gas_users {
+        let demand = breathing_demand.map_or(1., |breathing_demand| breathing_demand.0);
+        let used = gas_usage_rate.0 * demand * time.delta_seconds() / 60.;
         bloodstream_content.amount_remaining =
-            (bloodstream_content.amount_remaining - gas_usage_rate.0).max(0.);
+            (bloodstream_content.amount_remaining - used).max(0.);
     }
 }
 
 #[test]
 fn did_use() {
     let mut app = App::new();
+    app.init_resource::<Time>();
     app.add_systems(Update, usage);
     let gas_user_id = app
         .world_mut()
@@ -45,11 +118,42 @@ fn did_use() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 100.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
             },
         ))
         .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60));
+    app.update();
+    let new_bloodstream_content = app.world().get::<BloodstreamContent>(gas_user_id).unwrap();
+    assert_eq!(new_bloodstream_content.amount_remaining, 50.);
+}
+
+#[test]
+fn did_use_with_demand() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, usage);
+    let gas_user_id = app
+        .world_mut()
+        .spawn((
+            GasUsageRate(25.),
+            BreathingDemand(2.),
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 100.,
+                ..default()
+            },
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60));
     app.update();
     let new_bloodstream_content = app.world().get::<BloodstreamContent>(gas_user_id).unwrap();
     assert_eq!(new_bloodstream_content.amount_remaining, 50.);
@@ -58,6 +162,7 @@ fn did_use() {
 #[test]
 fn did_use_exhaust() {
     let mut app = App::new();
+    app.init_resource::<Time>();
     app.add_systems(Update, usage);
     let gas_user_id = app
         .world_mut()
@@ -66,11 +171,17 @@ fn did_use_exhaust() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 25.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
             },
         ))
         .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60));
     app.update();
     let new_bloodstream_content = app.world().get::<BloodstreamContent>(gas_user_id).unwrap();
     assert_eq!(new_bloodstream_content.amount_remaining, 0.);
@@ -79,7 +190,7 @@ fn did_use_exhaust() {
 pub fn update_proportions_on_exhaust(mut bloodstream_contents: Query<&mut BloodstreamContent>) {
     for mut bloodstream_content in &mut bloodstream_contents {
         if bloodstream_content.amount_remaining <= 0. {
-            bloodstream_content.proportion_of_oxygen = 0.;
+            bloodstream_content.mix.oxygen = 0.;
         }
     }
 }
@@ -93,13 +204,16 @@ fn did_update_proportions_on_exhaust() {
         .spawn((BloodstreamContent {
             capacity: 100.,
             amount_remaining: 0.,
-            proportion_of_oxygen: 0.5,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.5,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         },))
         .id();
     app.update();
     let new_bloodstream_content = app.world().get::<BloodstreamContent>(gas_user_id).unwrap();
-    assert_eq!(new_bloodstream_content.proportion_of_oxygen, 0.);
+    assert_eq!(new_bloodstream_content.mix.oxygen, 0.);
 }
 #[test]
 fn did_not_update_proportions_on_non_exhaust() {
@@ -110,11 +224,14 @@ fn did_not_update_proportions_on_non_exhaust() {
         .spawn((BloodstreamContent {
             capacity: 100.,
             amount_remaining: 25.,
-            proportion_of_oxygen: 0.5,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.5,
+                nitrogen: 0.,
+                helium: 0.,
+            },
         },))
         .id();
     app.update();
     let new_bloodstream_content = app.world().get::<BloodstreamContent>(gas_user_id).unwrap();
-    assert_eq!(new_bloodstream_content.proportion_of_oxygen, 0.5);
+    assert_eq!(new_bloodstream_content.mix.oxygen, 0.5);
 }
@@ -0,0 +1,254 @@
+use crate::environment::*;
+use crate::health::DamageEvent;
+use crate::position::*;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+
+// pressure difference across the eardrum, in bar, that starts to hurt and that ruptures it
+const EAR_PAIN_THRESHOLD: f32 = 0.1;
+const EARDRUM_RUPTURE_THRESHOLD: f32 = 0.4;
+const EARDRUM_RUPTURE_DAMAGE: f32 = 10.;
+// damage per second per bar of difference while in pain
+const EAR_PAIN_DAMAGE: f32 = 5.;
+// proportion of descent speed left while in pain
+const EAR_PAIN_DESCENT_FACTOR: f32 = 0.3;
+const VERTIGO_SECONDS: f32 = 30.;
+// largest angle, in radians, that vertigo turns the swimming direction by
+const VERTIGO_ANGLE: f32 = 1.;
+// radians per second the disturbance sways back and forth at
+const VERTIGO_SWAY: f32 = 2.;
+
+/// Pressure of the air space in the middle ear, in bar. It vents by itself on ascent but has to
+/// be equalized on descent.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct MiddleEar {
+    pub pressure: f32,
+    pub ruptured: bool,
+}
+
+impl Default for MiddleEar {
+    /// Equalized at sea level, venting by itself on the way up to a higher dive site.
+    fn default() -> Self {
+        Self {
+            pressure: STANDARD_PRESSURE,
+            ruptured: false,
+        }
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct EarPain;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Vertigo(pub Timer);
+
+impl Vertigo {
+    /// Turns the intended swimming direction by a swaying angle.
+    pub fn disturb(&self, swimming: Vec3) -> Vec3 {
+        let angle = VERTIGO_ANGLE * (VERTIGO_SWAY * self.0.elapsed_secs()).cos();
+        Quat::from_rotation_z(angle) * swimming
+    }
+}
+
+/// Slows any downward part of the swimming direction.
+pub fn slow_descent(swimming: Vec3) -> Vec3 {
+    if swimming.y < 0. {
+        Vec3::new(swimming.x, swimming.y * EAR_PAIN_DESCENT_FACTOR, swimming.z)
+    } else {
+        swimming
+    }
+}
+
+#[derive(Event)]
+pub struct Valsalva {
+    pub entity: Entity,
+}
+
+pub fn ear_plugin(app: &mut App) {
+    app.add_event::<Valsalva>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            equalize_ears,
+            ear_barotrauma.after(equalize_ears).after(update_depth),
+            recover_from_vertigo,
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<MiddleEar>();
+    app.register_type::<EarPain>();
+    app.register_type::<Vertigo>();
+}
+
+pub fn equalize_ears(
+    environment: Res<DiveSiteEnvironment>,
+    mut ears: Query<(&mut MiddleEar, &Depth)>,
+    mut valsalvas: EventReader<Valsalva>,
+) {
+    for valsalva in valsalvas.read() {
+        if let Ok((mut middle_ear, depth)) = ears.get_mut(valsalva.entity) {
+            middle_ear.pressure = environment.ambient_pressure(depth.0);
+        }
+    }
+}
+
+#[test]
+fn did_equalize_ears() {
+    let mut app = App::new();
+    app.add_event::<Valsalva>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, equalize_ears);
+    let diver_id = app
+        .world_mut()
+        .spawn((MiddleEar::default(), Depth(10.)))
+        .id();
+    app.world_mut()
+        .resource_mut::<Events<Valsalva>>()
+        .send(Valsalva { entity: diver_id });
+    app.update();
+    let middle_ear = app.world().get::<MiddleEar>(diver_id).unwrap();
+    assert_eq!(middle_ear.pressure, 2.);
+}
+
+pub fn ear_barotrauma(
+    mut commands: Commands,
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    mut ears: Query<(Entity, &mut MiddleEar, &Depth, Has<EarPain>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, mut middle_ear, depth, in_pain) in &mut ears {
+        let pressure = environment.ambient_pressure(depth.0);
+        // a ruptured eardrum lets water in, and air escapes through the eustachian tubes
+        if middle_ear.ruptured || middle_ear.pressure > pressure {
+            middle_ear.pressure = pressure;
+        }
+        let difference = pressure - middle_ear.pressure;
+        if difference > EARDRUM_RUPTURE_THRESHOLD {
+            println!("eardrum ruptured, pressure difference: {}", difference);
+            middle_ear.ruptured = true;
+            middle_ear.pressure = pressure;
+            commands
+                .entity(entity)
+                .remove::<EarPain>()
+                .insert(Vertigo(Timer::from_seconds(
+                    VERTIGO_SECONDS,
+                    TimerMode::Once,
+                )));
+            damage_events.send(DamageEvent {
+                target: entity,
+                damage: EARDRUM_RUPTURE_DAMAGE,
+            });
+        } else if difference > EAR_PAIN_THRESHOLD {
+            if !in_pain {
+                commands.entity(entity).insert(EarPain);
+            }
+            damage_events.send(DamageEvent {
+                target: entity,
+                damage: EAR_PAIN_DAMAGE * difference * time.delta_seconds(),
+            });
+        } else if in_pain {
+            commands.entity(entity).remove::<EarPain>();
+        }
+    }
+}
+
+#[test]
+fn did_ear_pain() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, ear_barotrauma);
+    let diver_id = app
+        .world_mut()
+        .spawn((MiddleEar::default(), Depth(2.)))
+        .id();
+    app.update();
+    assert!(app.world().get::<EarPain>(diver_id).is_some());
+    assert!(app.world().get::<Vertigo>(diver_id).is_none());
+    // equalizing should relieve it
+    app.world_mut()
+        .get_mut::<MiddleEar>(diver_id)
+        .unwrap()
+        .pressure = DiveSiteEnvironment::SEA.ambient_pressure(2.);
+    app.update();
+    assert!(app.world().get::<EarPain>(diver_id).is_none());
+}
+
+#[test]
+fn did_rupture_eardrum() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, ear_barotrauma);
+    let diver_id = app
+        .world_mut()
+        .spawn((MiddleEar::default(), Depth(5.)))
+        .id();
+    app.update();
+    let middle_ear = app.world().get::<MiddleEar>(diver_id).unwrap();
+    assert!(middle_ear.ruptured);
+    assert!(app.world().get::<Vertigo>(diver_id).is_some());
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damage = damage_reader.read(damage_events).next().unwrap();
+    assert_eq!(damage.damage, EARDRUM_RUPTURE_DAMAGE);
+}
+
+#[test]
+fn did_vent_on_ascent() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, ear_barotrauma);
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            MiddleEar {
+                pressure: DiveSiteEnvironment::SEA.ambient_pressure(20.),
+                ruptured: false,
+            },
+            Depth(10.),
+        ))
+        .id();
+    app.update();
+    let middle_ear = app.world().get::<MiddleEar>(diver_id).unwrap();
+    assert_eq!(
+        middle_ear.pressure,
+        DiveSiteEnvironment::SEA.ambient_pressure(10.)
+    );
+    assert!(app.world().get::<EarPain>(diver_id).is_none());
+}
+
+pub fn recover_from_vertigo(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dizzy: Query<(Entity, &mut Vertigo)>,
+) {
+    for (entity, mut vertigo) in &mut dizzy {
+        vertigo.0.tick(time.delta());
+        if vertigo.0.finished() {
+            commands.entity(entity).remove::<Vertigo>();
+        }
+    }
+}
+
+#[test]
+fn vertigo_disturbs_swimming() {
+    let vertigo = Vertigo(Timer::from_seconds(VERTIGO_SECONDS, TimerMode::Once));
+    let swimming = Vec3::new(1., 0., 0.);
+    let disturbed = vertigo.disturb(swimming);
+    assert!((disturbed.length() - 1.).abs() < 1e-6);
+    assert!((disturbed.angle_between(swimming) - VERTIGO_ANGLE).abs() < 1e-4);
+    assert_eq!(
+        slow_descent(Vec3::new(0., -1., 0.)).y,
+        -EAR_PAIN_DESCENT_FACTOR
+    );
+    assert_eq!(slow_descent(Vec3::new(0., 1., 0.)).y, 1.);
+}
@@ -0,0 +1,472 @@
+use crate::bag::Bag;
+use crate::diver::Diver;
+use crate::environment::DiveSiteEnvironment;
+use crate::respiration::gas_mix::*;
+use crate::respiration::inhalation::*;
+use crate::states::*;
+use crate::wallet::Wallet;
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+
+const FILL_STATION_WIDTH: f32 = 8.;
+const FILL_STATION_HEIGHT: f32 = 6.;
+// how close, in pixels, the diver has to be to use the fill station
+const FILL_STATION_RANGE: f32 = 20.;
+// price of each surface litre of gas
+const AIR_PRICE: f32 = 0.001;
+const OXYGEN_PRICE: f32 = 0.01;
+const HELIUM_PRICE: f32 = 0.05;
+// blends richer than this have a MOD too shallow to dive on, and leaner ones can't keep the
+// diver conscious
+const MAX_BLEND_OXYGEN: f32 = 0.8;
+const MIN_BLEND_OXYGEN: f32 = 0.1;
+// change in each fraction for each key press
+const BLEND_STEP: f32 = 0.01;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct FillStation;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct FillStationMenu;
+
+/// The blend being made up at the fill station, and which of the diver's cylinders it goes into.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct BlendOrder {
+    pub oxygen: f32,
+    pub helium: f32,
+    pub selected: usize,
+}
+
+impl Default for BlendOrder {
+    fn default() -> Self {
+        Self {
+            oxygen: GasMix::AIR.oxygen,
+            helium: 0.,
+            selected: 0,
+        }
+    }
+}
+
+impl BlendOrder {
+    pub fn mix(&self) -> Result<GasMix, GasMixError> {
+        GasMix::trimix(self.oxygen, self.helium)
+    }
+}
+
+/// Partial pressures, in bar, of each step of filling a cylinder: bleeding off what can't be
+/// kept, then adding helium, oxygen, and topping up with air.
+#[derive(Debug, PartialEq)]
+pub struct Blend {
+    pub bleed: f32,
+    pub helium: f32,
+    pub oxygen: f32,
+    pub air: f32,
+}
+
+impl Blend {
+    /// Price of the gas added to a cylinder of `water_volume` litres.
+    pub fn cost(&self, water_volume: f32) -> f32 {
+        water_volume
+            * (self.helium * HELIUM_PRICE + self.oxygen * OXYGEN_PRICE + self.air * AIR_PRICE)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BlendError {
+    TooMuchOxygen,
+    Hypoxic,
+    Unreachable,
+}
+
+/// Works out how to fill `cylinder` to its working pressure with `target` by partial pressure
+/// blending. What's already in it is kept where possible, so a part used cylinder is topped up.
+pub fn plan_blend(cylinder: &DivingCylinder, target: &GasMix) -> Result<Blend, BlendError> {
+    if target.oxygen > MAX_BLEND_OXYGEN {
+        return Err(BlendError::TooMuchOxygen);
+    }
+    if target.oxygen < MIN_BLEND_OXYGEN {
+        return Err(BlendError::Hypoxic);
+    }
+    let pressure = cylinder.working_pressure;
+    let current = &cylinder.mix;
+    // the air top up brings in oxygen along with all of the nitrogen
+    let air_oxygen = GasMix::AIR.oxygen / GasMix::AIR.nitrogen;
+    // each gas needs `pressure * needed - kept * present` bar added, which can't be negative
+    let requirements = [
+        (target.nitrogen, current.nitrogen),
+        (target.helium, current.helium),
+        (
+            target.oxygen - air_oxygen * target.nitrogen,
+            current.oxygen - air_oxygen * current.nitrogen,
+        ),
+    ];
+    let mut kept = cylinder.pressure().min(pressure);
+    for (needed, present) in requirements {
+        if needed < -MIX_TOLERANCE {
+            return Err(BlendError::Unreachable);
+        }
+        if present > 0. {
+            kept = kept.min((pressure * needed / present).max(0.));
+        }
+    }
+    let air = (pressure * target.nitrogen - kept * current.nitrogen) / GasMix::AIR.nitrogen;
+    Ok(Blend {
+        bleed: cylinder.pressure() - kept,
+        helium: pressure * target.helium - kept * current.helium,
+        oxygen: pressure * target.oxygen - kept * current.oxygen - GasMix::AIR.oxygen * air,
+        air,
+    })
+}
+
+#[derive(Event)]
+pub struct FillCylinder {
+    pub cylinder: Entity,
+    pub buyer: Entity,
+    pub mix: GasMix,
+}
+
+pub fn fill_station_plugin(app: &mut App) {
+    app.add_event::<FillCylinder>();
+    app.init_resource::<BlendOrder>();
+    app.add_systems(Startup, spawn_fill_stations);
+    app.add_systems(
+        Update,
+        (
+            toggle_fill_station.in_set(RunningStateSet),
+            (adjust_blend_order, order_fill, update_fill_station_menu)
+                .chain()
+                .run_if(in_state(InGameMenuState::FillStation))
+                .in_set(RunningStateSet),
+        ),
+    );
+    app.add_systems(FixedUpdate, fill_cylinder.in_set(RunningStateSet));
+    app.add_systems(
+        OnEnter(InGameMenuState::FillStation),
+        spawn_fill_station_menu,
+    );
+    app.add_systems(
+        OnExit(InGameMenuState::FillStation),
+        despawn_fill_station_menu,
+    );
+    app.register_type::<FillStation>();
+    app.register_type::<FillStationMenu>();
+    app.register_type::<BlendOrder>();
+}
+
+pub fn spawn_fill_stations(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    println!("Spawning fill stations");
+
+    let mesh = Mesh::from(Rectangle::new(FILL_STATION_WIDTH, FILL_STATION_HEIGHT));
+    let material = ColorMaterial::from_color(Srgba::rgb(0.2, 0.6, 0.2));
+
+    commands.spawn((
+        FillStation,
+        MaterialMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            material: materials.add(material),
+            transform: Transform::from_translation(Vec3::new(-80., 0., 0.)),
+            ..default()
+        },
+        Name::new("Fill station"),
+        crate::PIXEL_PERFECT_LAYERS,
+    ));
+}
+
+pub fn toggle_fill_station(
+    keys: Res<ButtonInput<KeyCode>>,
+    in_game_menu_state: Res<State<InGameMenuState>>,
+    mut next_in_game_menu_state: ResMut<NextState<InGameMenuState>>,
+    diver: Query<&Transform, With<Diver>>,
+    fill_stations: Query<&Transform, With<FillStation>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    match in_game_menu_state.get() {
+        InGameMenuState::FillStation => next_in_game_menu_state.set(InGameMenuState::NoMenu),
+        InGameMenuState::NoMenu => {
+            if let Ok(diver_transform) = diver.get_single() {
+                if fill_stations.iter().any(|transform| {
+                    transform.translation.distance(diver_transform.translation) < FILL_STATION_RANGE
+                }) {
+                    next_in_game_menu_state.set(InGameMenuState::FillStation);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn adjust_blend_order(keys: Res<ButtonInput<KeyCode>>, mut blend_order: ResMut<BlendOrder>) {
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        blend_order.oxygen += BLEND_STEP;
+    } else if keys.just_pressed(KeyCode::ArrowDown) {
+        blend_order.oxygen -= BLEND_STEP;
+    } else if keys.just_pressed(KeyCode::ArrowRight) {
+        blend_order.helium += BLEND_STEP;
+    } else if keys.just_pressed(KeyCode::ArrowLeft) {
+        blend_order.helium -= BLEND_STEP;
+    } else if keys.just_pressed(KeyCode::Tab) {
+        blend_order.selected += 1;
+    }
+    blend_order.oxygen = blend_order.oxygen.clamp(0., 1.);
+    blend_order.helium = blend_order.helium.clamp(0., 1. - blend_order.oxygen);
+}
+
+/// Cylinders in the diver's bag, in the order they can be selected at the fill station.
+fn bag_cylinders(bag: &Bag, cylinders: &Query<&DivingCylinder>) -> Vec<Entity> {
+    bag.collectibles
+        .iter()
+        .copied()
+        .filter(|item| cylinders.get(*item).is_ok())
+        .collect()
+}
+
+pub fn order_fill(
+    keys: Res<ButtonInput<KeyCode>>,
+    blend_order: Res<BlendOrder>,
+    diver: Query<(Entity, &Bag), With<Diver>>,
+    cylinders: Query<&DivingCylinder>,
+    mut fills: EventWriter<FillCylinder>,
+) {
+    if !keys.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    if let (Ok((diver_entity, bag)), Ok(mix)) = (diver.get_single(), blend_order.mix()) {
+        let bag_cylinders = bag_cylinders(bag, &cylinders);
+        if !bag_cylinders.is_empty() {
+            fills.send(FillCylinder {
+                cylinder: bag_cylinders[blend_order.selected % bag_cylinders.len()],
+                buyer: diver_entity,
+                mix,
+            });
+        }
+    }
+}
+
+/// Fills the cylinder if the blend is safe and the buyer can pay for it.
+pub fn fill_cylinder(
+    mut commands: Commands,
+    mut cylinders: Query<&mut DivingCylinder>,
+    mut wallets: Query<&mut Wallet>,
+    mut fills: EventReader<FillCylinder>,
+) {
+    for fill in fills.read() {
+        if let (Ok(mut cylinder), Ok(mut wallet)) = (
+            cylinders.get_mut(fill.cylinder),
+            wallets.get_mut(fill.buyer),
+        ) {
+            match plan_blend(&cylinder, &fill.mix) {
+                Ok(blend) if blend.cost(cylinder.water_volume) <= wallet.0 => {
+                    wallet.0 -= blend.cost(cylinder.water_volume);
+                    cylinder.mix = fill.mix;
+                    cylinder.amount_remaining = cylinder.capacity();
+                    commands
+                        .entity(fill.cylinder)
+                        .insert(Name::new(format!("{} tank", fill.mix.name())));
+                }
+                Ok(blend) => println!(
+                    "can't afford fill, cost: {}, funds: {}",
+                    blend.cost(cylinder.water_volume),
+                    wallet.0
+                ),
+                Err(error) => println!("can't blend {}: {:?}", fill.mix.name(), error),
+            }
+        }
+    }
+}
+
+#[test]
+fn did_fill_cylinder() {
+    let mut app = App::new();
+    app.add_event::<FillCylinder>();
+    app.add_systems(Update, fill_cylinder);
+    let cylinder_id = app
+        .world_mut()
+        .spawn(DivingCylinder::new(10., 200., 50., GasMix::AIR))
+        .id();
+    let buyer_id = app.world_mut().spawn(Wallet(100.)).id();
+    let mix = GasMix::nitrox(0.32).unwrap();
+    app.world_mut()
+        .resource_mut::<Events<FillCylinder>>()
+        .send(FillCylinder {
+            cylinder: cylinder_id,
+            buyer: buyer_id,
+            mix,
+        });
+    app.update();
+    let cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert_eq!(cylinder.mix, mix);
+    assert!((cylinder.pressure() - 200.).abs() < 1e-2);
+    let wallet = app.world().get::<Wallet>(buyer_id).unwrap();
+    assert!(wallet.0 < 100.);
+    let name = app.world().get::<Name>(cylinder_id).unwrap();
+    assert_eq!(name.as_str(), "EAN32 tank");
+}
+
+#[test]
+fn did_not_fill_unaffordable() {
+    let mut app = App::new();
+    app.add_event::<FillCylinder>();
+    app.add_systems(Update, fill_cylinder);
+    let cylinder_id = app
+        .world_mut()
+        .spawn(DivingCylinder::new(10., 200., 0., GasMix::AIR))
+        .id();
+    let buyer_id = app.world_mut().spawn(Wallet(1.)).id();
+    app.world_mut()
+        .resource_mut::<Events<FillCylinder>>()
+        .send(FillCylinder {
+            cylinder: cylinder_id,
+            buyer: buyer_id,
+            mix: GasMix::trimix(0.18, 0.45).unwrap(),
+        });
+    app.update();
+    let cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert_eq!(cylinder.amount_remaining, 0.);
+    let wallet = app.world().get::<Wallet>(buyer_id).unwrap();
+    assert_eq!(wallet.0, 1.);
+}
+
+#[test]
+fn blend_plans() {
+    // topping up air to EAN32 keeps the air already in the cylinder
+    let part_full = DivingCylinder::new(10., 200., 50., GasMix::AIR);
+    let blend = plan_blend(&part_full, &GasMix::nitrox(0.32).unwrap()).unwrap();
+    assert!(blend.bleed.abs() < 1e-3);
+    assert!((blend.oxygen - 27.85).abs() < 1e-2);
+    assert!((50. + blend.helium + blend.oxygen + blend.air - 200.).abs() < 1e-2);
+    // EAN50 has to be bled off entirely to get back to air
+    let rich = DivingCylinder::new(10., 200., 100., GasMix::nitrox(0.5).unwrap());
+    let blend = plan_blend(&rich, &GasMix::AIR).unwrap();
+    assert!((blend.bleed - rich.pressure()).abs() < 1e-3);
+    assert!((blend.air - 200.).abs() < 1e-3);
+    assert!(blend.cost(10.) > 0.);
+    // unsafe and impossible blends
+    let empty = DivingCylinder::default();
+    assert_eq!(
+        plan_blend(&empty, &GasMix::nitrox(1.).unwrap()),
+        Err(BlendError::TooMuchOxygen)
+    );
+    assert_eq!(
+        plan_blend(&empty, &GasMix::trimix(0.05, 0.9).unwrap()),
+        Err(BlendError::Hypoxic)
+    );
+    assert_eq!(
+        plan_blend(&empty, &GasMix::nitrox(0.15).unwrap()),
+        Err(BlendError::Unreachable)
+    );
+}
+
+pub fn spawn_fill_station_menu(mut commands: Commands) {
+    let container = NodeBundle {
+        style: Style {
+            width: Val::Percent(60.),
+            height: Val::Percent(40.),
+            align_self: AlignSelf::Center,
+            justify_self: JustifySelf::Center,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            padding: UiRect::all(Val::Px(20.)),
+            ..default()
+        },
+        background_color: Srgba::rgb(0., 0., 1.).into(),
+        ..default()
+    };
+    let message = TextBundle {
+        text: Text::from_section(
+            "",
+            TextStyle {
+                font_size: crate::FONT_SIZE,
+                ..default()
+            },
+        ),
+        ..default()
+    };
+    let container_id = commands
+        .spawn((container, FillStationMenu, Name::new("Fill station menu")))
+        .id();
+    let message_id = commands.spawn(message).id();
+    commands.entity(container_id).push_children(&[message_id]);
+}
+
+pub fn update_fill_station_menu(
+    blend_order: Res<BlendOrder>,
+    environment: Res<DiveSiteEnvironment>,
+    diver: Query<(&Bag, &Wallet), With<Diver>>,
+    cylinders: Query<&DivingCylinder>,
+    names: Query<&Name>,
+    menus: Query<&Children, With<FillStationMenu>>,
+    mut texts: Query<&mut Text>,
+) {
+    let Ok((bag, wallet)) = diver.get_single() else {
+        return;
+    };
+    let bag_cylinders = bag_cylinders(bag, &cylinders);
+    let selected = match bag_cylinders.len() {
+        0 => None,
+        n => Some(bag_cylinders[blend_order.selected % n]),
+    };
+    let cylinder_line =
+        match selected.and_then(|id| Some((cylinders.get(id).ok()?, names.get(id).ok()))) {
+            Some((cylinder, name)) => format!(
+                "{}: {:.0} bar of {}",
+                name.map_or("Cylinder", |name| name.as_str()),
+                cylinder.pressure(),
+                cylinder.mix.name()
+            ),
+            None => "No cylinders in bag".to_string(),
+        };
+    let blend_line = match blend_order.mix() {
+        Ok(mix) => {
+            let plan = selected
+                .and_then(|id| cylinders.get(id).ok())
+                .map(|cylinder| (plan_blend(cylinder, &mix), cylinder.water_volume));
+            let outcome = match plan {
+                Some((Ok(blend), water_volume)) => format!(
+                    "Cost: {:.2}, bleed {:.0} bar, He {:.0} bar, O2 {:.0} bar, air {:.0} bar",
+                    blend.cost(water_volume),
+                    blend.bleed,
+                    blend.helium,
+                    blend.oxygen,
+                    blend.air,
+                ),
+                Some((Err(error), _)) => format!("Rejected: {:?}", error),
+                None => String::new(),
+            };
+            format!(
+                "Blend: {}, MOD: {:.0} m\n{}",
+                mix.name(),
+                mix.maximum_operating_depth(&environment, MAX_OPERATING_PO2),
+                outcome
+            )
+        }
+        Err(error) => format!("Rejected: {:?}", error),
+    };
+    for children in &menus {
+        for child in children {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = format!(
+                    "{}\n{}\nFunds: {:.2}\nUp/Down O2, Left/Right He, Tab cylinder, Enter fill",
+                    cylinder_line, blend_line, wallet.0
+                );
+            }
+        }
+    }
+}
+
+pub fn despawn_fill_station_menu(
+    mut commands: Commands,
+    fill_station_menus: Query<Entity, With<FillStationMenu>>,
+) {
+    if let Ok(fill_station_menu) = fill_station_menus.get_single() {
+        commands.entity(fill_station_menu).despawn_recursive();
+    }
+}
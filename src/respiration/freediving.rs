@@ -0,0 +1,415 @@
+use crate::circulation::CirculateGas;
+use crate::environment::DiveSiteEnvironment;
+use crate::health::DamageEvent;
+use crate::position::Depth;
+use crate::respiration::air_sharing::SharingAir;
+use crate::respiration::circulation::equalization::BloodstreamPressure;
+use crate::respiration::circulation::usage::*;
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::inhalation::*;
+use crate::respiration::rebreather::EquippedRebreather;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+
+// litres of gas in the lungs after a full breath at the surface
+const DEFAULT_LUNG_VOLUME: f32 = 6.;
+// proportion of the surface lung volume of oxygen consumed each second
+const OXYGEN_CONSUMPTION: f32 = 0.0007;
+const DIVE_REFLEX_FACTOR: f32 = 0.7;
+pub const BLACKOUT_PO2: f32 = 0.1;
+const CONSCIOUS_PO2: f32 = 0.16;
+// damage per second while unconscious underwater
+const DROWNING_DAMAGE: f32 = 5.;
+
+/// Breathers with nothing to breathe from underwater.
+type BreathHolding = (
+    Without<EquippedTank>,
+    Without<EquippedRebreather>,
+    Without<SharingAir>,
+);
+
+/// Oxygen partial pressure, in bar, where a breather passes out.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct BlackoutPo2(pub f32);
+
+impl Default for BlackoutPo2 {
+    fn default() -> Self {
+        Self(BLACKOUT_PO2)
+    }
+}
+
+/// Volume of the lungs at the surface, in litres.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct LungVolume(pub f32);
+
+impl Default for LungVolume {
+    fn default() -> Self {
+        Self(DEFAULT_LUNG_VOLUME)
+    }
+}
+
+impl LungVolume {
+    /// Volume of a breath taken at the surface once compressed at `depth`, by Boyle's law.
+    pub fn compressed(&self, environment: &DiveSiteEnvironment, depth: f32) -> f32 {
+        self.0 * environment.surface_pressure / environment.ambient_pressure(depth)
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Unconscious;
+
+pub fn freediving_plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (
+            surface_inhalation,
+            dive_reflex.after(reset_breathing_demand).before(usage),
+            hold_breath.after(usage),
+            shallow_water_blackout.after(hold_breath),
+            recover_consciousness.after(shallow_water_blackout),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<BlackoutPo2>();
+    app.register_type::<LungVolume>();
+    app.register_type::<Unconscious>();
+}
+
+pub fn surface_inhalation(
+    breathers: Query<(Entity, &BloodstreamContent, &Depth), BreathHolding>,
+    mut breaths: EventReader<BreathTaken>,
+    mut circulate_gas: EventWriter<CirculateGas>,
+) {
+    for breath in breaths.read() {
+        if let Ok((entity, bloodstream_content, depth)) = breathers.get(breath.entity) {
+            let amount_breathed =
+                bloodstream_content.capacity - bloodstream_content.amount_remaining;
+            if depth.0 <= 0. && amount_breathed > 0. {
+                circulate_gas.send(CirculateGas {
+                    entity,
+                    amount: amount_breathed,
+                    mix: GasMix::AIR,
+                });
+            }
+        }
+    }
+}
+
+#[test]
+fn did_breathe_at_surface() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.add_event::<CirculateGas>();
+    app.add_systems(Update, surface_inhalation);
+    let surface_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 50.,
+                ..default()
+            },
+            Depth(0.),
+        ))
+        .id();
+    let submerged_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 50.,
+                ..default()
+            },
+            Depth(5.),
+        ))
+        .id();
+    let mut breaths = app.world_mut().resource_mut::<Events<BreathTaken>>();
+    breaths.send(BreathTaken { entity: surface_id });
+    breaths.send(BreathTaken {
+        entity: submerged_id,
+    });
+    app.update();
+    // only the diver at the surface should have breathed
+    let gas_to_circulate_events = app.world().resource::<Events<CirculateGas>>();
+    let mut gas_to_circulate_reader = gas_to_circulate_events.get_reader();
+    let mut gases_to_circulate = gas_to_circulate_reader.read(gas_to_circulate_events);
+    let gas_to_circulate = gases_to_circulate.next().unwrap();
+    assert_eq!(gas_to_circulate.entity, surface_id);
+    assert_eq!(gas_to_circulate.amount, 50.);
+    assert_eq!(gas_to_circulate.mix, GasMix::AIR);
+    assert!(gases_to_circulate.next().is_none());
+}
+
+/// The mammalian dive reflex slows the heart and metabolism once the face is submerged.
+pub fn dive_reflex(mut breathers: Query<(&mut BreathingDemand, &Depth), BreathHolding>) {
+    for (mut breathing_demand, depth) in &mut breathers {
+        if depth.0 > 0. {
+            breathing_demand.0 *= DIVE_REFLEX_FACTOR;
+        }
+    }
+}
+
+#[test]
+fn did_dive_reflex() {
+    let mut app = App::new();
+    app.add_systems(Update, dive_reflex);
+    let freediver_id = app
+        .world_mut()
+        .spawn((BreathingDemand::default(), Depth(5.)))
+        .id();
+    let cylinder_id = app.world_mut().spawn(DivingCylinder::default()).id();
+    let scuba_diver_id = app
+        .world_mut()
+        .spawn((
+            BreathingDemand::default(),
+            Depth(5.),
+            EquippedTank(cylinder_id),
+        ))
+        .id();
+    app.update();
+    let freediver = app.world().get::<BreathingDemand>(freediver_id).unwrap();
+    assert_eq!(freediver.0, DIVE_REFLEX_FACTOR);
+    let scuba_diver = app.world().get::<BreathingDemand>(scuba_diver_id).unwrap();
+    assert_eq!(scuba_diver.0, 1.);
+}
+
+/// The held breath is compressed with depth, raising its partial pressures, while its oxygen is
+/// used up.
+pub fn hold_breath(
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<
+        (
+            &mut BloodstreamPressure,
+            &mut BloodstreamContent,
+            &Depth,
+            Option<&BreathingDemand>,
+        ),
+        BreathHolding,
+    >,
+) {
+    for (mut bloodstream_pressure, mut bloodstream_content, depth, breathing_demand) in
+        &mut breathers
+    {
+        bloodstream_pressure.0 = environment.ambient_pressure(depth.0);
+        if depth.0 > 0. {
+            let demand = breathing_demand.map_or(1., |breathing_demand| breathing_demand.0);
+            bloodstream_content.mix.oxygen = (bloodstream_content.mix.oxygen
+                - OXYGEN_CONSUMPTION * demand * time.delta_seconds())
+            .max(0.);
+        }
+    }
+}
+
+#[test]
+fn did_hold_breath() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, hold_breath);
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamPressure(1.),
+            BloodstreamContent::default(),
+            Depth(20.),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(10));
+    app.update();
+    // compressed to ambient pressure, with some oxygen used
+    let bloodstream_pressure = app.world().get::<BloodstreamPressure>(breather_id).unwrap();
+    assert_eq!(bloodstream_pressure.0, 3.);
+    let bloodstream_content = app.world().get::<BloodstreamContent>(breather_id).unwrap();
+    assert!((bloodstream_content.mix.oxygen - (0.21 - 10. * OXYGEN_CONSUMPTION)).abs() < 1e-6);
+    // the oxygen used isn't made up by the other gases
+    assert_eq!(bloodstream_content.mix.nitrogen, 0.79);
+}
+
+#[test]
+fn lungs_compress() {
+    let sea = DiveSiteEnvironment::SEA;
+    let lungs = LungVolume(6.);
+    assert_eq!(lungs.compressed(&sea, 0.), 6.);
+    assert_eq!(lungs.compressed(&sea, 10.), 3.);
+    assert_eq!(lungs.compressed(&sea, 50.), 1.);
+}
+
+pub fn shallow_water_blackout(
+    mut commands: Commands,
+    breathers: Query<
+        (
+            Entity,
+            &BloodstreamPressure,
+            &BloodstreamContent,
+            &BlackoutPo2,
+        ),
+        Without<Unconscious>,
+    >,
+) {
+    for (entity, bloodstream_pressure, bloodstream_content, blackout_po2) in &breathers {
+        if bloodstream_content.mix.oxygen * bloodstream_pressure.0 < blackout_po2.0 {
+            println!("blacked out, pressure: {}", bloodstream_pressure.0);
+            commands.entity(entity).insert(Unconscious);
+        }
+    }
+}
+
+#[test]
+fn did_blackout_on_ascent() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(
+        Update,
+        (hold_breath, shallow_water_blackout.after(hold_breath)),
+    );
+    // enough oxygen left at depth, but not once the breath re-expands
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamPressure(1.),
+            BloodstreamContent {
+                mix: GasMix {
+                    oxygen: 0.06,
+                    nitrogen: 0.79,
+                    helium: 0.,
+                },
+                ..default()
+            },
+            BlackoutPo2::default(),
+            Depth(20.),
+        ))
+        .id();
+    let hardy_breather_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamPressure(1.),
+            BloodstreamContent {
+                mix: GasMix {
+                    oxygen: 0.06,
+                    nitrogen: 0.79,
+                    helium: 0.,
+                },
+                ..default()
+            },
+            BlackoutPo2(0.05),
+            Depth(20.),
+        ))
+        .id();
+    app.update();
+    assert!(app.world().get::<Unconscious>(breather_id).is_none());
+    app.world_mut().get_mut::<Depth>(breather_id).unwrap().0 = 5.;
+    app.world_mut()
+        .get_mut::<Depth>(hardy_breather_id)
+        .unwrap()
+        .0 = 5.;
+    app.update();
+    assert!(app.world().get::<Unconscious>(breather_id).is_some());
+    assert!(app.world().get::<Unconscious>(hardy_breather_id).is_none());
+}
+
+/// Unconscious breathers drown underwater, and breathe air again once they are at the surface.
+pub fn recover_consciousness(
+    mut commands: Commands,
+    time: Res<Time>,
+    breathers: Query<
+        (Entity, &BloodstreamPressure, &BloodstreamContent, &Depth),
+        With<Unconscious>,
+    >,
+    mut circulate_gas: EventWriter<CirculateGas>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, bloodstream_pressure, bloodstream_content, depth) in &breathers {
+        if depth.0 > 0. {
+            damage_events.send(DamageEvent {
+                target: entity,
+                damage: DROWNING_DAMAGE * time.delta_seconds(),
+            });
+        } else if bloodstream_content.mix.oxygen * bloodstream_pressure.0 >= CONSCIOUS_PO2 {
+            commands.entity(entity).remove::<Unconscious>();
+        } else {
+            circulate_gas.send(CirculateGas {
+                entity,
+                amount: bloodstream_content.capacity,
+                mix: GasMix::AIR,
+            });
+        }
+    }
+}
+
+#[test]
+fn did_drown() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<CirculateGas>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, recover_consciousness);
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            Unconscious,
+            BloodstreamPressure(1.),
+            BloodstreamContent::default(),
+            Depth(5.),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
+    app.update();
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damage = damage_reader.read(damage_events).next().unwrap();
+    assert_eq!(damage.target, breather_id);
+    assert_eq!(damage.damage, DROWNING_DAMAGE);
+    assert!(app.world().get::<Unconscious>(breather_id).is_some());
+}
+
+#[test]
+fn did_recover_at_surface() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<CirculateGas>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, recover_consciousness);
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            Unconscious,
+            BloodstreamPressure(1.),
+            BloodstreamContent {
+                capacity: 100.,
+                mix: GasMix {
+                    oxygen: 0.05,
+                    nitrogen: 0.79,
+                    helium: 0.,
+                },
+                ..default()
+            },
+            Depth(0.),
+        ))
+        .id();
+    app.update();
+    // should have taken a breath of air
+    let gas_to_circulate_events = app.world().resource::<Events<CirculateGas>>();
+    let mut gas_to_circulate_reader = gas_to_circulate_events.get_reader();
+    let gas_to_circulate = gas_to_circulate_reader
+        .read(gas_to_circulate_events)
+        .next()
+        .unwrap();
+    assert_eq!(gas_to_circulate.entity, breather_id);
+    assert_eq!(gas_to_circulate.mix, GasMix::AIR);
+    // and come round once the oxygen is back
+    app.world_mut()
+        .get_mut::<BloodstreamContent>(breather_id)
+        .unwrap()
+        .mix = GasMix::AIR;
+    app.update();
+    assert!(app.world().get::<Unconscious>(breather_id).is_none());
+}
@@ -0,0 +1,168 @@
+use crate::environment::DiveSiteEnvironment;
+use bevy::prelude::*;
+
+pub const MIX_TOLERANCE: f32 = 1e-4;
+
+/// Fractions of each gas in a breathing mix. Gases are built with `new`, `nitrox` or `trimix`,
+/// which check the fractions add up to 1. A bloodstream mix is left unnormalised on purpose: its
+/// fractions are of the breath as it was taken, so oxygen used up since just leaves a shortfall.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct GasMix {
+    pub oxygen: f32,
+    pub nitrogen: f32,
+    pub helium: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GasMixError {
+    NegativeFraction,
+    DoesNotSumToOne,
+}
+
+impl Default for GasMix {
+    fn default() -> Self {
+        Self::AIR
+    }
+}
+
+impl GasMix {
+    pub const AIR: Self = Self {
+        oxygen: 0.21,
+        nitrogen: 0.79,
+        helium: 0.,
+    };
+
+    pub const OXYGEN: Self = Self {
+        oxygen: 1.,
+        nitrogen: 0.,
+        helium: 0.,
+    };
+
+    pub fn new(oxygen: f32, nitrogen: f32, helium: f32) -> Result<Self, GasMixError> {
+        if oxygen < 0. || nitrogen < 0. || helium < 0. {
+            Err(GasMixError::NegativeFraction)
+        } else if (oxygen + nitrogen + helium - 1.).abs() > MIX_TOLERANCE {
+            Err(GasMixError::DoesNotSumToOne)
+        } else {
+            Ok(Self {
+                oxygen,
+                nitrogen,
+                helium,
+            })
+        }
+    }
+
+    pub fn nitrox(oxygen: f32) -> Result<Self, GasMixError> {
+        Self::new(oxygen, 1. - oxygen, 0.)
+    }
+
+    pub fn trimix(oxygen: f32, helium: f32) -> Result<Self, GasMixError> {
+        Self::new(oxygen, 1. - oxygen - helium, helium)
+    }
+
+    pub fn inert(&self) -> f32 {
+        self.nitrogen + self.helium
+    }
+
+    /// The mix that results from adding `added_amount` of `added` to `amount` of this mix.
+    pub fn blend(&self, amount: f32, added: &GasMix, added_amount: f32) -> GasMix {
+        let total = amount + added_amount;
+        if total <= 0. {
+            return *self;
+        }
+        let blend_gas =
+            |gas: f32, added_gas: f32| (gas * amount + added_gas * added_amount) / total;
+        GasMix {
+            oxygen: blend_gas(self.oxygen, added.oxygen),
+            nitrogen: blend_gas(self.nitrogen, added.nitrogen),
+            helium: blend_gas(self.helium, added.helium),
+        }
+    }
+
+    /// Deepest depth, in metres, where the oxygen partial pressure stays within `max_po2`.
+    pub fn maximum_operating_depth(&self, environment: &DiveSiteEnvironment, max_po2: f32) -> f32 {
+        environment.depth_at_pressure(max_po2 / self.oxygen).max(0.)
+    }
+
+    /// Shallowest depth, in metres, where the oxygen partial pressure reaches `min_po2`. Only
+    /// hypoxic mixes have one below the surface.
+    pub fn minimum_operating_depth(&self, environment: &DiveSiteEnvironment, min_po2: f32) -> f32 {
+        environment.depth_at_pressure(min_po2 / self.oxygen).max(0.)
+    }
+
+    /// Conventional short name, e.g. "air", "EAN32" or "Tx18/45".
+    pub fn name(&self) -> String {
+        let oxygen = (self.oxygen * 100.).round();
+        let helium = (self.helium * 100.).round();
+        if helium > 0. {
+            format!("Tx{}/{}", oxygen, helium)
+        } else if oxygen == 21. {
+            "air".to_string()
+        } else {
+            format!("EAN{}", oxygen)
+        }
+    }
+}
+
+#[test]
+fn valid_mixes() {
+    assert_eq!(GasMix::new(0.21, 0.79, 0.), Ok(GasMix::AIR));
+    assert_eq!(GasMix::nitrox(0.32).unwrap().nitrogen, 1. - 0.32);
+    let trimix = GasMix::trimix(0.18, 0.45).unwrap();
+    assert!((trimix.nitrogen - 0.37).abs() < MIX_TOLERANCE);
+    assert!((trimix.inert() - 0.82).abs() < MIX_TOLERANCE);
+}
+
+#[test]
+fn invalid_mixes() {
+    assert_eq!(
+        GasMix::new(0.21, 0.78, 0.),
+        Err(GasMixError::DoesNotSumToOne)
+    );
+    assert_eq!(GasMix::trimix(0.5, 0.6), Err(GasMixError::NegativeFraction));
+}
+
+#[test]
+fn blended_mixes() {
+    let oxygen = GasMix::nitrox(1.).unwrap();
+    let blend = GasMix::AIR.blend(3., &oxygen, 1.);
+    assert!((blend.oxygen - (0.21 * 3. + 1.) / 4.).abs() < MIX_TOLERANCE);
+    assert!((blend.oxygen + blend.nitrogen + blend.helium - 1.).abs() < MIX_TOLERANCE);
+    assert_eq!(GasMix::AIR.blend(0., &oxygen, 0.), GasMix::AIR);
+}
+
+#[test]
+fn operating_depths() {
+    let sea = DiveSiteEnvironment::SEA;
+    assert!((GasMix::AIR.maximum_operating_depth(&sea, 1.4) - 56.67).abs() < 1e-2);
+    assert!(
+        (GasMix::nitrox(0.5)
+            .unwrap()
+            .maximum_operating_depth(&sea, 1.6)
+            - 22.)
+            .abs()
+            < 1e-4
+    );
+    assert_eq!(
+        GasMix::nitrox(1.)
+            .unwrap()
+            .maximum_operating_depth(&sea, 0.5),
+        0.
+    );
+    assert_eq!(GasMix::AIR.minimum_operating_depth(&sea, 0.18), 0.);
+    assert!(
+        (GasMix::trimix(0.1, 0.7)
+            .unwrap()
+            .minimum_operating_depth(&sea, 0.18)
+            - 8.)
+            .abs()
+            < 1e-4
+    );
+}
+
+#[test]
+fn mix_names() {
+    assert_eq!(GasMix::AIR.name(), "air");
+    assert_eq!(GasMix::nitrox(0.5).unwrap().name(), "EAN50");
+    assert_eq!(GasMix::trimix(0.18, 0.45).unwrap().name(), "Tx18/45");
+}
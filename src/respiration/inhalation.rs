@@ -1,34 +1,133 @@
 use crate::circulation::CirculateGas;
+use crate::environment::DiveSiteEnvironment;
+use crate::position::Depth;
+use crate::respiration::air_sharing::*;
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::rebreather::EquippedRebreather;
+use crate::respiration::regulator::Regulator;
 use crate::states::RunningStateSet;
 use bevy::prelude::*;
 use bevy::sprite::MaterialMesh2dBundle;
 
-const AIR_O2_RATIO: f32 = 0.21;
-const AIR_N2_RATIO: f32 = 0.78;
-
 const CYLINDER_WIDTH: f32 = 2.;
 const CYLINDER_HEIGHT: f32 = 4.;
 
+const DEFAULT_WATER_VOLUME: f32 = 10.;
+const DEFAULT_WORKING_PRESSURE: f32 = 200.;
+// change in compressibility factor per bar for each gas, so that Z = 1 + bP
+const OXYGEN_COMPRESSIBILITY: f32 = -0.0001;
+const NITROGEN_COMPRESSIBILITY: f32 = 0.0002;
+const HELIUM_COMPRESSIBILITY: f32 = 0.0005;
+// oxygen partial pressures, in bar, a mix is safe to breathe between
+pub const MAX_OPERATING_PO2: f32 = 1.4;
+pub const MIN_OPERATING_PO2: f32 = 0.18;
+// metres inside either operating depth where a mix is getting close to its limit
+const OPERATING_DEPTH_MARGIN: f32 = 3.;
+
+/// A cylinder of `water_volume` litres rated to `working_pressure` bar. `amount_remaining` is
+/// the gas left in it, in litres at the surface.
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct DivingCylinder {
-    pub capacity: f32,
+    pub water_volume: f32,
+    pub working_pressure: f32,
     pub amount_remaining: f32,
-    pub proportion_of_oxygen: f32,
-    pub proportion_of_nitrogen: f32,
+    pub mix: GasMix,
 }
 
 impl Default for DivingCylinder {
     fn default() -> Self {
         Self {
-            capacity: 0.,
+            water_volume: DEFAULT_WATER_VOLUME,
+            working_pressure: DEFAULT_WORKING_PRESSURE,
+            amount_remaining: 0.,
+            mix: GasMix::AIR,
+        }
+    }
+}
+
+impl DivingCylinder {
+    /// A cylinder filled to `pressure` bar.
+    pub fn new(water_volume: f32, working_pressure: f32, pressure: f32, mix: GasMix) -> Self {
+        let mut cylinder = Self {
+            water_volume,
+            working_pressure,
             amount_remaining: 0.,
-            proportion_of_oxygen: AIR_O2_RATIO,
-            proportion_of_nitrogen: AIR_N2_RATIO,
+            mix,
+        };
+        cylinder.amount_remaining = cylinder.amount_at_pressure(pressure);
+        cylinder
+    }
+
+    fn compressibility(&self) -> f32 {
+        self.mix.oxygen * OXYGEN_COMPRESSIBILITY
+            + self.mix.nitrogen * NITROGEN_COMPRESSIBILITY
+            + self.mix.helium * HELIUM_COMPRESSIBILITY
+    }
+
+    /// Surface litres of gas the cylinder holds at `pressure` bar. Gases stop compressing
+    /// ideally at cylinder pressures, so this isn't quite proportional to the pressure.
+    pub fn amount_at_pressure(&self, pressure: f32) -> f32 {
+        self.water_volume * pressure / (1. + self.compressibility() * pressure)
+    }
+
+    /// Surface litres of gas the cylinder holds when full.
+    pub fn capacity(&self) -> f32 {
+        self.amount_at_pressure(self.working_pressure)
+    }
+
+    /// Pressure of the gas left in the cylinder, in bar.
+    pub fn pressure(&self) -> f32 {
+        self.amount_remaining / (self.water_volume - self.amount_remaining * self.compressibility())
+    }
+
+    pub fn maximum_operating_depth(&self, environment: &DiveSiteEnvironment) -> f32 {
+        self.mix
+            .maximum_operating_depth(environment, MAX_OPERATING_PO2)
+    }
+
+    pub fn minimum_operating_depth(&self, environment: &DiveSiteEnvironment) -> f32 {
+        self.mix
+            .minimum_operating_depth(environment, MIN_OPERATING_PO2)
+    }
+
+    pub fn suitability(&self, environment: &DiveSiteEnvironment, depth: f32) -> GasSuitability {
+        let (minimum, maximum) = (
+            self.minimum_operating_depth(environment),
+            self.maximum_operating_depth(environment),
+        );
+        if depth > maximum {
+            GasSuitability::TooDeep
+        } else if depth < minimum {
+            GasSuitability::TooShallow
+        } else if depth > maximum - OPERATING_DEPTH_MARGIN
+            || (minimum > 0. && depth < minimum + OPERATING_DEPTH_MARGIN)
+        {
+            GasSuitability::Marginal
+        } else {
+            GasSuitability::Breathable
         }
     }
 }
 
+/// Whether a cylinder's mix is safe to breathe at a given depth.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum GasSuitability {
+    Breathable,
+    /// Within `OPERATING_DEPTH_MARGIN` of one of the operating depths.
+    Marginal,
+    /// Beyond the MOD, risking oxygen toxicity.
+    TooDeep,
+    /// Above the minimum operating depth, risking hypoxia.
+    TooShallow,
+}
+
+impl GasSuitability {
+    pub fn is_safe(&self) -> bool {
+        matches!(self, Self::Breathable | Self::Marginal)
+    }
+}
+
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct EquippedTank(pub Entity);
@@ -38,8 +137,7 @@ pub struct EquippedTank(pub Entity);
 pub struct BloodstreamContent {
     pub capacity: f32,
     pub amount_remaining: f32,
-    pub proportion_of_oxygen: f32,
-    pub proportion_of_nitrogen: f32,
+    pub mix: GasMix,
 }
 
 impl Default for BloodstreamContent {
@@ -47,8 +145,7 @@ impl Default for BloodstreamContent {
         Self {
             capacity: 0.,
             amount_remaining: 0.,
-            proportion_of_oxygen: AIR_O2_RATIO,
-            proportion_of_nitrogen: AIR_N2_RATIO,
+            mix: GasMix::AIR,
         }
     }
 }
@@ -74,10 +171,7 @@ pub fn spawn_cylinders(
 ) {
     println!("Spawning cylinders");
 
-    let mut spawn_cylinder = |x: f32,
-                              y: f32,
-                              proportion_of_oxygen: f32,
-                              proportion_of_nitrogen: f32| {
+    let mut spawn_cylinder = |x: f32, y: f32, mix: GasMix| {
         let mesh = Mesh::from(Rectangle::new(CYLINDER_WIDTH, CYLINDER_HEIGHT));
         let material = ColorMaterial::from_color(Srgba::rgb(0.5, 0.5, 0.5));
 
@@ -85,12 +179,13 @@ pub fn spawn_cylinders(
         let material_handle = materials.add(material);
 
         commands.spawn((
-            DivingCylinder {
-                capacity: crate::diver::DIVER_TANK_CAPACITY,
-                amount_remaining: crate::diver::DIVER_TANK_CAPACITY,
-                proportion_of_oxygen: proportion_of_oxygen,
-                proportion_of_nitrogen: proportion_of_nitrogen,
-            },
+            DivingCylinder::new(
+                crate::diver::DIVER_TANK_WATER_VOLUME,
+                crate::diver::DIVER_TANK_WORKING_PRESSURE,
+                crate::diver::DIVER_TANK_WORKING_PRESSURE,
+                mix,
+            ),
+            Regulator::default(),
             crate::collision::RectangularHitbox(Rectangle::new(CYLINDER_WIDTH, CYLINDER_HEIGHT)),
             MaterialMesh2dBundle {
                 mesh: mesh_handle.into(),
@@ -99,33 +194,43 @@ pub fn spawn_cylinders(
                 ..default()
             },
             crate::bag::Collectible,
-            Name::new(format!(
-                "{}O2 {}N tank",
-                proportion_of_oxygen, proportion_of_nitrogen
-            )),
+            Name::new(format!("{} tank", mix.name())),
             crate::PIXEL_PERFECT_LAYERS,
         ));
     };
 
-    spawn_cylinder(30., 0., 0.5, 0.5);
-    spawn_cylinder(70., 0., 0.3, 0.7);
+    spawn_cylinder(30., 0., GasMix::nitrox(0.5).unwrap());
+    spawn_cylinder(70., 0., GasMix::nitrox(0.3).unwrap());
 }
 
+/// Breaths come from the breather's own `EquippedTank`, or their donor's while sharing air.
 pub fn inhalation(
-    mut breathers: Query<(Entity, &mut BloodstreamContent, &EquippedTank)>,
-    mut cylinders: Query<&mut DivingCylinder>,
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<(Entity, &mut BloodstreamContent, &Depth), Without<EquippedRebreather>>,
+    equipped_tanks: Query<&EquippedTank>,
+    sharers: Query<&SharingAir>,
+    mut cylinders: Query<(&mut DivingCylinder, Option<&Regulator>)>,
     mut breaths: EventReader<BreathTaken>,
     mut circulate_gas: EventWriter<CirculateGas>,
 ) {
     for breath in breaths.read() {
-        if let Ok((entity, bloodstream_content, equipped_tank_id)) =
-            breathers.get_mut(breath.entity)
-        {
-            if let Ok(mut cylinder) = cylinders.get_mut(equipped_tank_id.0) {
-                let amount_breathed = (bloodstream_content.capacity
+        if let Ok((entity, bloodstream_content, depth)) = breathers.get_mut(breath.entity) {
+            let Some(tank) = supplying_tank(entity, &equipped_tanks, &sharers) else {
+                continue;
+            };
+            if let Ok((mut cylinder, regulator)) = cylinders.get_mut(tank) {
+                // filling the lungs at depth takes gas compressed to the ambient pressure
+                let pressure = environment.ambient_pressure(depth.0);
+                // a regulator can only give part of a breath once the tank is nearly empty
+                let delivery = regulator.map_or(1., |regulator| {
+                    regulator.delivery(cylinder.pressure(), pressure)
+                });
+                let amount_breathed = ((bloodstream_content.capacity
                     - bloodstream_content.amount_remaining)
-                    .min(cylinder.amount_remaining);
-                cylinder.amount_remaining -= amount_breathed;
+                    * delivery)
+                    .min(cylinder.amount_remaining / pressure);
+                cylinder.amount_remaining =
+                    (cylinder.amount_remaining - amount_breathed * pressure).max(0.);
                 println!(
                     "amount breathed: {}, tank remaining: {}",
                     amount_breathed, cylinder.amount_remaining,
@@ -134,8 +239,7 @@ pub fn inhalation(
                     circulate_gas.send(CirculateGas {
                         entity: entity,
                         amount: amount_breathed,
-                        proportion_of_oxygen: cylinder.proportion_of_oxygen,
-                        proportion_of_nitrogen: cylinder.proportion_of_nitrogen,
+                        mix: cylinder.mix,
                     });
                 }
             }
@@ -148,14 +252,18 @@ fn did_inhale_full() {
     let mut app = App::new();
     app.add_event::<BreathTaken>();
     app.add_event::<CirculateGas>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, inhalation);
     let cylinder_id = app
         .world_mut()
         .spawn(DivingCylinder {
-            capacity: 100.,
             amount_remaining: 100.,
-            proportion_of_oxygen: 0.5,
-            proportion_of_nitrogen: 0.5,
+            mix: GasMix {
+                oxygen: 0.5,
+                nitrogen: 0.5,
+                helium: 0.,
+            },
+            ..default()
         })
         .id();
     let breather_id = app
@@ -164,10 +272,14 @@ fn did_inhale_full() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 50.,
-                proportion_of_oxygen: 0.,
-                proportion_of_nitrogen: 0.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
             },
             EquippedTank(cylinder_id),
+            Depth(0.),
         ))
         .id();
     app.world_mut()
@@ -188,7 +300,60 @@ fn did_inhale_full() {
         .unwrap();
     assert_eq!(gas_to_circulate.entity, breather_id);
     assert_eq!(gas_to_circulate.amount, 50.);
-    assert_eq!(gas_to_circulate.proportion_of_oxygen, 0.5);
+    assert_eq!(gas_to_circulate.mix.oxygen, 0.5);
+}
+
+#[test]
+fn did_inhale_shared() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.add_event::<CirculateGas>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, inhalation);
+    let cylinder_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            amount_remaining: 200.,
+            ..default()
+        })
+        .id();
+    let empty_id = app.world_mut().spawn(DivingCylinder::default()).id();
+    let lungs = || BloodstreamContent {
+        capacity: 100.,
+        amount_remaining: 50.,
+        ..default()
+    };
+    let donor_id = app
+        .world_mut()
+        .spawn((lungs(), EquippedTank(cylinder_id), Depth(0.)))
+        .id();
+    let receiver_id = app
+        .world_mut()
+        .spawn((
+            lungs(),
+            EquippedTank(empty_id),
+            SharingAir { donor: donor_id },
+            Depth(0.),
+        ))
+        .id();
+    let mut breaths = app.world_mut().resource_mut::<Events<BreathTaken>>();
+    breaths.send(BreathTaken { entity: donor_id });
+    breaths.send(BreathTaken {
+        entity: receiver_id,
+    });
+    app.update();
+    // both breaths come out of the donor's tank
+    let cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert_eq!(cylinder.amount_remaining, 100.);
+    let gas_to_circulate_events = app.world().resource::<Events<CirculateGas>>();
+    let mut gas_to_circulate_reader = gas_to_circulate_events.get_reader();
+    assert_eq!(
+        gas_to_circulate_reader
+            .read(gas_to_circulate_events)
+            .filter(|gas_to_circulate| gas_to_circulate.entity == receiver_id)
+            .count(),
+        1
+    );
 }
 
 #[test]
@@ -196,14 +361,18 @@ fn did_inhale_partial() {
     let mut app = App::new();
     app.add_event::<BreathTaken>();
     app.add_event::<CirculateGas>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, inhalation);
     let cylinder_id = app
         .world_mut()
         .spawn(DivingCylinder {
-            capacity: 100.,
             amount_remaining: 50.,
-            proportion_of_oxygen: 0.5,
-            proportion_of_nitrogen: 0.5,
+            mix: GasMix {
+                oxygen: 0.5,
+                nitrogen: 0.5,
+                helium: 0.,
+            },
+            ..default()
         })
         .id();
     let breather_id = app
@@ -212,10 +381,14 @@ fn did_inhale_partial() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 25.,
-                proportion_of_oxygen: 0.0,
-                proportion_of_nitrogen: 0.0,
+                mix: GasMix {
+                    oxygen: 0.0,
+                    nitrogen: 0.0,
+                    helium: 0.,
+                },
             },
             EquippedTank(cylinder_id),
+            Depth(0.),
         ))
         .id();
     app.world_mut()
@@ -236,8 +409,8 @@ fn did_inhale_partial() {
         .unwrap();
     assert_eq!(gas_to_circulate.entity, breather_id);
     assert_eq!(gas_to_circulate.amount, 50.);
-    assert_eq!(gas_to_circulate.proportion_of_oxygen, 0.5);
-    assert_eq!(gas_to_circulate.proportion_of_nitrogen, 0.5);
+    assert_eq!(gas_to_circulate.mix.oxygen, 0.5);
+    assert_eq!(gas_to_circulate.mix.nitrogen, 0.5);
 }
 
 #[test]
@@ -245,14 +418,18 @@ fn did_not_inhale_empty_cylinder() {
     let mut app = App::new();
     app.add_event::<BreathTaken>();
     app.add_event::<CirculateGas>();
+    app.init_resource::<DiveSiteEnvironment>();
     app.add_systems(Update, inhalation);
     let cylinder_id = app
         .world_mut()
         .spawn(DivingCylinder {
-            capacity: 100.,
             amount_remaining: 0.,
-            proportion_of_oxygen: 0.,
-            proportion_of_nitrogen: 0.,
+            mix: GasMix {
+                oxygen: 0.,
+                nitrogen: 0.,
+                helium: 0.,
+            },
+            ..default()
         })
         .id();
     let breather_id = app
@@ -261,10 +438,14 @@ fn did_not_inhale_empty_cylinder() {
             BloodstreamContent {
                 capacity: 100.,
                 amount_remaining: 50.,
-                proportion_of_oxygen: 0.5,
-                proportion_of_nitrogen: 0.5,
+                mix: GasMix {
+                    oxygen: 0.5,
+                    nitrogen: 0.5,
+                    helium: 0.,
+                },
             },
             EquippedTank(cylinder_id),
+            Depth(0.),
         ))
         .id();
     app.world_mut()
@@ -282,3 +463,130 @@ fn did_not_inhale_empty_cylinder() {
     let gas_to_circulate = gas_to_circulate_reader.read(gas_to_circulate_events).next();
     assert!(gas_to_circulate.is_none());
 }
+
+#[test]
+fn did_inhale_at_depth() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.add_event::<CirculateGas>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, inhalation);
+    let cylinder_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            amount_remaining: 1000.,
+            ..default()
+        })
+        .id();
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 50.,
+                ..default()
+            },
+            EquippedTank(cylinder_id),
+            Depth(30.),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Events<BreathTaken>>()
+        .send(BreathTaken {
+            entity: breather_id,
+        });
+    app.update();
+    // the same breath takes four times the gas at 30 metres
+    let new_cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert_eq!(new_cylinder.amount_remaining, 800.);
+    let gas_to_circulate_events = app.world().resource::<Events<CirculateGas>>();
+    let mut gas_to_circulate_reader = gas_to_circulate_events.get_reader();
+    let gas_to_circulate = gas_to_circulate_reader
+        .read(gas_to_circulate_events)
+        .next()
+        .unwrap();
+    assert_eq!(gas_to_circulate.amount, 50.);
+}
+
+#[test]
+fn regulator_limits_low_tank() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.add_event::<CirculateGas>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, inhalation);
+    // 5 bar left in a 10 litre tank, with ambient at 1 bar
+    let cylinder_id = app
+        .world_mut()
+        .spawn((
+            DivingCylinder {
+                amount_remaining: 50.,
+                mix: GasMix {
+                    oxygen: 0.,
+                    nitrogen: 0.,
+                    helium: 0.,
+                },
+                ..default()
+            },
+            Regulator {
+                intermediate_pressure: 8.,
+                ..default()
+            },
+        ))
+        .id();
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamContent {
+                capacity: 20.,
+                amount_remaining: 0.,
+                ..default()
+            },
+            EquippedTank(cylinder_id),
+            Depth(0.),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Events<BreathTaken>>()
+        .send(BreathTaken {
+            entity: breather_id,
+        });
+    app.update();
+    // only half a breath should get through
+    let new_cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert_eq!(new_cylinder.amount_remaining, 40.);
+}
+
+#[test]
+fn cylinder_pressure() {
+    let air = DivingCylinder::new(12., 232., 232., GasMix::AIR);
+    assert!((air.pressure() - 232.).abs() < 1e-2);
+    // air is less compressible than an ideal gas at high pressure
+    assert!(air.capacity() < 12. * 232.);
+    let heliox = DivingCylinder::new(
+        12.,
+        232.,
+        232.,
+        GasMix {
+            oxygen: 0.2,
+            nitrogen: 0.,
+            helium: 0.8,
+        },
+    );
+    assert!(heliox.capacity() < air.capacity());
+    let empty = DivingCylinder::default();
+    assert_eq!(empty.pressure(), 0.);
+}
+
+#[test]
+fn cylinder_suitability() {
+    let sea = DiveSiteEnvironment::SEA;
+    let deco = DivingCylinder::new(10., 200., 200., GasMix::nitrox(0.5).unwrap());
+    assert_eq!(deco.suitability(&sea, 5.), GasSuitability::Breathable);
+    assert_eq!(deco.suitability(&sea, 17.), GasSuitability::Marginal);
+    assert_eq!(deco.suitability(&sea, 40.), GasSuitability::TooDeep);
+    let hypoxic = DivingCylinder::new(10., 200., 200., GasMix::trimix(0.1, 0.7).unwrap());
+    assert_eq!(hypoxic.suitability(&sea, 0.), GasSuitability::TooShallow);
+    assert_eq!(hypoxic.suitability(&sea, 30.), GasSuitability::Breathable);
+    assert!(!hypoxic.suitability(&sea, 0.).is_safe());
+}
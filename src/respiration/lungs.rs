@@ -0,0 +1,276 @@
+use crate::environment::DiveSiteEnvironment;
+use crate::health::DamageEvent;
+use crate::position::*;
+use crate::respiration::circulation::usage::*;
+use crate::respiration::circulation::CirculateGas;
+use crate::respiration::freediving::LungVolume;
+#[cfg(test)]
+use crate::respiration::gas_mix::GasMix;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+
+// how far a held breath can expand before it injures the lungs, relative to when it was taken
+const PNEUMOTHORAX_EXPANSION: f32 = 1.15;
+const EMBOLISM_EXPANSION: f32 = 1.3;
+const PNEUMOTHORAX_DAMAGE: f32 = 30.;
+const EMBOLISM_DAMAGE: f32 = 50.;
+// damage per second while gas bubbles stay lodged in the arteries
+const EMBOLISM_DAMAGE_RATE: f32 = 1.;
+// extra breathing demand with a collapsed lung
+const PNEUMOTHORAX_DEMAND: f32 = 1.5;
+
+/// Ambient pressure the gas currently in the lungs was breathed in at, if it hasn't been breathed
+/// out yet.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct LungGas {
+    pub inhaled_pressure: Option<f32>,
+}
+
+impl LungGas {
+    /// Volume of the held breath at `depth` relative to when it was taken.
+    pub fn expansion(&self, environment: &DiveSiteEnvironment, depth: f32) -> f32 {
+        self.inhaled_pressure.map_or(1., |inhaled_pressure| {
+            inhaled_pressure / environment.ambient_pressure(depth)
+        })
+    }
+
+    /// Volume of the held breath at `depth`, in litres.
+    pub fn volume(
+        &self,
+        environment: &DiveSiteEnvironment,
+        lung_volume: &LungVolume,
+        depth: f32,
+    ) -> f32 {
+        lung_volume.0 * self.expansion(environment, depth)
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Pneumothorax;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct ArterialGasEmbolism;
+
+#[derive(Event)]
+pub struct BreathExhaled {
+    pub entity: Entity,
+}
+
+pub fn lungs_plugin(app: &mut App) {
+    app.add_event::<BreathExhaled>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            fill_lungs,
+            empty_lungs.after(fill_lungs),
+            pulmonary_barotrauma.after(empty_lungs).after(update_depth),
+            arterial_gas_embolism,
+            pneumothorax.after(reset_breathing_demand).before(usage),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<LungGas>();
+    app.register_type::<Pneumothorax>();
+    app.register_type::<ArterialGasEmbolism>();
+}
+
+/// Only gas that was actually breathed in fills the lungs, so trying to breathe with nothing to
+/// breathe from doesn't count.
+pub fn fill_lungs(
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<(&mut LungGas, &Depth)>,
+    mut gases_to_circulate: EventReader<CirculateGas>,
+) {
+    for gas_to_circulate in gases_to_circulate.read() {
+        if let Ok((mut lung_gas, depth)) = breathers.get_mut(gas_to_circulate.entity) {
+            lung_gas.inhaled_pressure = Some(environment.ambient_pressure(depth.0));
+        }
+    }
+}
+
+pub fn empty_lungs(mut breathers: Query<&mut LungGas>, mut exhales: EventReader<BreathExhaled>) {
+    for exhale in exhales.read() {
+        if let Ok(mut lung_gas) = breathers.get_mut(exhale.entity) {
+            lung_gas.inhaled_pressure = None;
+        }
+    }
+}
+
+#[test]
+fn did_fill_and_empty_lungs() {
+    let mut app = App::new();
+    app.add_event::<CirculateGas>();
+    app.add_event::<BreathExhaled>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, (fill_lungs, empty_lungs.after(fill_lungs)));
+    let breather_id = app.world_mut().spawn((LungGas::default(), Depth(20.))).id();
+    app.world_mut()
+        .resource_mut::<Events<CirculateGas>>()
+        .send(CirculateGas {
+            entity: breather_id,
+            amount: 50.,
+            mix: GasMix::AIR,
+        });
+    app.update();
+    let lung_gas = app.world().get::<LungGas>(breather_id).unwrap();
+    assert_eq!(lung_gas.inhaled_pressure, Some(3.));
+    let sea = DiveSiteEnvironment::SEA;
+    assert_eq!(lung_gas.expansion(&sea, 0.), 3.);
+    assert_eq!(lung_gas.volume(&sea, &LungVolume(6.), 0.), 18.);
+    app.world_mut()
+        .resource_mut::<Events<BreathExhaled>>()
+        .send(BreathExhaled {
+            entity: breather_id,
+        });
+    app.update();
+    let lung_gas = app.world().get::<LungGas>(breather_id).unwrap();
+    assert_eq!(lung_gas.inhaled_pressure, None);
+    assert_eq!(lung_gas.expansion(&sea, 0.), 1.);
+}
+
+/// Ascending with a held breath overexpands the lungs, tearing them and forcing gas into the
+/// chest cavity or the arteries.
+pub fn pulmonary_barotrauma(
+    mut commands: Commands,
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<(Entity, &mut LungGas, &Depth)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, mut lung_gas, depth) in &mut breathers {
+        let expansion = lung_gas.expansion(&environment, depth.0);
+        if expansion > EMBOLISM_EXPANSION {
+            println!("arterial gas embolism, lung expansion: {}", expansion);
+            commands.entity(entity).insert(ArterialGasEmbolism);
+            damage_events.send(DamageEvent {
+                target: entity,
+                damage: EMBOLISM_DAMAGE,
+            });
+        } else if expansion > PNEUMOTHORAX_EXPANSION {
+            println!("pneumothorax, lung expansion: {}", expansion);
+            commands.entity(entity).insert(Pneumothorax);
+            damage_events.send(DamageEvent {
+                target: entity,
+                damage: PNEUMOTHORAX_DAMAGE,
+            });
+        } else {
+            continue;
+        }
+        // the torn lung lets the held breath escape
+        lung_gas.inhaled_pressure = None;
+    }
+}
+
+#[test]
+fn did_pneumothorax() {
+    let mut app = App::new();
+    app.add_event::<DamageEvent>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, pulmonary_barotrauma);
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            LungGas {
+                inhaled_pressure: Some(DiveSiteEnvironment::SEA.ambient_pressure(10.)),
+            },
+            Depth(7.),
+        ))
+        .id();
+    app.update();
+    assert!(app.world().get::<Pneumothorax>(breather_id).is_some());
+    assert!(app
+        .world()
+        .get::<ArterialGasEmbolism>(breather_id)
+        .is_none());
+    let lung_gas = app.world().get::<LungGas>(breather_id).unwrap();
+    assert_eq!(lung_gas.inhaled_pressure, None);
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damage = damage_reader.read(damage_events).next().unwrap();
+    assert_eq!(damage.target, breather_id);
+    assert_eq!(damage.damage, PNEUMOTHORAX_DAMAGE);
+}
+
+#[test]
+fn did_arterial_gas_embolism() {
+    let mut app = App::new();
+    app.add_event::<DamageEvent>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, pulmonary_barotrauma);
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            LungGas {
+                inhaled_pressure: Some(DiveSiteEnvironment::SEA.ambient_pressure(10.)),
+            },
+            Depth(0.),
+        ))
+        .id();
+    app.update();
+    assert!(app
+        .world()
+        .get::<ArterialGasEmbolism>(breather_id)
+        .is_some());
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damage = damage_reader.read(damage_events).next().unwrap();
+    assert_eq!(damage.damage, EMBOLISM_DAMAGE);
+}
+
+#[test]
+fn exhaled_ascent_is_safe() {
+    let mut app = App::new();
+    app.add_event::<DamageEvent>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, pulmonary_barotrauma);
+    let exhaled_id = app.world_mut().spawn((LungGas::default(), Depth(0.))).id();
+    // a small ascent is within what the lungs can take
+    let held_id = app
+        .world_mut()
+        .spawn((
+            LungGas {
+                inhaled_pressure: Some(DiveSiteEnvironment::SEA.ambient_pressure(10.)),
+            },
+            Depth(9.),
+        ))
+        .id();
+    app.update();
+    assert!(app.world().get::<Pneumothorax>(exhaled_id).is_none());
+    assert!(app.world().get::<Pneumothorax>(held_id).is_none());
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    assert!(damage_events.is_empty());
+}
+
+pub fn arterial_gas_embolism(
+    time: Res<Time>,
+    embolised: Query<Entity, With<ArterialGasEmbolism>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for entity in &embolised {
+        damage_events.send(DamageEvent {
+            target: entity,
+            damage: EMBOLISM_DAMAGE_RATE * time.delta_seconds(),
+        });
+    }
+}
+
+pub fn pneumothorax(mut breathers: Query<&mut BreathingDemand, With<Pneumothorax>>) {
+    for mut breathing_demand in &mut breathers {
+        breathing_demand.0 *= PNEUMOTHORAX_DEMAND;
+    }
+}
+
+#[test]
+fn pneumothorax_raises_demand() {
+    let mut app = App::new();
+    app.add_systems(Update, pneumothorax);
+    let breather_id = app
+        .world_mut()
+        .spawn((BreathingDemand::default(), Pneumothorax))
+        .id();
+    app.update();
+    let breathing_demand = app.world().get::<BreathingDemand>(breather_id).unwrap();
+    assert_eq!(breathing_demand.0, PNEUMOTHORAX_DEMAND);
+}
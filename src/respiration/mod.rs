@@ -1,28 +1,112 @@
 use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
+pub mod air_sharing;
+pub mod bcd;
+pub mod breathing;
+pub mod chamber;
 pub mod circulation;
+pub mod ear;
+pub mod fill_station;
+pub mod freediving;
+pub mod gas_mix;
 pub mod inhalation;
+pub mod lungs;
+pub mod planner;
+pub mod rebreather;
+pub mod regulator;
+pub mod stops;
+pub mod stress;
+pub mod surface_interval;
+pub mod thermal;
 
 use crate::circulation::*;
-use crate::position::Depth;
-use crate::respiration::decompression::GasExchangeInLungs;
+use crate::position::{Depth, WaterTemperature};
+use crate::respiration::carbon_dioxide::CarbonDioxide;
+use crate::respiration::decompression::{DcsRisk, TissueCompartments};
 use crate::respiration::equalization::BloodstreamPressure;
+use crate::respiration::impairment::Impairment;
 use crate::respiration::nitrogen::NitrogenHazard;
-use crate::respiration::oxygen::OxygenHazard;
-use crate::respiration::usage::GasUsageRate;
+use crate::respiration::oxygen::{OxygenExposure, OxygenHazard};
+use crate::respiration::usage::{BreathingDemand, GasUsageRate};
+use air_sharing::*;
+use bcd::*;
+use breathing::*;
+use chamber::*;
+use ear::*;
+use fill_station::*;
+use freediving::*;
 use inhalation::*;
+use lungs::*;
+use rebreather::*;
+use regulator::*;
+use stops::*;
+use stress::*;
+use surface_interval::*;
+use thermal::*;
 
 #[derive(Bundle, Default)]
 pub struct BreatherBundle {
     pub depth: Depth,
+    pub water_temperature: WaterTemperature,
+    pub core_temperature: CoreTemperature,
     pub bloodstream_content: BloodstreamContent,
     pub bloodstream_pressure: BloodstreamPressure,
     pub gas_usage_rate: GasUsageRate,
+    pub breathing_demand: BreathingDemand,
+    pub carbon_dioxide: CarbonDioxide,
     pub oxygen_hazard: OxygenHazard,
+    pub oxygen_exposure: OxygenExposure,
     pub nitrogen_hazard: NitrogenHazard,
-    pub gas_exchange_in_lungs: GasExchangeInLungs,
+    pub lung_volume: LungVolume,
+    pub lung_gas: LungGas,
+    pub blackout_po2: BlackoutPo2,
+    pub impairment: Impairment,
+    pub stress: Stress,
+}
+
+/// Inert gas loading and the dive log, for breathers that can get bent. Marine mammals go
+/// without, as their lungs collapse at depth and stop them taking up nitrogen.
+#[derive(Bundle, Default)]
+pub struct DecompressionBundle {
+    pub tissue_compartments: TissueCompartments,
+    pub dcs_risk: DcsRisk,
+    pub decompression_schedule: DecompressionSchedule,
+    pub dive_session: DiveSession,
+}
+
+/// Rolls for the chance hazards of breathing underwater, seeded in tests so they always come out
+/// the same.
+#[derive(Resource)]
+pub struct HazardRng(pub StdRng);
+
+impl Default for HazardRng {
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
 }
 
 pub fn respiration_plugin(app: &mut App) {
-    app.add_plugins((inhalation_plugin, circulation_plugin));
+    app.init_resource::<HazardRng>();
+    app.add_plugins((
+        (
+            inhalation_plugin,
+            air_sharing_plugin,
+            bcd_plugin,
+            breathing_plugin,
+            chamber_plugin,
+            fill_station_plugin,
+            rebreather_plugin,
+            freediving_plugin,
+        ),
+        circulation_plugin,
+        ear_plugin,
+        lungs_plugin,
+        regulator_plugin,
+        stops_plugin,
+        stress_plugin,
+        surface_interval_plugin,
+        thermal_plugin,
+    ));
 }
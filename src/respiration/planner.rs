@@ -0,0 +1,302 @@
+//! Decompression calculations that only depend on tissue state, so that anything (UI, AI, level
+//! scripts) can ask what a diver's ascent would look like without going through the ECS.
+
+use crate::environment::DiveSiteEnvironment;
+use crate::respiration::circulation::decompression::TissueCompartments;
+use crate::respiration::gas_mix::GasMix;
+use bevy::reflect::Reflect;
+
+pub const ASCENT_RATE_M_PER_MIN: f32 = 9.;
+pub const STOP_INTERVAL_M: f32 = 3.;
+pub const MAX_NO_DECOMPRESSION_LIMIT_MIN: f32 = 99.;
+const MAX_TIME_TO_SURFACE_MIN: f32 = 24. * 60.;
+const ASCENT_STEP_MIN: f32 = 0.1;
+// depth, in metres, that residual nitrogen time is worked out for, as on repetitive dive tables
+const REPETITIVE_REFERENCE_DEPTH: f32 = 18.;
+
+#[derive(Debug, PartialEq)]
+pub struct AscentPlan {
+    /// Minutes that can still be spent at the current depth before a stop is required, or
+    /// `None` if the diver already has a decompression obligation.
+    pub no_decompression_limit: Option<f32>,
+    /// Shallowest depth (in metres) the diver can currently ascend to.
+    pub ceiling: f32,
+    /// Depth (in metres) of the first required stop, if any.
+    pub first_stop: Option<f32>,
+    /// Minutes needed to reach the surface, including ascent and stops.
+    pub time_to_surface: f32,
+}
+
+/// A decompression stop the diver has to hold before ascending further.
+#[derive(Clone, Debug, PartialEq, Reflect)]
+pub struct Stop {
+    /// Depth of the stop, in metres.
+    pub depth: f32,
+    /// Minutes to spend at the stop.
+    pub minutes: f32,
+}
+
+pub fn ceiling(environment: &DiveSiteEnvironment, tissues: &TissueCompartments) -> f32 {
+    environment
+        .depth_at_pressure(tissues.tolerated_ambient_pressure())
+        .max(0.)
+}
+
+pub fn can_surface(environment: &DiveSiteEnvironment, tissues: &TissueCompartments) -> bool {
+    ceiling(environment, tissues) <= 0.
+}
+
+fn stop_depth(ceiling: f32) -> f32 {
+    (ceiling / STOP_INTERVAL_M).ceil() * STOP_INTERVAL_M
+}
+
+pub fn no_decompression_limit(
+    environment: &DiveSiteEnvironment,
+    tissues: &TissueCompartments,
+    depth: f32,
+    mix: &GasMix,
+) -> Option<f32> {
+    if !can_surface(environment, tissues) {
+        return None;
+    }
+    let mut tissues = tissues.clone();
+    let mut minutes = 0.;
+    while minutes < MAX_NO_DECOMPRESSION_LIMIT_MIN {
+        tissues.load(environment.ambient_pressure(depth), mix, 60.);
+        if !can_surface(environment, &tissues) {
+            break;
+        }
+        minutes += 1.;
+    }
+    Some(minutes)
+}
+
+/// Minutes of no-decompression time at `REPETITIVE_REFERENCE_DEPTH` on air already used up by
+/// gas left over from earlier dives, compared with tissues saturated at the site's surface.
+pub fn residual_nitrogen_time(
+    environment: &DiveSiteEnvironment,
+    tissues: &TissueCompartments,
+) -> f32 {
+    let clean = no_decompression_limit(
+        environment,
+        &TissueCompartments::saturated(environment.surface_pressure, &GasMix::AIR),
+        REPETITIVE_REFERENCE_DEPTH,
+        &GasMix::AIR,
+    )
+    .unwrap_or(0.);
+    let residual = no_decompression_limit(
+        environment,
+        tissues,
+        REPETITIVE_REFERENCE_DEPTH,
+        &GasMix::AIR,
+    )
+    .unwrap_or(0.);
+    clean - residual
+}
+
+/// Loads the tissues for an ascent at `ASCENT_RATE_M_PER_MIN` and returns its duration.
+fn ascend(
+    environment: &DiveSiteEnvironment,
+    tissues: &mut TissueCompartments,
+    from: f32,
+    to: f32,
+    mix: &GasMix,
+) -> f32 {
+    let duration = (from - to) / ASCENT_RATE_M_PER_MIN;
+    let steps = (duration / ASCENT_STEP_MIN).ceil().max(1.);
+    for step in 0..steps as usize {
+        let progress = (step as f32 + 0.5) / steps;
+        let depth = from + (to - from) * progress;
+        tissues.load(
+            environment.ambient_pressure(depth),
+            mix,
+            duration * 60. / steps,
+        );
+    }
+    duration
+}
+
+pub fn plan_ascent(
+    environment: &DiveSiteEnvironment,
+    tissues: &TissueCompartments,
+    depth: f32,
+    mix: &GasMix,
+) -> AscentPlan {
+    let current_ceiling = ceiling(environment, tissues);
+    let no_decompression_limit = no_decompression_limit(environment, tissues, depth, mix);
+    let first_stop = Some(stop_depth(current_ceiling)).filter(|stop| *stop > 0.);
+
+    let mut tissues = tissues.clone();
+    let mut current_depth = depth;
+    let mut time_to_surface = 0.;
+    while current_depth > 0. && time_to_surface < MAX_TIME_TO_SURFACE_MIN {
+        let next_stop = stop_depth(ceiling(environment, &tissues));
+        if next_stop < current_depth {
+            time_to_surface += ascend(environment, &mut tissues, current_depth, next_stop, mix);
+            current_depth = next_stop;
+        } else {
+            tissues.load(environment.ambient_pressure(current_depth), mix, 60.);
+            time_to_surface += 1.;
+        }
+    }
+
+    AscentPlan {
+        no_decompression_limit,
+        ceiling: current_ceiling,
+        first_stop,
+        time_to_surface,
+    }
+}
+
+/// The stops needed to reach the surface from `depth`, deepest first. A diver who is already
+/// shallower than their ceiling is told to go back down to the first stop.
+pub fn stop_schedule(
+    environment: &DiveSiteEnvironment,
+    tissues: &TissueCompartments,
+    depth: f32,
+    mix: &GasMix,
+) -> Vec<Stop> {
+    let mut tissues = tissues.clone();
+    let mut stops: Vec<Stop> = vec![];
+    let mut current_depth = depth.max(stop_depth(ceiling(environment, &tissues)));
+    let mut elapsed = 0.;
+    while current_depth > 0. && elapsed < MAX_TIME_TO_SURFACE_MIN {
+        let next_stop = stop_depth(ceiling(environment, &tissues));
+        if next_stop < current_depth {
+            elapsed += ascend(environment, &mut tissues, current_depth, next_stop, mix);
+            current_depth = next_stop;
+        } else {
+            tissues.load(environment.ambient_pressure(current_depth), mix, 60.);
+            elapsed += 1.;
+            match stops.last_mut() {
+                Some(stop) if stop.depth == current_depth => stop.minutes += 1.,
+                _ => stops.push(Stop {
+                    depth: current_depth,
+                    minutes: 1.,
+                }),
+            }
+        }
+    }
+    stops
+}
+
+/// Minutes `amount` of gas lasts at `depth` when breathed at `usage_rate` litres per minute.
+pub fn gas_time_remaining(
+    environment: &DiveSiteEnvironment,
+    amount: f32,
+    usage_rate: f32,
+    depth: f32,
+) -> f32 {
+    amount / (usage_rate * environment.ambient_pressure(depth))
+}
+
+#[test]
+fn gas_lasts_less_at_depth() {
+    let sea = DiveSiteEnvironment::SEA;
+    assert_eq!(gas_time_remaining(&sea, 1000., 20., 0.), 50.);
+    assert_eq!(gas_time_remaining(&sea, 1000., 20., 30.), 12.5);
+}
+
+#[test]
+fn surface_plan() {
+    let sea = DiveSiteEnvironment::SEA;
+    let plan = plan_ascent(&sea, &TissueCompartments::default(), 0., &GasMix::AIR);
+    assert_eq!(plan.ceiling, 0.);
+    assert_eq!(plan.first_stop, None);
+    assert_eq!(plan.time_to_surface, 0.);
+}
+
+#[test]
+fn air_no_decompression_limits() {
+    let sea = DiveSiteEnvironment::SEA;
+    let tissues = TissueCompartments::default();
+    assert_eq!(
+        no_decompression_limit(&sea, &tissues, 18., &GasMix::AIR),
+        Some(59.)
+    );
+    assert_eq!(
+        no_decompression_limit(&sea, &tissues, 30., &GasMix::AIR),
+        Some(16.)
+    );
+    assert_eq!(
+        no_decompression_limit(&sea, &tissues, 40., &GasMix::AIR),
+        Some(8.)
+    );
+    // no limit is reported beyond the cap
+    assert_eq!(
+        no_decompression_limit(&sea, &tissues, 3., &GasMix::AIR),
+        Some(MAX_NO_DECOMPRESSION_LIMIT_MIN)
+    );
+}
+
+#[test]
+fn altitude_no_decompression_limits() {
+    let sea = DiveSiteEnvironment::SEA;
+    let mountain_lake = DiveSiteEnvironment::lake(3000.);
+    let acclimatised = TissueCompartments::saturated(mountain_lake.surface_pressure, &GasMix::AIR);
+    // the same depth leaves less time where the surface is at a lower pressure
+    assert!(
+        no_decompression_limit(&mountain_lake, &acclimatised, 18., &GasMix::AIR)
+            < no_decompression_limit(&sea, &TissueCompartments::default(), 18., &GasMix::AIR)
+    );
+}
+
+#[test]
+fn no_decompression_ascent() {
+    let sea = DiveSiteEnvironment::SEA;
+    let mut tissues = TissueCompartments::default();
+    tissues.load(sea.ambient_pressure(30.), &GasMix::AIR, 10. * 60.);
+    let plan = plan_ascent(&sea, &tissues, 30., &GasMix::AIR);
+    assert_eq!(plan.no_decompression_limit, Some(6.));
+    assert_eq!(plan.first_stop, None);
+    assert!((plan.time_to_surface - 30. / ASCENT_RATE_M_PER_MIN).abs() < 1e-4);
+}
+
+#[test]
+fn decompression_ascent() {
+    let sea = DiveSiteEnvironment::SEA;
+    // an hour at 30 metres on air
+    let mut tissues = TissueCompartments::default();
+    tissues.load(sea.ambient_pressure(30.), &GasMix::AIR, 60. * 60.);
+    assert!(!can_surface(&sea, &tissues));
+    let plan = plan_ascent(&sea, &tissues, 30., &GasMix::AIR);
+    assert_eq!(plan.no_decompression_limit, None);
+    assert!((plan.ceiling - 6.46).abs() < 0.01);
+    assert_eq!(plan.first_stop, Some(9.));
+    assert!((plan.time_to_surface - 48.33).abs() <= 1.);
+}
+
+#[test]
+fn decompression_stops() {
+    let sea = DiveSiteEnvironment::SEA;
+    let mut tissues = TissueCompartments::default();
+    tissues.load(sea.ambient_pressure(30.), &GasMix::AIR, 60. * 60.);
+    let stops = stop_schedule(&sea, &tissues, 30., &GasMix::AIR);
+    assert_eq!(stops[0].depth, 9.);
+    assert_eq!(stops.last().unwrap().depth, STOP_INTERVAL_M);
+    assert!(stops.windows(2).all(|pair| pair[0].depth > pair[1].depth));
+    // stops and the ascent between them should add up to the time to surface
+    let stop_minutes: f32 = stops.iter().map(|stop| stop.minutes).sum();
+    let plan = plan_ascent(&sea, &tissues, 30., &GasMix::AIR);
+    assert!((stop_minutes + 30. / ASCENT_RATE_M_PER_MIN - plan.time_to_surface).abs() < 0.1);
+    // the same stops are required after shooting up past them
+    assert_eq!(stop_schedule(&sea, &tissues, 0., &GasMix::AIR)[0].depth, 9.);
+    assert!(stop_schedule(&sea, &TissueCompartments::default(), 30., &GasMix::AIR).is_empty());
+}
+
+#[test]
+fn repetitive_dive_residual_nitrogen() {
+    let sea = DiveSiteEnvironment::SEA;
+    assert_eq!(
+        residual_nitrogen_time(&sea, &TissueCompartments::default()),
+        0.
+    );
+    let mut tissues = TissueCompartments::default();
+    tissues.load(sea.ambient_pressure(18.), &GasMix::AIR, 30. * 60.);
+    tissues.load(sea.ambient_pressure(0.), &GasMix::AIR, 30. * 60.);
+    let short_interval = residual_nitrogen_time(&sea, &tissues);
+    assert!(short_interval > 0.);
+    // a longer surface interval leaves less behind
+    tissues.load(sea.ambient_pressure(0.), &GasMix::AIR, 3. * 60. * 60.);
+    assert!(residual_nitrogen_time(&sea, &tissues) < short_interval);
+}
@@ -0,0 +1,479 @@
+use crate::circulation::carbon_dioxide::CarbonDioxide;
+use crate::circulation::CirculateGas;
+use crate::environment::DiveSiteEnvironment;
+use crate::position::Depth;
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::inhalation::*;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+
+const REBREATHER_WIDTH: f32 = 4.;
+const REBREATHER_HEIGHT: f32 = 4.;
+
+const DEFAULT_SETPOINT: f32 = 1.3;
+// litres of gas the loop holds at the surface, about a lungful with the counterlung
+const DEFAULT_LOOP_VOLUME: f32 = 6.;
+pub const DEFAULT_SCRUBBER_DURATION: f32 = 3. * 60. * 60.;
+const REBREATHER_CYLINDER_WATER_VOLUME: f32 = 3.;
+const REBREATHER_CYLINDER_PRESSURE: f32 = 200.;
+// part of each breath that is oxygen consumed by the body rather than exhaled back into the loop
+const METABOLISED_PROPORTION: f32 = 0.05;
+// the solenoid can't raise the loop above this proportion of oxygen
+const MAX_LOOP_OXYGEN: f32 = 0.99;
+// rise in the wearer's arterial carbon dioxide, in mmHg, for each proportion of it in the loop
+const LOOP_CARBON_DIOXIDE_MMHG: f32 = 750.;
+
+/// A closed circuit rebreather. `loop_content` is the amount of gas currently in the loop, which
+/// the wearer breathes from instead of an `EquippedTank`.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Rebreather {
+    pub diluent: Entity,
+    pub oxygen: Entity,
+    pub setpoint: f32,
+    pub loop_volume: f32,
+    pub loop_content: f32,
+    pub loop_mix: GasMix,
+    pub carbon_dioxide: f32,
+    pub scrubber_remaining: f32,
+}
+
+impl Rebreather {
+    pub fn new(diluent: Entity, oxygen: Entity) -> Self {
+        Self {
+            diluent,
+            oxygen,
+            setpoint: DEFAULT_SETPOINT,
+            loop_volume: DEFAULT_LOOP_VOLUME,
+            loop_content: DEFAULT_LOOP_VOLUME,
+            loop_mix: GasMix::AIR,
+            carbon_dioxide: 0.,
+            scrubber_remaining: DEFAULT_SCRUBBER_DURATION,
+        }
+    }
+
+    pub fn loop_po2(&self, ambient_pressure: f32) -> f32 {
+        self.loop_mix.oxygen * ambient_pressure
+    }
+
+    fn add_gas(&mut self, mix: &GasMix, amount: f32) {
+        self.loop_mix = self.loop_mix.blend(self.loop_content, mix, amount);
+        // fresh gas dilutes whatever carbon dioxide is in the loop
+        self.carbon_dioxide *= self.loop_content / (self.loop_content + amount).max(f32::EPSILON);
+        self.loop_content += amount;
+    }
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct EquippedRebreather(pub Entity);
+
+pub fn rebreather_plugin(app: &mut App) {
+    app.add_systems(Startup, spawn_rebreathers);
+    app.add_systems(
+        FixedUpdate,
+        (
+            rebreather_loop,
+            rebreather_inhalation.after(rebreather_loop),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Rebreather>();
+    app.register_type::<EquippedRebreather>();
+}
+
+pub fn spawn_rebreathers(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    println!("Spawning rebreathers");
+
+    let mut spawn_rebreather = |x: f32, y: f32, diluent_mix: GasMix| {
+        let mesh = Mesh::from(Rectangle::new(REBREATHER_WIDTH, REBREATHER_HEIGHT));
+        let material = ColorMaterial::from_color(Srgba::rgb(0.8, 0.8, 0.));
+
+        let mesh_handle = meshes.add(mesh);
+        let material_handle = materials.add(material);
+
+        let diluent_id = commands
+            .spawn((
+                DivingCylinder::new(
+                    REBREATHER_CYLINDER_WATER_VOLUME,
+                    REBREATHER_CYLINDER_PRESSURE,
+                    REBREATHER_CYLINDER_PRESSURE,
+                    diluent_mix,
+                ),
+                Name::new(format!("{} diluent cylinder", diluent_mix.name())),
+            ))
+            .id();
+        let oxygen_id = commands
+            .spawn((
+                DivingCylinder::new(
+                    REBREATHER_CYLINDER_WATER_VOLUME,
+                    REBREATHER_CYLINDER_PRESSURE,
+                    REBREATHER_CYLINDER_PRESSURE,
+                    GasMix::OXYGEN,
+                ),
+                Name::new("Oxygen cylinder"),
+            ))
+            .id();
+
+        commands.spawn((
+            Rebreather::new(diluent_id, oxygen_id),
+            crate::collision::RectangularHitbox(Rectangle::new(
+                REBREATHER_WIDTH,
+                REBREATHER_HEIGHT,
+            )),
+            MaterialMesh2dBundle {
+                mesh: mesh_handle.into(),
+                material: material_handle,
+                transform: Transform::from_translation(Vec3::new(x, y, 0.)),
+                ..default()
+            },
+            crate::bag::Collectible,
+            Name::new(format!("Rebreather ({} diluent)", diluent_mix.name())),
+            crate::PIXEL_PERFECT_LAYERS,
+        ));
+    };
+
+    spawn_rebreather(-40., 0., GasMix::AIR);
+}
+
+/// Keeps the loop full and at its setpoint: diluent makes up the volume lost as the loop is
+/// compressed, the solenoid injects oxygen when the loop PO2 falls below the setpoint, any excess
+/// is vented, and a working scrubber takes the carbon dioxide out.
+pub fn rebreather_loop(
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    wearers: Query<(&EquippedRebreather, &Depth)>,
+    mut rebreathers: Query<&mut Rebreather>,
+    mut cylinders: Query<&mut DivingCylinder>,
+) {
+    for (equipped_rebreather, depth) in &wearers {
+        if let Ok(mut rebreather) = rebreathers.get_mut(equipped_rebreather.0) {
+            let pressure = environment.ambient_pressure(depth.0);
+            rebreather.scrubber_remaining =
+                (rebreather.scrubber_remaining - time.delta_seconds()).max(0.);
+            if rebreather.scrubber_remaining > 0. {
+                rebreather.carbon_dioxide = 0.;
+            }
+
+            let full_loop = rebreather.loop_volume * pressure;
+            if rebreather.loop_content < full_loop {
+                if let Ok(mut diluent) = cylinders.get_mut(rebreather.diluent) {
+                    let added = (full_loop - rebreather.loop_content).min(diluent.amount_remaining);
+                    diluent.amount_remaining -= added;
+                    rebreather.add_gas(&diluent.mix, added);
+                }
+            }
+
+            let target_oxygen = (rebreather.setpoint / pressure).min(MAX_LOOP_OXYGEN);
+            if rebreather.loop_mix.oxygen < target_oxygen {
+                if let Ok(mut oxygen) = cylinders.get_mut(rebreather.oxygen) {
+                    // the injected oxygen pushes as much loop gas out through the vent
+                    let needed = rebreather.loop_content
+                        * (target_oxygen - rebreather.loop_mix.oxygen)
+                        / (oxygen.mix.oxygen - rebreather.loop_mix.oxygen).max(f32::EPSILON);
+                    let added = needed.min(oxygen.amount_remaining);
+                    oxygen.amount_remaining -= added;
+                    rebreather.loop_content -= added;
+                    rebreather.add_gas(&oxygen.mix, added);
+                }
+            }
+
+            rebreather.loop_content = rebreather.loop_content.min(full_loop);
+        }
+    }
+}
+
+#[test]
+fn did_add_diluent_on_descent() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, rebreather_loop);
+    let diluent_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            amount_remaining: 1000.,
+            mix: GasMix::AIR,
+            ..default()
+        })
+        .id();
+    let oxygen_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            amount_remaining: 0.,
+            mix: GasMix::OXYGEN,
+            ..default()
+        })
+        .id();
+    let rebreather_id = app
+        .world_mut()
+        .spawn(Rebreather {
+            loop_content: 100.,
+            loop_volume: 100.,
+            ..Rebreather::new(diluent_id, oxygen_id)
+        })
+        .id();
+    app.world_mut()
+        .spawn((EquippedRebreather(rebreather_id), Depth(10.)));
+    app.update();
+    // the loop should have been topped up to twice its surface content
+    let rebreather = app.world().get::<Rebreather>(rebreather_id).unwrap();
+    assert_eq!(rebreather.loop_content, 200.);
+    let diluent = app.world().get::<DivingCylinder>(diluent_id).unwrap();
+    assert_eq!(diluent.amount_remaining, 900.);
+}
+
+#[test]
+fn did_inject_oxygen() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, rebreather_loop);
+    let diluent_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            amount_remaining: 1000.,
+            mix: GasMix::AIR,
+            ..default()
+        })
+        .id();
+    let oxygen_id = app
+        .world_mut()
+        .spawn(DivingCylinder {
+            amount_remaining: 1000.,
+            mix: GasMix::OXYGEN,
+            ..default()
+        })
+        .id();
+    let rebreather_id = app
+        .world_mut()
+        .spawn(Rebreather {
+            setpoint: 1.2,
+            loop_content: 300.,
+            loop_volume: 100.,
+            ..Rebreather::new(diluent_id, oxygen_id)
+        })
+        .id();
+    app.world_mut()
+        .spawn((EquippedRebreather(rebreather_id), Depth(20.)));
+    app.update();
+    // the loop PO2 should be held at the setpoint without overfilling the loop
+    let rebreather = app.world().get::<Rebreather>(rebreather_id).unwrap();
+    assert!((rebreather.loop_po2(3.) - 1.2).abs() < 1e-4);
+    assert_eq!(rebreather.loop_content, 300.);
+    let oxygen = app.world().get::<DivingCylinder>(oxygen_id).unwrap();
+    assert!(oxygen.amount_remaining < 1000.);
+}
+
+#[test]
+fn did_descend_on_diluent() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, rebreather_loop);
+    let diluent_id = app
+        .world_mut()
+        .spawn(DivingCylinder::new(
+            REBREATHER_CYLINDER_WATER_VOLUME,
+            REBREATHER_CYLINDER_PRESSURE,
+            REBREATHER_CYLINDER_PRESSURE,
+            GasMix::AIR,
+        ))
+        .id();
+    let oxygen_id = app
+        .world_mut()
+        .spawn(DivingCylinder::new(
+            REBREATHER_CYLINDER_WATER_VOLUME,
+            REBREATHER_CYLINDER_PRESSURE,
+            REBREATHER_CYLINDER_PRESSURE,
+            GasMix::OXYGEN,
+        ))
+        .id();
+    let rebreather_id = app
+        .world_mut()
+        .spawn(Rebreather::new(diluent_id, oxygen_id))
+        .id();
+    let wearer_id = app
+        .world_mut()
+        .spawn((EquippedRebreather(rebreather_id), Depth(0.)))
+        .id();
+    for depth in 0..=30 {
+        app.world_mut().get_mut::<Depth>(wearer_id).unwrap().0 = depth as f32;
+        app.update();
+    }
+    // a recreational descent only takes a small part of either cylinder
+    for cylinder_id in [diluent_id, oxygen_id] {
+        let cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+        assert!(cylinder.amount_remaining > 0.9 * cylinder.capacity());
+    }
+}
+
+#[test]
+fn did_scrub_carbon_dioxide() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, rebreather_loop);
+    let rebreather_id = app
+        .world_mut()
+        .spawn(Rebreather {
+            carbon_dioxide: 0.1,
+            ..Rebreather::new(Entity::PLACEHOLDER, Entity::PLACEHOLDER)
+        })
+        .id();
+    app.world_mut()
+        .spawn((EquippedRebreather(rebreather_id), Depth(0.)));
+    app.update();
+    let rebreather = app.world().get::<Rebreather>(rebreather_id).unwrap();
+    assert_eq!(rebreather.carbon_dioxide, 0.);
+}
+
+pub fn rebreather_inhalation(
+    mut breathers: Query<(
+        Entity,
+        &BloodstreamContent,
+        &EquippedRebreather,
+        Option<&mut CarbonDioxide>,
+    )>,
+    mut rebreathers: Query<&mut Rebreather>,
+    mut breaths: EventReader<BreathTaken>,
+    mut circulate_gas: EventWriter<CirculateGas>,
+) {
+    for breath in breaths.read() {
+        if let Ok((entity, bloodstream_content, equipped_rebreather, carbon_dioxide)) =
+            breathers.get_mut(breath.entity)
+        {
+            if let Ok(mut rebreather) = rebreathers.get_mut(equipped_rebreather.0) {
+                // everything but the metabolised oxygen is exhaled back into the loop, so a breath
+                // only needs the loop to have gas in it, not to hold the whole breath
+                let amount_breathed =
+                    bloodstream_content.capacity - bloodstream_content.amount_remaining;
+                if amount_breathed <= 0. || rebreather.loop_content <= 0. {
+                    continue;
+                }
+                let breathed_mix = rebreather.loop_mix;
+                let metabolised = (amount_breathed * METABOLISED_PROPORTION)
+                    .min(rebreather.loop_content * rebreather.loop_mix.oxygen);
+                let remaining_content = rebreather.loop_content - metabolised;
+                let scale = rebreather.loop_content / remaining_content.max(f32::EPSILON);
+                rebreather.loop_mix = GasMix {
+                    oxygen: (rebreather.loop_mix.oxygen * rebreather.loop_content - metabolised)
+                        / remaining_content.max(f32::EPSILON),
+                    nitrogen: rebreather.loop_mix.nitrogen * scale,
+                    helium: rebreather.loop_mix.helium * scale,
+                };
+                rebreather.loop_content = remaining_content;
+                if rebreather.scrubber_remaining <= 0. {
+                    // with nothing left to scrub it, the exhaled carbon dioxide stays in the loop
+                    rebreather.carbon_dioxide = (rebreather.carbon_dioxide
+                        + metabolised / rebreather.loop_content.max(f32::EPSILON))
+                    .min(1.);
+                }
+                if let Some(mut carbon_dioxide) = carbon_dioxide {
+                    carbon_dioxide.0 += LOOP_CARBON_DIOXIDE_MMHG * rebreather.carbon_dioxide;
+                }
+                println!(
+                    "amount breathed from loop: {}, loop remaining: {}",
+                    amount_breathed, rebreather.loop_content,
+                );
+                circulate_gas.send(CirculateGas {
+                    entity,
+                    amount: amount_breathed,
+                    mix: breathed_mix,
+                });
+            }
+        }
+    }
+}
+
+#[test]
+fn did_inhale_from_loop() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.add_event::<CirculateGas>();
+    app.add_systems(Update, rebreather_inhalation);
+    let rebreather_id = app
+        .world_mut()
+        .spawn(Rebreather {
+            loop_content: 100.,
+            loop_mix: GasMix::nitrox(0.5).unwrap(),
+            ..Rebreather::new(Entity::PLACEHOLDER, Entity::PLACEHOLDER)
+        })
+        .id();
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 50.,
+                mix: GasMix::AIR,
+            },
+            EquippedRebreather(rebreather_id),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Events<BreathTaken>>()
+        .send(BreathTaken {
+            entity: breather_id,
+        });
+    app.update();
+    // only the metabolised oxygen should have left the loop
+    let rebreather = app.world().get::<Rebreather>(rebreather_id).unwrap();
+    let metabolised = 50. * METABOLISED_PROPORTION;
+    assert!((rebreather.loop_content - (100. - metabolised)).abs() < 1e-4);
+    assert!((rebreather.loop_mix.oxygen - (50. - metabolised) / (100. - metabolised)).abs() < 1e-4);
+    assert_eq!(rebreather.carbon_dioxide, 0.);
+    // should have sent an event with the loop gas
+    let gas_to_circulate_events = app.world().resource::<Events<CirculateGas>>();
+    let mut gas_to_circulate_reader = gas_to_circulate_events.get_reader();
+    let gas_to_circulate = gas_to_circulate_reader
+        .read(gas_to_circulate_events)
+        .next()
+        .unwrap();
+    assert_eq!(gas_to_circulate.entity, breather_id);
+    assert_eq!(gas_to_circulate.amount, 50.);
+    assert_eq!(gas_to_circulate.mix.oxygen, 0.5);
+}
+
+#[test]
+fn exhausted_scrubber_retains_carbon_dioxide() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.add_event::<CirculateGas>();
+    app.add_systems(Update, rebreather_inhalation);
+    let rebreather_id = app
+        .world_mut()
+        .spawn(Rebreather {
+            scrubber_remaining: 0.,
+            ..Rebreather::new(Entity::PLACEHOLDER, Entity::PLACEHOLDER)
+        })
+        .id();
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            BloodstreamContent {
+                capacity: 100.,
+                amount_remaining: 0.,
+                mix: GasMix::AIR,
+            },
+            EquippedRebreather(rebreather_id),
+            CarbonDioxide::default(),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Events<BreathTaken>>()
+        .send(BreathTaken {
+            entity: breather_id,
+        });
+    app.update();
+    let rebreather = app.world().get::<Rebreather>(rebreather_id).unwrap();
+    assert!(rebreather.carbon_dioxide > 0.);
+    // the wearer should be rebreathing it
+    let carbon_dioxide = app.world().get::<CarbonDioxide>(breather_id).unwrap();
+    assert!(carbon_dioxide.0 > CarbonDioxide::default().0);
+}
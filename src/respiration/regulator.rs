@@ -0,0 +1,390 @@
+use crate::environment::DiveSiteEnvironment;
+use crate::position::Depth;
+use crate::respiration::air_sharing::{supplying_tank, SharingAir};
+use crate::respiration::circulation::usage::*;
+#[cfg(test)]
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::inhalation::*;
+use crate::respiration::HazardRng;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+#[cfg(test)]
+use rand::rngs::StdRng;
+use rand::Rng;
+#[cfg(test)]
+use rand::SeedableRng;
+
+// pressure above ambient the first stage delivers to the second stage, in bar
+const DEFAULT_INTERMEDIATE_PRESSURE: f32 = 9.5;
+// tank pressure above ambient, in bar, below which breathing starts getting harder
+const RESISTANCE_ONSET: f32 = 50.;
+// extra breathing demand when the tank is down to ambient pressure
+const MAX_RESISTANCE: f32 = 0.5;
+// surface litres per minute lost from a free-flowing regulator at the surface
+const FREE_FLOW_RATE: f32 = 150.;
+// chance per breath, for each bar of ambient pressure above the surface, that the first stage
+// freezes open
+const FREEZE_CHANCE: f32 = 0.0005;
+// seconds with the valve shut for a frozen first stage to thaw
+const THAW_SECONDS: f32 = 30.;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum RegulatorState {
+    #[default]
+    Working,
+    FreeFlowing,
+}
+
+/// The first and second stages fitted to a `DivingCylinder`, reducing tank pressure to something
+/// breathable.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Regulator {
+    pub intermediate_pressure: f32,
+    pub state: RegulatorState,
+    /// Shutting the cylinder valve stops a free-flow, and nothing can be breathed until it's
+    /// opened again.
+    pub valve_open: bool,
+    pub thaw: Timer,
+}
+
+impl Default for Regulator {
+    fn default() -> Self {
+        Self {
+            intermediate_pressure: DEFAULT_INTERMEDIATE_PRESSURE,
+            state: RegulatorState::Working,
+            valve_open: true,
+            thaw: Timer::from_seconds(THAW_SECONDS, TimerMode::Once),
+        }
+    }
+}
+
+impl Regulator {
+    /// Proportion of a full breath the regulator can deliver. It falls off once the tank can't
+    /// keep the intermediate pressure above ambient.
+    pub fn delivery(&self, tank_pressure: f32, ambient_pressure: f32) -> f32 {
+        if !self.valve_open {
+            return 0.;
+        }
+        ((tank_pressure - ambient_pressure) / self.intermediate_pressure).clamp(0., 1.)
+    }
+
+    /// Factor applied to breathing demand, which rises as the tank runs low.
+    pub fn resistance(&self, tank_pressure: f32, ambient_pressure: f32) -> f32 {
+        let headroom = (tank_pressure - ambient_pressure).max(0.);
+        1. + MAX_RESISTANCE * ((RESISTANCE_ONSET - headroom) / RESISTANCE_ONSET).max(0.)
+    }
+}
+
+/// Chance per breath at `depth` that the first stage freezes, as the gas expanding through it
+/// chills it. Deeper breaths move more gas through it.
+pub fn freeze_chance(environment: &DiveSiteEnvironment, depth: f32) -> f32 {
+    FREEZE_CHANCE * (environment.ambient_pressure(depth) - environment.surface_pressure)
+}
+
+#[derive(Event)]
+pub struct ToggleValve {
+    pub entity: Entity,
+}
+
+pub fn regulator_plugin(app: &mut App) {
+    app.add_event::<ToggleValve>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            regulator_breathing_resistance
+                .after(reset_breathing_demand)
+                .before(usage),
+            first_stage_freeze,
+            toggle_valve,
+            free_flow.after(toggle_valve),
+            thaw_regulator.after(toggle_valve),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Regulator>();
+}
+
+/// Breathing gets harder as the supplying tank runs low, whether it's the breather's own or a
+/// buddy's.
+pub fn regulator_breathing_resistance(
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<(Entity, &mut BreathingDemand, &Depth)>,
+    equipped_tanks: Query<&EquippedTank>,
+    sharers: Query<&SharingAir>,
+    cylinders: Query<(&DivingCylinder, &Regulator)>,
+) {
+    for (entity, mut breathing_demand, depth) in &mut breathers {
+        let Some(tank) = supplying_tank(entity, &equipped_tanks, &sharers) else {
+            continue;
+        };
+        if let Ok((cylinder, regulator)) = cylinders.get(tank) {
+            breathing_demand.0 *=
+                regulator.resistance(cylinder.pressure(), environment.ambient_pressure(depth.0));
+        }
+    }
+}
+
+#[test]
+fn did_resist_low_tank() {
+    let mut app = App::new();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, regulator_breathing_resistance);
+    let full_id = app
+        .world_mut()
+        .spawn((
+            DivingCylinder::new(10., 200., 200., GasMix::AIR),
+            Regulator::default(),
+        ))
+        .id();
+    let empty_id = app
+        .world_mut()
+        .spawn((DivingCylinder::default(), Regulator::default()))
+        .id();
+    let full_breather_id = app
+        .world_mut()
+        .spawn((BreathingDemand::default(), EquippedTank(full_id), Depth(0.)))
+        .id();
+    let empty_breather_id = app
+        .world_mut()
+        .spawn((
+            BreathingDemand::default(),
+            EquippedTank(empty_id),
+            Depth(0.),
+        ))
+        .id();
+    // breathing off the low tank's octopus, with a full tank of their own
+    let sharing_breather_id = app
+        .world_mut()
+        .spawn((
+            BreathingDemand::default(),
+            EquippedTank(full_id),
+            SharingAir {
+                donor: empty_breather_id,
+            },
+            Depth(0.),
+        ))
+        .id();
+    app.update();
+    let full = app
+        .world()
+        .get::<BreathingDemand>(full_breather_id)
+        .unwrap();
+    assert_eq!(full.0, 1.);
+    let empty = app
+        .world()
+        .get::<BreathingDemand>(empty_breather_id)
+        .unwrap();
+    assert_eq!(empty.0, 1. + MAX_RESISTANCE);
+    let sharing = app
+        .world()
+        .get::<BreathingDemand>(sharing_breather_id)
+        .unwrap();
+    assert_eq!(sharing.0, 1. + MAX_RESISTANCE);
+}
+
+pub fn first_stage_freeze(
+    environment: Res<DiveSiteEnvironment>,
+    mut rng: ResMut<HazardRng>,
+    breathers: Query<&Depth>,
+    equipped_tanks: Query<&EquippedTank>,
+    sharers: Query<&SharingAir>,
+    mut regulators: Query<&mut Regulator>,
+    mut breaths: EventReader<BreathTaken>,
+) {
+    for breath in breaths.read() {
+        let Some(tank) = supplying_tank(breath.entity, &equipped_tanks, &sharers) else {
+            continue;
+        };
+        if let Ok(depth) = breathers.get(breath.entity) {
+            if let Ok(mut regulator) = regulators.get_mut(tank) {
+                if regulator.state == RegulatorState::Working
+                    && regulator.valve_open
+                    && rng.0.gen::<f32>() < freeze_chance(&environment, depth.0)
+                {
+                    println!("regulator froze, depth: {}", depth.0);
+                    regulator.state = RegulatorState::FreeFlowing;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn did_not_freeze_at_surface() {
+    let mut app = App::new();
+    app.add_event::<BreathTaken>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.insert_resource(HazardRng(StdRng::seed_from_u64(0)));
+    app.add_systems(Update, first_stage_freeze);
+    let cylinder_id = app
+        .world_mut()
+        .spawn((DivingCylinder::default(), Regulator::default()))
+        .id();
+    let breather_id = app
+        .world_mut()
+        .spawn((EquippedTank(cylinder_id), Depth(0.)))
+        .id();
+    for _ in 0..100 {
+        app.world_mut()
+            .resource_mut::<Events<BreathTaken>>()
+            .send(BreathTaken {
+                entity: breather_id,
+            });
+        app.update();
+    }
+    let regulator = app.world().get::<Regulator>(cylinder_id).unwrap();
+    assert_eq!(regulator.state, RegulatorState::Working);
+    let sea = DiveSiteEnvironment::SEA;
+    assert!(freeze_chance(&sea, 30.) > freeze_chance(&sea, 10.));
+}
+
+/// A free-flowing regulator dumps gas whether or not anyone is breathing from it.
+pub fn free_flow(
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    breathers: Query<(&EquippedTank, &Depth)>,
+    mut cylinders: Query<(&mut DivingCylinder, &Regulator)>,
+) {
+    for (equipped_tank, depth) in &breathers {
+        if let Ok((mut cylinder, regulator)) = cylinders.get_mut(equipped_tank.0) {
+            if regulator.state == RegulatorState::FreeFlowing && regulator.valve_open {
+                let lost =
+                    FREE_FLOW_RATE * environment.ambient_pressure(depth.0) * time.delta_seconds()
+                        / 60.;
+                cylinder.amount_remaining = (cylinder.amount_remaining - lost).max(0.);
+            }
+        }
+    }
+}
+
+#[test]
+fn did_free_flow() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, free_flow);
+    let cylinder_id = app
+        .world_mut()
+        .spawn((
+            DivingCylinder {
+                amount_remaining: 1000.,
+                ..default()
+            },
+            Regulator {
+                state: RegulatorState::FreeFlowing,
+                ..default()
+            },
+        ))
+        .id();
+    app.world_mut()
+        .spawn((EquippedTank(cylinder_id), Depth(10.)));
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
+    app.update();
+    let cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert!((cylinder.amount_remaining - (1000. - FREE_FLOW_RATE * 2. / 60.)).abs() < 1e-3);
+}
+
+#[test]
+fn regulator_delivery() {
+    let regulator = Regulator::default();
+    assert_eq!(regulator.delivery(200., 1.), 1.);
+    assert_eq!(regulator.delivery(1., 1.), 0.);
+    assert!((regulator.delivery(5.75, 1.) - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn did_not_deliver_with_valve_shut() {
+    let regulator = Regulator {
+        valve_open: false,
+        ..default()
+    };
+    assert_eq!(regulator.delivery(200., 1.), 0.);
+}
+
+/// Opens or shuts the valve of the wearer's equipped cylinder.
+pub fn toggle_valve(
+    wearers: Query<&EquippedTank>,
+    mut regulators: Query<&mut Regulator>,
+    mut toggles: EventReader<ToggleValve>,
+) {
+    for toggle in toggles.read() {
+        if let Ok(equipped_tank) = wearers.get(toggle.entity) {
+            if let Ok(mut regulator) = regulators.get_mut(equipped_tank.0) {
+                regulator.valve_open = !regulator.valve_open;
+            }
+        }
+    }
+}
+
+#[test]
+fn did_toggle_valve() {
+    let mut app = App::new();
+    app.add_event::<ToggleValve>();
+    app.add_systems(Update, toggle_valve);
+    let cylinder_id = app
+        .world_mut()
+        .spawn((DivingCylinder::default(), Regulator::default()))
+        .id();
+    let wearer_id = app.world_mut().spawn(EquippedTank(cylinder_id)).id();
+    app.world_mut()
+        .resource_mut::<Events<ToggleValve>>()
+        .send(ToggleValve { entity: wearer_id });
+    app.update();
+    let regulator = app.world().get::<Regulator>(cylinder_id).unwrap();
+    assert!(!regulator.valve_open);
+}
+
+/// A frozen first stage thaws once the valve is shut and the gas stops chilling it.
+pub fn thaw_regulator(time: Res<Time>, mut regulators: Query<&mut Regulator>) {
+    for mut regulator in &mut regulators {
+        if regulator.state == RegulatorState::FreeFlowing && !regulator.valve_open {
+            regulator.thaw.tick(time.delta());
+            if regulator.thaw.finished() {
+                regulator.state = RegulatorState::Working;
+                regulator.thaw.reset();
+            }
+        }
+    }
+}
+
+#[test]
+fn did_stop_free_flow_and_thaw() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, (free_flow, thaw_regulator));
+    let cylinder_id = app
+        .world_mut()
+        .spawn((
+            DivingCylinder {
+                amount_remaining: 1000.,
+                ..default()
+            },
+            Regulator {
+                state: RegulatorState::FreeFlowing,
+                valve_open: false,
+                ..default()
+            },
+        ))
+        .id();
+    app.world_mut()
+        .spawn((EquippedTank(cylinder_id), Depth(10.)));
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs_f32(THAW_SECONDS / 2.));
+    app.update();
+    // no gas lost with the valve shut, but still frozen
+    let cylinder = app.world().get::<DivingCylinder>(cylinder_id).unwrap();
+    assert_eq!(cylinder.amount_remaining, 1000.);
+    let regulator = app.world().get::<Regulator>(cylinder_id).unwrap();
+    assert_eq!(regulator.state, RegulatorState::FreeFlowing);
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs_f32(THAW_SECONDS / 2.));
+    app.update();
+    let regulator = app.world().get::<Regulator>(cylinder_id).unwrap();
+    assert_eq!(regulator.state, RegulatorState::Working);
+}
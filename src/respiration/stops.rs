@@ -0,0 +1,338 @@
+use crate::environment::DiveSiteEnvironment;
+use crate::position::*;
+use crate::respiration::circulation::decompression::*;
+#[cfg(test)]
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::planner::*;
+use crate::respiration::BloodstreamContent;
+use crate::states::RunningStateSet;
+use crate::wallet::Wallet;
+use bevy::prelude::*;
+
+// metres either side of a stop that still count as being at it
+const STOP_TOLERANCE: f32 = 1.5;
+// dives deeper than this should finish with a safety stop
+const SAFETY_STOP_TRIGGER_DEPTH: f32 = 10.;
+pub const SAFETY_STOP_DEPTH: f32 = 5.;
+const SAFETY_STOP_SECONDS: f32 = 3. * 60.;
+// dives shallower than this don't end a dive or get scored
+pub const MIN_DIVE_DEPTH: f32 = 3.;
+// DCS risk added for each minute spent shallower than a required stop, and the minutes at the
+// surface for it to halve
+const MISSED_STOP_RISK: f32 = 0.5;
+const DCS_RISK_HALF_TIME_MIN: f32 = 30.;
+// paid in full for a clean ascent from a dive at least this deep and long, and in proportion for
+// anything less, so bobbing just under the surface doesn't pay
+const CLEAN_ASCENT_REWARD: f32 = 10.;
+const CLEAN_ASCENT_DEPTH: f32 = 30.;
+const CLEAN_ASCENT_MINUTES: f32 = 10.;
+const STOP_REWARD_PER_MINUTE: f32 = 2.;
+const SAFETY_STOP_REWARD: f32 = 5.;
+
+/// The stops the diver currently has to make, and how well they've kept to them this dive.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct DecompressionSchedule {
+    pub stops: Vec<Stop>,
+    pub safety_stop_remaining: f32,
+    pub deepest: f32,
+    pub minutes: f32,
+    pub honoured_minutes: f32,
+    pub missed_minutes: f32,
+}
+
+impl Default for DecompressionSchedule {
+    fn default() -> Self {
+        Self {
+            stops: vec![],
+            safety_stop_remaining: SAFETY_STOP_SECONDS,
+            deepest: 0.,
+            minutes: 0.,
+            honoured_minutes: 0.,
+            missed_minutes: 0.,
+        }
+    }
+}
+
+impl DecompressionSchedule {
+    /// Whether a safety stop is still owed, once any required stops are done.
+    pub fn safety_stop_due(&self) -> bool {
+        self.stops.is_empty()
+            && self.deepest > SAFETY_STOP_TRIGGER_DEPTH
+            && self.safety_stop_remaining > 0.
+    }
+
+    /// Currency earned for surfacing now.
+    pub fn reward(&self) -> f32 {
+        if self.missed_minutes > 0. {
+            return 0.;
+        }
+        let safety_stop_reward = if self.safety_stop_remaining <= 0. {
+            SAFETY_STOP_REWARD
+        } else {
+            0.
+        };
+        let clean_ascent_reward = CLEAN_ASCENT_REWARD
+            * (self.deepest / CLEAN_ASCENT_DEPTH).min(1.)
+            * (self.minutes / CLEAN_ASCENT_MINUTES).min(1.);
+        clean_ascent_reward + STOP_REWARD_PER_MINUTE * self.honoured_minutes + safety_stop_reward
+    }
+}
+
+pub fn stops_plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (
+            schedule_stops.after(load_tissues),
+            honour_stops.after(schedule_stops),
+            reward_ascent.after(honour_stops),
+            ease_dcs_risk,
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<DecompressionSchedule>();
+}
+
+pub fn schedule_stops(
+    environment: Res<DiveSiteEnvironment>,
+    mut divers: Query<(
+        &mut DecompressionSchedule,
+        &TissueCompartments,
+        &BloodstreamContent,
+        &Depth,
+    )>,
+) {
+    for (mut schedule, tissues, bloodstream_content, depth) in &mut divers {
+        schedule.stops = stop_schedule(&environment, tissues, depth.0, &bloodstream_content.mix);
+    }
+}
+
+#[test]
+fn did_schedule_stops() {
+    let mut app = App::new();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, schedule_stops);
+    let mut tissues = TissueCompartments::default();
+    tissues.load(
+        DiveSiteEnvironment::SEA.ambient_pressure(30.),
+        &GasMix::AIR,
+        60. * 60.,
+    );
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            DecompressionSchedule::default(),
+            tissues,
+            BloodstreamContent::default(),
+            Depth(30.),
+        ))
+        .id();
+    app.update();
+    let schedule = app.world().get::<DecompressionSchedule>(diver_id).unwrap();
+    assert_eq!(schedule.stops[0].depth, 9.);
+    assert!(!schedule.safety_stop_due());
+}
+
+/// Time spent within `STOP_TOLERANCE` of the next stop counts towards it, and time spent above it
+/// raises the risk of decompression sickness.
+pub fn honour_stops(
+    time: Res<Time>,
+    mut divers: Query<(&mut DecompressionSchedule, &mut DcsRisk, &Depth)>,
+) {
+    for (mut schedule, mut dcs_risk, depth) in &mut divers {
+        let minutes = time.delta_seconds() / 60.;
+        schedule.deepest = schedule.deepest.max(depth.0);
+        if depth.0 > 0. {
+            schedule.minutes += minutes;
+        }
+        if let Some(stop_depth) = schedule.stops.first().map(|stop| stop.depth) {
+            if depth.0 < stop_depth - STOP_TOLERANCE {
+                schedule.missed_minutes += minutes;
+                dcs_risk.0 += MISSED_STOP_RISK * minutes;
+            } else if depth.0 <= stop_depth + STOP_TOLERANCE {
+                schedule.honoured_minutes += minutes;
+            }
+        } else if schedule.safety_stop_due()
+            && (depth.0 - SAFETY_STOP_DEPTH).abs() <= STOP_TOLERANCE
+        {
+            schedule.safety_stop_remaining =
+                (schedule.safety_stop_remaining - time.delta_seconds()).max(0.);
+        }
+    }
+}
+
+#[test]
+fn did_honour_stop() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, honour_stops);
+    let schedule = DecompressionSchedule {
+        stops: vec![Stop {
+            depth: 6.,
+            minutes: 5.,
+        }],
+        ..default()
+    };
+    let honouring_id = app
+        .world_mut()
+        .spawn((
+            DecompressionSchedule {
+                stops: schedule.stops.clone(),
+                ..default()
+            },
+            DcsRisk::default(),
+            Depth(6.5),
+        ))
+        .id();
+    let skipping_id = app
+        .world_mut()
+        .spawn((schedule, DcsRisk::default(), Depth(2.)))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60));
+    app.update();
+    let honouring = app
+        .world()
+        .get::<DecompressionSchedule>(honouring_id)
+        .unwrap();
+    assert_eq!(honouring.honoured_minutes, 1.);
+    assert_eq!(honouring.missed_minutes, 0.);
+    assert_eq!(app.world().get::<DcsRisk>(honouring_id).unwrap().0, 0.);
+    let skipping = app
+        .world()
+        .get::<DecompressionSchedule>(skipping_id)
+        .unwrap();
+    assert_eq!(skipping.missed_minutes, 1.);
+    assert_eq!(skipping.reward(), 0.);
+    assert_eq!(
+        app.world().get::<DcsRisk>(skipping_id).unwrap().0,
+        MISSED_STOP_RISK
+    );
+}
+
+#[test]
+fn did_safety_stop() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, honour_stops);
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            DecompressionSchedule {
+                deepest: CLEAN_ASCENT_DEPTH,
+                minutes: CLEAN_ASCENT_MINUTES,
+                ..default()
+            },
+            DcsRisk::default(),
+            Depth(SAFETY_STOP_DEPTH),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(SAFETY_STOP_SECONDS as u64));
+    app.update();
+    let schedule = app.world().get::<DecompressionSchedule>(diver_id).unwrap();
+    assert!(!schedule.safety_stop_due());
+    assert_eq!(schedule.reward(), CLEAN_ASCENT_REWARD + SAFETY_STOP_REWARD);
+}
+
+/// Surfacing ends the dive, paying out for a clean ascent and starting a fresh schedule.
+pub fn reward_ascent(mut divers: Query<(&mut DecompressionSchedule, &Depth, Option<&mut Wallet>)>) {
+    for (mut schedule, depth, wallet) in &mut divers {
+        if depth.0 > 0. || schedule.deepest < MIN_DIVE_DEPTH {
+            continue;
+        }
+        let reward = schedule.reward();
+        println!(
+            "surfaced, missed stop minutes: {}, reward: {}",
+            schedule.missed_minutes, reward
+        );
+        if let Some(mut wallet) = wallet {
+            wallet.0 += reward;
+        }
+        *schedule = DecompressionSchedule::default();
+    }
+}
+
+#[test]
+fn did_reward_clean_ascent() {
+    let mut app = App::new();
+    app.add_systems(Update, reward_ascent);
+    let clean_id = app
+        .world_mut()
+        .spawn((
+            DecompressionSchedule {
+                deepest: 30.,
+                minutes: 40.,
+                honoured_minutes: 5.,
+                ..default()
+            },
+            Depth(0.),
+            Wallet(0.),
+        ))
+        .id();
+    let rushed_id = app
+        .world_mut()
+        .spawn((
+            DecompressionSchedule {
+                deepest: 30.,
+                missed_minutes: 1.,
+                ..default()
+            },
+            Depth(0.),
+            Wallet(0.),
+        ))
+        .id();
+    app.update();
+    let clean = app.world().get::<Wallet>(clean_id).unwrap();
+    assert_eq!(clean.0, CLEAN_ASCENT_REWARD + 5. * STOP_REWARD_PER_MINUTE);
+    let rushed = app.world().get::<Wallet>(rushed_id).unwrap();
+    assert_eq!(rushed.0, 0.);
+    // a quick dip just past the scoring depth earns next to nothing
+    let bobbing_id = app
+        .world_mut()
+        .spawn((
+            DecompressionSchedule {
+                deepest: MIN_DIVE_DEPTH,
+                minutes: 0.1,
+                ..default()
+            },
+            Depth(0.),
+            Wallet(0.),
+        ))
+        .id();
+    app.update();
+    let bobbing = app.world().get::<Wallet>(bobbing_id).unwrap();
+    assert!(bobbing.0 < 0.1);
+    // the next dive starts afresh
+    let schedule = app.world().get::<DecompressionSchedule>(rushed_id).unwrap();
+    assert_eq!(schedule.deepest, 0.);
+    assert_eq!(schedule.missed_minutes, 0.);
+}
+
+/// The extra risk from missed stops wears off over the surface interval.
+pub fn ease_dcs_risk(time: Res<Time>, mut divers: Query<(&mut DcsRisk, &Depth)>) {
+    for (mut dcs_risk, depth) in &mut divers {
+        if depth.0 <= 0. {
+            dcs_risk.0 *= 0.5_f32.powf(time.delta_seconds() / 60. / DCS_RISK_HALF_TIME_MIN);
+        }
+    }
+}
+
+#[test]
+fn did_ease_dcs_risk() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, ease_dcs_risk);
+    let surface_id = app.world_mut().spawn((DcsRisk(1.), Depth(0.))).id();
+    let submerged_id = app.world_mut().spawn((DcsRisk(1.), Depth(10.))).id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(
+            (DCS_RISK_HALF_TIME_MIN * 60.) as u64,
+        ));
+    app.update();
+    assert!((app.world().get::<DcsRisk>(surface_id).unwrap().0 - 0.5).abs() < 1e-5);
+    assert_eq!(app.world().get::<DcsRisk>(submerged_id).unwrap().0, 1.);
+}
@@ -0,0 +1,413 @@
+use crate::diver::Swimming;
+use crate::fauna::enemy::Enemy;
+use crate::health::*;
+use crate::position::Depth;
+use crate::respiration::air_sharing::*;
+use crate::respiration::circulation::usage::*;
+#[cfg(test)]
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::inhalation::*;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+
+// pixels within which an enemy starts to unnerve the diver
+const ENEMY_STRESS_RANGE: f32 = 60.;
+// stress per second from an enemy right alongside, falling off with distance
+const ENEMY_STRESS: f32 = 0.05;
+// stress for each point of damage taken
+const DAMAGE_STRESS: f32 = 0.01;
+// cylinder pressure, in bar, below which the diver starts to worry about running out
+const LOW_GAS_PRESSURE: f32 = 50.;
+// stress per second with an empty cylinder
+const LOW_GAS_STRESS: f32 = 0.03;
+// metres of water over which daylight fades by a factor of e
+const LIGHT_ATTENUATION_DEPTH: f32 = 20.;
+// light level below which the dark starts to get to the diver
+const DARKNESS_THRESHOLD: f32 = 0.2;
+// stress per second in total darkness
+const DARKNESS_STRESS: f32 = 0.01;
+const ENTANGLEMENT_STRESS: f32 = 0.05;
+// stress lost per second while calm
+const CALM_RECOVERY: f32 = 0.05;
+// seconds between breaths that count as breathing slowly
+const SLOW_BREATH_INTERVAL: f32 = 5.;
+// stress where the diver panics, and where they come to their senses again
+const PANIC_THRESHOLD: f32 = 0.8;
+const PANIC_RECOVERY_THRESHOLD: f32 = 0.4;
+// extra breathing demand at full stress
+const STRESS_DEMAND: f32 = 1.;
+// radians the diver's swimming is thrown off by at full stress
+const MAX_AGITATION: f32 = 0.6;
+
+/// How stressed the breather is, from 0 to 1, along with how fast they're breathing.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Stress {
+    pub level: f32,
+    /// Seconds between the last two breaths.
+    pub breath_interval: f32,
+    /// Seconds since the last breath.
+    pub since_breath: f32,
+}
+
+impl Stress {
+    pub fn agitate(&self, direction: Vec3, noise: f32) -> Vec3 {
+        Quat::from_rotation_z(MAX_AGITATION * self.level * noise) * direction
+    }
+
+    pub fn breathing_slowly(&self) -> bool {
+        self.breath_interval >= SLOW_BREATH_INTERVAL
+    }
+}
+
+/// Overwhelmed by stress, and bolting for the surface whatever the consequences.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Panicking;
+
+/// Caught up in `Netting`, and unable to swim until cut free.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Entangled;
+
+/// Proportion of daylight that reaches `depth`.
+pub fn light_level(depth: f32) -> f32 {
+    (-depth.max(0.) / LIGHT_ATTENUATION_DEPTH).exp()
+}
+
+pub fn stress_plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (
+            pace_breaths,
+            build_stress.after(pace_breaths),
+            panic.after(build_stress),
+            stress_breathing_demand
+                .after(reset_breathing_demand)
+                .before(usage),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<Stress>();
+    app.register_type::<Panicking>();
+    app.register_type::<Entangled>();
+}
+
+pub fn pace_breaths(
+    time: Res<Time>,
+    mut breathers: Query<&mut Stress>,
+    mut breaths: EventReader<BreathTaken>,
+) {
+    for mut stress in &mut breathers {
+        stress.since_breath += time.delta_seconds();
+    }
+    for breath in breaths.read() {
+        if let Ok(mut stress) = breathers.get_mut(breath.entity) {
+            stress.breath_interval = stress.since_breath;
+            stress.since_breath = 0.;
+        }
+    }
+}
+
+#[test]
+fn did_pace_breaths() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<BreathTaken>();
+    app.add_systems(Update, pace_breaths);
+    let breather_id = app.world_mut().spawn(Stress::default()).id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(6));
+    app.world_mut()
+        .resource_mut::<Events<BreathTaken>>()
+        .send(BreathTaken {
+            entity: breather_id,
+        });
+    app.update();
+    let stress = app.world().get::<Stress>(breather_id).unwrap();
+    assert_eq!(stress.breath_interval, 6.);
+    assert_eq!(stress.since_breath, 0.);
+    assert!(stress.breathing_slowly());
+}
+
+type Stressed<'a> = (
+    Entity,
+    &'a mut Stress,
+    &'a Transform,
+    &'a Depth,
+    Option<&'a Swimming>,
+    Has<Entangled>,
+);
+
+/// Nearby enemies, damage, running low on gas, the dark and being entangled all build stress.
+/// Staying still and breathing slowly calms the diver down, and so does reaching the surface.
+pub fn build_stress(
+    time: Res<Time>,
+    mut breathers: Query<Stressed>,
+    enemies: Query<&Transform, (With<Enemy>, Without<Dead>)>,
+    equipped_tanks: Query<&EquippedTank>,
+    sharers: Query<&SharingAir>,
+    cylinders: Query<&DivingCylinder>,
+    mut damage_events: EventReader<DamageEvent>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut stress, transform, depth, swimming, entangled) in &mut breathers {
+        let enemy: f32 = enemies
+            .iter()
+            .map(|enemy| {
+                let distance = enemy.translation.distance(transform.translation);
+                ENEMY_STRESS * (1. - distance / ENEMY_STRESS_RANGE).max(0.)
+            })
+            .sum();
+        let low_gas = supplying_tank(entity, &equipped_tanks, &sharers)
+            .and_then(|tank| cylinders.get(tank).ok())
+            .map_or(0., |cylinder| {
+                LOW_GAS_STRESS * (1. - cylinder.pressure() / LOW_GAS_PRESSURE).max(0.)
+            });
+        let darkness = DARKNESS_STRESS * (1. - light_level(depth.0) / DARKNESS_THRESHOLD).max(0.);
+        let entanglement = if entangled { ENTANGLEMENT_STRESS } else { 0. };
+        let still = swimming.is_none_or(|swimming| swimming.0 == Vec3::ZERO);
+        let calm = depth.0 <= 0. || (still && stress.breathing_slowly());
+        let recovery = if calm { CALM_RECOVERY } else { 0. };
+        stress.level += (enemy + low_gas + darkness + entanglement - recovery) * delta;
+        stress.level = stress.level.clamp(0., 1.);
+    }
+    for damage_event in damage_events.read() {
+        if let Ok((_, mut stress, ..)) = breathers.get_mut(damage_event.target) {
+            stress.level = (stress.level + DAMAGE_STRESS * damage_event.damage).min(1.);
+        }
+    }
+}
+
+#[test]
+fn did_build_stress() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, build_stress);
+    let shallow_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::default(),
+            Depth(5.),
+            Swimming(Vec3::new(1., 0., 0.)),
+        ))
+        .id();
+    let dark_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::default(),
+            Depth(60.),
+            Swimming(Vec3::new(1., 0., 0.)),
+        ))
+        .id();
+    let hurt_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::default(),
+            Depth(5.),
+            Swimming(Vec3::new(1., 0., 0.)),
+        ))
+        .id();
+    let entangled_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::default(),
+            Depth(5.),
+            Swimming(Vec3::new(1., 0., 0.)),
+            Entangled,
+        ))
+        .id();
+    let low_gas_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::default(),
+            Depth(5.),
+            Swimming(Vec3::new(1., 0., 0.)),
+        ))
+        .id();
+    let cylinder_id = app
+        .world_mut()
+        .spawn(DivingCylinder::new(10., 200., 20., GasMix::AIR))
+        .id();
+    app.world_mut()
+        .entity_mut(low_gas_id)
+        .insert(EquippedTank(cylinder_id));
+    app.world_mut()
+        .resource_mut::<Events<DamageEvent>>()
+        .send(DamageEvent {
+            target: hurt_id,
+            damage: 10.,
+        });
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
+    app.update();
+    let level = |id: Entity| app.world().get::<Stress>(id).unwrap().level;
+    assert_eq!(level(shallow_id), 0.);
+    assert!(level(dark_id) > 0.);
+    assert!((level(hurt_id) - 10. * DAMAGE_STRESS).abs() < 1e-6);
+    assert!((level(entangled_id) - ENTANGLEMENT_STRESS).abs() < 1e-6);
+    assert!(level(low_gas_id) > 0.);
+}
+
+#[test]
+fn enemies_build_stress() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, build_stress);
+    let near_id = app
+        .world_mut()
+        .spawn((Stress::default(), Transform::default(), Depth(5.)))
+        .id();
+    let far_id = app
+        .world_mut()
+        .spawn((
+            Stress::default(),
+            Transform::from_translation(Vec3::new(ENEMY_STRESS_RANGE * 3., 0., 0.)),
+            Depth(5.),
+        ))
+        .id();
+    app.world_mut().spawn((
+        Enemy,
+        Transform::from_translation(Vec3::new(ENEMY_STRESS_RANGE / 2., 0., 0.)),
+    ));
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
+    app.update();
+    let near = app.world().get::<Stress>(near_id).unwrap();
+    assert!((near.level - ENEMY_STRESS / 2.).abs() < 1e-6);
+    let far = app.world().get::<Stress>(far_id).unwrap();
+    assert_eq!(far.level, 0.);
+}
+
+#[test]
+fn did_calm_down() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, build_stress);
+    let calm_id = app
+        .world_mut()
+        .spawn((
+            Stress {
+                level: 0.5,
+                breath_interval: SLOW_BREATH_INTERVAL,
+                ..default()
+            },
+            Transform::default(),
+            Depth(5.),
+            Swimming(Vec3::ZERO),
+        ))
+        .id();
+    let panting_id = app
+        .world_mut()
+        .spawn((
+            Stress {
+                level: 0.5,
+                breath_interval: 1.,
+                ..default()
+            },
+            Transform::default(),
+            Depth(5.),
+            Swimming(Vec3::ZERO),
+        ))
+        .id();
+    let swimming_id = app
+        .world_mut()
+        .spawn((
+            Stress {
+                level: 0.5,
+                breath_interval: SLOW_BREATH_INTERVAL,
+                ..default()
+            },
+            Transform::default(),
+            Depth(5.),
+            Swimming(Vec3::new(1., 0., 0.)),
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
+    app.update();
+    let level = |id: Entity| app.world().get::<Stress>(id).unwrap().level;
+    assert!((level(calm_id) - (0.5 - CALM_RECOVERY)).abs() < 1e-6);
+    assert_eq!(level(panting_id), 0.5);
+    assert_eq!(level(swimming_id), 0.5);
+}
+
+/// Panic sets in past `PANIC_THRESHOLD`, and only passes once stress has fallen well below it.
+pub fn panic(mut commands: Commands, breathers: Query<(Entity, &Stress, Has<Panicking>)>) {
+    for (entity, stress, panicking) in &breathers {
+        if !panicking && stress.level >= PANIC_THRESHOLD {
+            println!("panicking, stress: {}", stress.level);
+            commands.entity(entity).insert(Panicking);
+        } else if panicking && stress.level <= PANIC_RECOVERY_THRESHOLD {
+            println!("calmed down, stress: {}", stress.level);
+            commands.entity(entity).remove::<Panicking>();
+        }
+    }
+}
+
+#[test]
+fn did_panic() {
+    let mut app = App::new();
+    app.add_systems(Update, panic);
+    let breather_id = app
+        .world_mut()
+        .spawn(Stress {
+            level: PANIC_THRESHOLD,
+            ..default()
+        })
+        .id();
+    app.update();
+    assert!(app.world().get::<Panicking>(breather_id).is_some());
+    // still panicking part of the way back down
+    app.world_mut()
+        .get_mut::<Stress>(breather_id)
+        .unwrap()
+        .level = (PANIC_THRESHOLD + PANIC_RECOVERY_THRESHOLD) / 2.;
+    app.update();
+    assert!(app.world().get::<Panicking>(breather_id).is_some());
+    app.world_mut()
+        .get_mut::<Stress>(breather_id)
+        .unwrap()
+        .level = PANIC_RECOVERY_THRESHOLD;
+    app.update();
+    assert!(app.world().get::<Panicking>(breather_id).is_none());
+}
+
+pub fn stress_breathing_demand(mut breathers: Query<(&mut BreathingDemand, &Stress)>) {
+    for (mut breathing_demand, stress) in &mut breathers {
+        breathing_demand.0 *= 1. + STRESS_DEMAND * stress.level;
+    }
+}
+
+#[test]
+fn did_stress_breathing_demand() {
+    let mut app = App::new();
+    app.add_systems(Update, stress_breathing_demand);
+    let breather_id = app
+        .world_mut()
+        .spawn((
+            BreathingDemand::default(),
+            Stress {
+                level: 0.5,
+                ..default()
+            },
+        ))
+        .id();
+    app.update();
+    assert_eq!(
+        app.world().get::<BreathingDemand>(breather_id).unwrap().0,
+        1. + STRESS_DEMAND * 0.5
+    );
+}
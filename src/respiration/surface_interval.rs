@@ -0,0 +1,165 @@
+use crate::position::Depth;
+use crate::respiration::stops::MIN_DIVE_DEPTH;
+use crate::states::*;
+use bevy::prelude::*;
+
+// seconds of surface interval that pass for each second spent resting
+const REST_TIME_SCALE: f32 = 60.;
+
+/// The diver's day of diving. Tissues carry over from one dive to the next, so each dive after
+/// the first starts with whatever the surface interval didn't wash out.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct DiveSession {
+    pub dive_number: u32,
+    pub diving: bool,
+    /// Seconds since the last dive ended.
+    pub surface_interval: f32,
+    pub resting: bool,
+}
+
+impl DiveSession {
+    pub fn is_repetitive(&self) -> bool {
+        self.dive_number > 0
+    }
+}
+
+pub fn surface_interval_plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (track_dives, rest_at_surface.after(track_dives)).in_set(RunningStateSet),
+    );
+    app.add_systems(OnExit(InGameMenuState::NoMenu), stop_resting);
+    app.add_systems(OnEnter(GameState::Paused), stop_resting);
+    app.register_type::<DiveSession>();
+}
+
+/// Going below `MIN_DIVE_DEPTH` starts a dive, and surfacing ends it and starts the interval.
+pub fn track_dives(time: Res<Time>, mut divers: Query<(&mut DiveSession, &Depth)>) {
+    for (mut session, depth) in &mut divers {
+        if session.diving {
+            if depth.0 <= 0. {
+                println!("dive {} over", session.dive_number);
+                session.diving = false;
+                session.surface_interval = 0.;
+            }
+        } else if depth.0 > MIN_DIVE_DEPTH {
+            session.dive_number += 1;
+            session.diving = true;
+            session.resting = false;
+            println!(
+                "dive {} after {} s at the surface",
+                session.dive_number, session.surface_interval
+            );
+        } else {
+            session.surface_interval += time.delta_seconds();
+        }
+    }
+}
+
+#[test]
+fn did_track_dives() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_systems(Update, track_dives);
+    let diver_id = app
+        .world_mut()
+        .spawn((DiveSession::default(), Depth(10.)))
+        .id();
+    app.update();
+    let session = app.world().get::<DiveSession>(diver_id).unwrap();
+    assert_eq!(session.dive_number, 1);
+    assert!(session.diving);
+    app.world_mut().get_mut::<Depth>(diver_id).unwrap().0 = 0.;
+    app.update();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60));
+    app.update();
+    let session = app.world().get::<DiveSession>(diver_id).unwrap();
+    assert!(!session.diving);
+    assert_eq!(session.surface_interval, 60.);
+    assert!(session.is_repetitive());
+}
+
+/// Resting on the boat speeds up the game clock itself, so tissues, CNS, core temperature and
+/// everything else carry on through the interval together.
+pub fn rest_at_surface(mut time: ResMut<Time<Virtual>>, divers: Query<(&DiveSession, &Depth)>) {
+    let resting = divers
+        .iter()
+        .any(|(session, depth)| session.resting && !session.diving && depth.0 <= 0.);
+    let speed = if resting { REST_TIME_SCALE } else { 1. };
+    if time.relative_speed() != speed {
+        time.set_relative_speed(speed);
+    }
+}
+
+#[test]
+fn did_rest_at_surface() {
+    let mut app = App::new();
+    app.init_resource::<Time<Virtual>>();
+    app.add_systems(Update, rest_at_surface);
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            DiveSession {
+                dive_number: 1,
+                resting: true,
+                ..default()
+            },
+            Depth(0.),
+        ))
+        .id();
+    app.update();
+    assert_eq!(
+        app.world().resource::<Time<Virtual>>().relative_speed(),
+        REST_TIME_SCALE
+    );
+    // the clock goes back to normal as soon as the diver stops resting
+    app.world_mut()
+        .get_mut::<DiveSession>(diver_id)
+        .unwrap()
+        .resting = false;
+    app.update();
+    assert_eq!(app.world().resource::<Time<Virtual>>().relative_speed(), 1.);
+}
+
+/// Opening a menu or pausing ends the rest, so the world doesn't race on behind it.
+pub fn stop_resting(mut time: ResMut<Time<Virtual>>, mut divers: Query<&mut DiveSession>) {
+    for mut session in &mut divers {
+        session.resting = false;
+    }
+    time.set_relative_speed(1.);
+}
+
+#[test]
+fn did_stop_resting_in_menu() {
+    let mut app = App::new();
+    app.add_plugins(bevy::state::app::StatesPlugin);
+    app.init_state::<InGameMenuState>();
+    app.init_resource::<Time<Virtual>>();
+    app.add_systems(Update, rest_at_surface);
+    app.add_systems(OnExit(InGameMenuState::NoMenu), stop_resting);
+    let diver_id = app
+        .world_mut()
+        .spawn((
+            DiveSession {
+                dive_number: 1,
+                resting: true,
+                ..default()
+            },
+            Depth(0.),
+        ))
+        .id();
+    app.update();
+    assert_eq!(
+        app.world().resource::<Time<Virtual>>().relative_speed(),
+        REST_TIME_SCALE
+    );
+    app.world_mut()
+        .resource_mut::<NextState<InGameMenuState>>()
+        .set(InGameMenuState::Inventory);
+    app.update();
+    assert!(!app.world().get::<DiveSession>(diver_id).unwrap().resting);
+    assert_eq!(app.world().resource::<Time<Virtual>>().relative_speed(), 1.);
+}
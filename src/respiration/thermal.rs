@@ -0,0 +1,306 @@
+use crate::environment::DiveSiteEnvironment;
+use crate::health::*;
+use crate::position::*;
+use crate::respiration::bcd::Wetsuit;
+use crate::respiration::circulation::carbon_dioxide::relative_density;
+use crate::respiration::circulation::usage::*;
+#[cfg(test)]
+use crate::respiration::gas_mix::GasMix;
+use crate::respiration::BloodstreamContent;
+use crate::states::RunningStateSet;
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+
+const DRYSUIT_WIDTH: f32 = 4.;
+const DRYSUIT_HEIGHT: f32 = 6.;
+
+const NORMAL_CORE_TEMPERATURE: f32 = 37.;
+// degrees per second the body can make up for
+const METABOLIC_HEAT: f32 = 0.001;
+// degrees per second lost through bare skin for each degree of difference with the water
+const SKIN_HEAT_LOSS: f32 = 0.0002;
+// degrees per second lost warming each breath, for each degree of difference and each unit of
+// gas density relative to surface air
+const RESPIRATORY_HEAT_LOSS: f32 = 0.00002;
+// extra respiratory heat loss from helium, which carries heat away far faster than nitrogen
+const HELIUM_HEAT_LOSS: f32 = 5.;
+const DRYSUIT_INSULATION: f32 = 4.;
+// core temperatures where shivering starts, and where it's at its worst and starts to do harm
+const MILD_HYPOTHERMIA: f32 = 35.;
+const SEVERE_HYPOTHERMIA: f32 = 32.;
+// extra breathing demand when shivering hardest
+const SHIVERING_DEMAND: f32 = 0.5;
+// radians the diver's swimming is turned by when shivering hardest
+const MAX_SHIVER: f32 = 0.3;
+// damage per second for each degree below `SEVERE_HYPOTHERMIA`
+const HYPOTHERMIA_DAMAGE: f32 = 1.;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct CoreTemperature(pub f32);
+
+impl Default for CoreTemperature {
+    fn default() -> Self {
+        Self(NORMAL_CORE_TEMPERATURE)
+    }
+}
+
+impl CoreTemperature {
+    /// How hard the body is shivering, from 0 to 1.
+    pub fn shivering(&self) -> f32 {
+        ((MILD_HYPOTHERMIA - self.0) / (MILD_HYPOTHERMIA - SEVERE_HYPOTHERMIA)).clamp(0., 1.)
+    }
+
+    pub fn shiver(&self, direction: Vec3, noise: f32) -> Vec3 {
+        Quat::from_rotation_z(MAX_SHIVER * self.shivering() * noise) * direction
+    }
+}
+
+/// Unlike neoprene, a drysuit isn't compressed and insulates the same at any depth.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Drysuit;
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct EquippedDrysuit(pub Entity);
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Blubber(pub f32);
+
+/// Insulation of whatever suit is worn, with a drysuit worn over a wetsuit taking over from it.
+pub fn insulation(
+    environment: &DiveSiteEnvironment,
+    wetsuit: Option<&Wetsuit>,
+    drysuit: Option<&EquippedDrysuit>,
+    depth: f32,
+) -> f32 {
+    match (wetsuit, drysuit) {
+        (_, Some(_)) => DRYSUIT_INSULATION,
+        (Some(wetsuit), None) => wetsuit.insulation(environment, depth),
+        (None, None) => 0.,
+    }
+}
+
+pub fn thermal_plugin(app: &mut App) {
+    app.add_systems(Startup, spawn_drysuits);
+    app.add_systems(
+        FixedUpdate,
+        (
+            lose_heat.after(update_water_temperature),
+            shivering_breathing_demand
+                .after(reset_breathing_demand)
+                .before(usage),
+            hypothermia_damage.after(lose_heat),
+        )
+            .in_set(RunningStateSet),
+    );
+    app.register_type::<CoreTemperature>();
+    app.register_type::<Drysuit>();
+    app.register_type::<EquippedDrysuit>();
+    app.register_type::<Blubber>();
+}
+
+type Exposed<'a> = (
+    &'a mut CoreTemperature,
+    &'a WaterTemperature,
+    &'a Depth,
+    &'a BloodstreamContent,
+    Option<&'a Wetsuit>,
+    Option<&'a EquippedDrysuit>,
+    Option<&'a Blubber>,
+);
+
+pub fn spawn_drysuits(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mesh = Mesh::from(Rectangle::new(DRYSUIT_WIDTH, DRYSUIT_HEIGHT));
+    let material = ColorMaterial::from_color(Srgba::rgb(0.2, 0.2, 0.2));
+    commands.spawn((
+        Drysuit,
+        crate::collision::RectangularHitbox(Rectangle::new(DRYSUIT_WIDTH, DRYSUIT_HEIGHT)),
+        MaterialMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            material: materials.add(material),
+            transform: Transform::from_translation(Vec3::new(110., 0., 0.)),
+            ..default()
+        },
+        crate::bag::Collectible,
+        Name::new("Drysuit"),
+        crate::PIXEL_PERFECT_LAYERS,
+    ));
+}
+
+/// Heat is lost through the suit and by warming each breath, which costs more the denser the gas.
+pub fn lose_heat(
+    time: Res<Time>,
+    environment: Res<DiveSiteEnvironment>,
+    mut breathers: Query<Exposed>,
+) {
+    for (mut core, water, depth, bloodstream_content, wetsuit, drysuit, blubber) in &mut breathers {
+        let mix = &bloodstream_content.mix;
+        let insulation = insulation(&environment, wetsuit, drysuit, depth.0)
+            + blubber.map_or(0., |blubber| blubber.0);
+        let skin = SKIN_HEAT_LOSS / (1. + insulation);
+        let respiratory = RESPIRATORY_HEAT_LOSS
+            * relative_density(mix, environment.ambient_pressure(depth.0))
+            * (1. + HELIUM_HEAT_LOSS * mix.helium);
+        let change = METABOLIC_HEAT - (core.0 - water.0) * (skin + respiratory);
+        core.0 = (core.0 + change * time.delta_seconds()).min(NORMAL_CORE_TEMPERATURE);
+    }
+}
+
+#[test]
+fn did_lose_heat() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.init_resource::<DiveSiteEnvironment>();
+    app.add_systems(Update, lose_heat);
+    let air_id = app
+        .world_mut()
+        .spawn((
+            CoreTemperature::default(),
+            WaterTemperature(10.),
+            Depth(30.),
+            BloodstreamContent {
+                mix: GasMix::AIR,
+                ..default()
+            },
+            Wetsuit {
+                thickness: 5.,
+                ..default()
+            },
+        ))
+        .id();
+    let trimix_id = app
+        .world_mut()
+        .spawn((
+            CoreTemperature::default(),
+            WaterTemperature(10.),
+            Depth(30.),
+            BloodstreamContent {
+                mix: GasMix::trimix(0.21, 0.35).unwrap(),
+                ..default()
+            },
+            Wetsuit {
+                thickness: 5.,
+                ..default()
+            },
+        ))
+        .id();
+    let thick_id = app
+        .world_mut()
+        .spawn((
+            CoreTemperature::default(),
+            WaterTemperature(10.),
+            Depth(30.),
+            BloodstreamContent {
+                mix: GasMix::AIR,
+                ..default()
+            },
+            Wetsuit {
+                thickness: 7.,
+                ..default()
+            },
+        ))
+        .id();
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(60));
+    app.update();
+    let air = app.world().get::<CoreTemperature>(air_id).unwrap().0;
+    let trimix = app.world().get::<CoreTemperature>(trimix_id).unwrap().0;
+    let thick = app.world().get::<CoreTemperature>(thick_id).unwrap().0;
+    assert!(air < NORMAL_CORE_TEMPERATURE);
+    // helium chills faster, and thicker neoprene keeps more heat in
+    assert!(trimix < air);
+    assert!(thick > air);
+}
+
+#[test]
+fn drysuit_insulation() {
+    let wetsuit = Wetsuit {
+        thickness: 7.,
+        ..default()
+    };
+    let sea = DiveSiteEnvironment::SEA;
+    assert!(
+        insulation(&sea, Some(&wetsuit), None, 40.) < insulation(&sea, Some(&wetsuit), None, 0.)
+    );
+    let drysuit = EquippedDrysuit(Entity::PLACEHOLDER);
+    assert_eq!(
+        insulation(&sea, Some(&wetsuit), Some(&drysuit), 40.),
+        insulation(&sea, None, Some(&drysuit), 0.)
+    );
+}
+
+pub fn shivering_breathing_demand(mut breathers: Query<(&mut BreathingDemand, &CoreTemperature)>) {
+    for (mut breathing_demand, core) in &mut breathers {
+        breathing_demand.0 *= 1. + SHIVERING_DEMAND * core.shivering();
+    }
+}
+
+#[test]
+fn did_shiver() {
+    let mut app = App::new();
+    app.add_systems(Update, shivering_breathing_demand);
+    let warm_id = app
+        .world_mut()
+        .spawn((BreathingDemand::default(), CoreTemperature::default()))
+        .id();
+    let cold_id = app
+        .world_mut()
+        .spawn((
+            BreathingDemand::default(),
+            CoreTemperature(SEVERE_HYPOTHERMIA),
+        ))
+        .id();
+    app.update();
+    assert_eq!(app.world().get::<BreathingDemand>(warm_id).unwrap().0, 1.);
+    assert_eq!(
+        app.world().get::<BreathingDemand>(cold_id).unwrap().0,
+        1. + SHIVERING_DEMAND
+    );
+}
+
+pub fn hypothermia_damage(
+    time: Res<Time>,
+    breathers: Query<(Entity, &CoreTemperature), With<Health>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, core) in &breathers {
+        if core.0 < SEVERE_HYPOTHERMIA {
+            damage_events.send(DamageEvent {
+                target: entity,
+                damage: HYPOTHERMIA_DAMAGE * (SEVERE_HYPOTHERMIA - core.0) * time.delta_seconds(),
+            });
+        }
+    }
+}
+
+#[test]
+fn did_hypothermia_damage() {
+    let mut app = App::new();
+    app.init_resource::<Time>();
+    app.add_event::<DamageEvent>();
+    app.add_systems(Update, hypothermia_damage);
+    let cold_id = app
+        .world_mut()
+        .spawn((CoreTemperature(SEVERE_HYPOTHERMIA - 2.), Health(100.)))
+        .id();
+    app.world_mut()
+        .spawn((CoreTemperature(MILD_HYPOTHERMIA), Health(100.)));
+    app.world_mut()
+        .resource_mut::<Time>()
+        .advance_by(std::time::Duration::from_secs(1));
+    app.update();
+    let damage_events = app.world().resource::<Events<DamageEvent>>();
+    let mut damage_reader = damage_events.get_reader();
+    let damages: Vec<&DamageEvent> = damage_reader.read(damage_events).collect();
+    assert_eq!(damages.len(), 1);
+    assert_eq!(damages[0].target, cold_id);
+    assert_eq!(damages[0].damage, 2. * HYPOTHERMIA_DAMAGE);
+}
@@ -15,6 +15,7 @@ pub enum InGameMenuState {
     #[default]
     NoMenu,
     Inventory,
+    FillStation,
 }
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
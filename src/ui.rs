@@ -1,9 +1,35 @@
+use crate::buoyancy::Buoyancy;
 use crate::diver::*;
+use crate::environment::DiveSiteEnvironment;
+use crate::equipment::PendingGasSwitch;
 use crate::health::*;
+use crate::position::{Depth, WaterTemperature};
+use crate::respiration::air_sharing::SharingAir;
+use crate::respiration::bcd::Bcd;
+use crate::respiration::chamber::ChamberTreatment;
+use crate::respiration::circulation::carbon_dioxide::Headache;
+use crate::respiration::circulation::decompression::*;
+use crate::respiration::circulation::impairment::Impairment;
+use crate::respiration::circulation::usage::GasUsageRate;
+use crate::respiration::ear::*;
+use crate::respiration::freediving::LungVolume;
+use crate::respiration::gas_mix::GasMix;
 use crate::respiration::inhalation::*;
+use crate::respiration::lungs::*;
+use crate::respiration::planner::*;
+use crate::respiration::rebreather::*;
+use crate::respiration::regulator::*;
+use crate::respiration::stops::*;
+use crate::respiration::stress::*;
+use crate::respiration::surface_interval::DiveSession;
+use crate::respiration::thermal::CoreTemperature;
 use bevy::prelude::*;
 
 pub const FONT_SIZE: f32 = 32.;
+// how much of the screen is blacked out on each side at full narcosis
+const MAX_TUNNEL_VISION_PERCENT: f32 = 30.;
+// opacity of the grey out at full hypoxia
+const MAX_GREY_OUT: f32 = 0.8;
 
 #[derive(Component, Reflect)]
 #[reflect(Component)]
@@ -17,19 +43,32 @@ pub struct CirculationText;
 #[reflect(Component)]
 pub struct EquipmentText;
 
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct ImpairmentOverlay;
+
 pub fn ui_plugin(app: &mut App) {
-    app.add_systems(Startup, (spawn_health_ui, spawn_equipment_ui));
+    app.add_systems(
+        Startup,
+        (
+            spawn_health_ui,
+            spawn_equipment_ui,
+            spawn_impairment_overlay,
+        ),
+    );
     app.add_systems(
         FixedUpdate,
         (
             update_health_ui.after(damage_health),
             update_respiration_ui.after(inhalation),
             update_equipment_ui,
+            update_impairment_overlay,
         ),
     );
     app.register_type::<HealthText>();
     app.register_type::<CirculationText>();
     app.register_type::<EquipmentText>();
+    app.register_type::<ImpairmentOverlay>();
 }
 
 pub fn spawn_health_ui(mut commands: Commands) {
@@ -79,35 +118,214 @@ pub fn spawn_health_ui(mut commands: Commands) {
         });
 }
 
+/// Conditions shown next to the diver's health.
+type Conditions = (
+    Has<Headache>,
+    Has<EarPain>,
+    Has<Vertigo>,
+    Has<Pneumothorax>,
+    Has<ArterialGasEmbolism>,
+    Option<&'static DecompressionSickness>,
+    Option<&'static ChamberTreatment>,
+);
+
+type Unsettled = (&'static Stress, Has<Panicking>, Has<Entangled>);
+
 pub fn update_health_ui(
+    environment: Res<DiveSiteEnvironment>,
     mut texts: Query<&mut Text, With<HealthText>>,
-    health_query: Query<&Health, With<Diver>>,
+    health_query: Query<(&Health, Conditions), With<Diver>>,
+    temperatures: Query<(&CoreTemperature, &WaterTemperature), With<Diver>>,
+    sessions: Query<(&DiveSession, &TissueCompartments), With<Diver>>,
+    stresses: Query<Unsettled, With<Diver>>,
 ) {
     for mut text in &mut texts {
-        if let Ok(health) = health_query.get_single() {
-            text.sections[0].value = format!("Health: {0:.0}", health.0);
+        if let Ok((health, (headache, ear_pain, vertigo, pneumothorax, embolism, dcs, treatment))) =
+            health_query.get_single()
+        {
+            let mut conditions: String = [
+                (headache, ", Headache"),
+                (ear_pain, ", Ear pain"),
+                (vertigo, ", Vertigo"),
+                (pneumothorax, ", Pneumothorax"),
+                (embolism, ", Gas embolism"),
+            ]
+            .iter()
+            .filter(|(present, _)| *present)
+            .map(|(_, condition)| *condition)
+            .collect();
+            match dcs.map(|dcs| dcs.dcs_type) {
+                Some(DcsType::TypeI) => conditions.push_str(", DCS (joint pain)"),
+                Some(DcsType::TypeII) => conditions.push_str(", DCS (neurological)"),
+                None => {}
+            }
+            if let Some(treatment) = treatment {
+                conditions.push_str(&format!(
+                    ", Chamber: {0:.0} m, {1:.0} min left",
+                    treatment.depth().unwrap_or(0.),
+                    treatment.remaining_minutes()
+                ));
+            }
+            if let Ok((stress, panicking, entangled)) = stresses.get_single() {
+                if panicking {
+                    conditions.push_str(", Panicking");
+                }
+                if entangled {
+                    conditions.push_str(", Entangled");
+                }
+                conditions.push_str(&format!(", Stress: {0:.0}%", stress.level * 100.));
+            }
+            if let Ok((core, water)) = temperatures.get_single() {
+                if core.shivering() > 0. {
+                    conditions.push_str(", Hypothermia");
+                }
+                conditions.push_str(&format!(
+                    ", Core: {0:.1} °C, Water: {1:.0} °C",
+                    core.0, water.0
+                ));
+            }
+            if let Ok((session, tissues)) = sessions.get_single() {
+                conditions.push_str(&format!(", Dive {}", session.dive_number.max(1)));
+                if !session.diving && session.is_repetitive() {
+                    conditions.push_str(&format!(
+                        ", Surface interval: {0:.0} min, RNT: {1:.0} min",
+                        session.surface_interval / 60.,
+                        residual_nitrogen_time(&environment, tissues)
+                    ));
+                }
+            }
+            text.sections[0].value = format!("Health: {0:.0}{1}", health.0, conditions);
         }
     }
 }
 
+/// What the diver's gauges and computer read from.
+type Readouts = (
+    Entity,
+    &'static BloodstreamContent,
+    &'static TissueCompartments,
+    &'static LungVolume,
+    &'static Depth,
+    Option<&'static SharingAir>,
+);
+
 pub fn update_respiration_ui(
+    environment: Res<DiveSiteEnvironment>,
     mut texts: Query<&mut Text, With<CirculationText>>,
-    diver_query: Query<(&BloodstreamContent, &EquippedTank), With<Diver>>,
-    cylinder_query: Query<&DivingCylinder>,
+    diver_query: Query<Readouts, With<Diver>>,
+    equipped_tanks: Query<&EquippedTank>,
+    computers: Query<(Option<&GasUsageRate>, Option<&Impairment>), With<Diver>>,
+    schedules: Query<&DecompressionSchedule, With<Diver>>,
+    cylinder_query: Query<(&DivingCylinder, Option<&Regulator>)>,
 ) {
     for mut text in &mut texts {
-        if let Ok((bloodstream, equipped_tank)) = diver_query.get_single() {
-            if let Ok(cylinder) = cylinder_query.get(equipped_tank.0) {
-                text.sections[0].value = format!(
-                    "Breath remaining: {0:.0}%, Tank remaining: {1:.0}%",
-                    (bloodstream.amount_remaining / bloodstream.capacity) * 100.,
-                    (cylinder.amount_remaining / cylinder.capacity) * 100.,
-                );
-            }
+        if let Ok((entity, bloodstream, tissues, lung_volume, depth, sharing)) =
+            diver_query.get_single()
+        {
+            // a narced diver can't read their gauges properly
+            let (usage_rate, impairment) = computers.get_single().unwrap_or((None, None));
+            let blur = |value: f32| impairment.map_or(value, |impairment| impairment.blur(value));
+            // while sharing air the gauge that matters is the donor's
+            let wearer = sharing.map_or(entity, |sharing| sharing.donor);
+            let equipped = equipped_tanks
+                .get(wearer)
+                .ok()
+                .and_then(|tank| cylinder_query.get(tank.0).ok());
+            let cylinder = equipped.map(|(cylinder, _)| cylinder);
+            let mix = cylinder.map_or(GasMix::AIR, |cylinder| cylinder.mix);
+            let plan = plan_ascent(&environment, tissues, depth.0, &mix);
+            let schedule = schedules.get_single().ok();
+            let next_stop = schedule.and_then(|schedule| schedule.stops.first());
+            let decompression = match (plan.no_decompression_limit, next_stop) {
+                (Some(limit), _) => format!("NDL: {0:.0} min", blur(limit)),
+                (None, Some(stop)) => format!(
+                    "Stop: {0:.0} m for {1:.0} min, TTS: {2:.0} min",
+                    stop.depth,
+                    blur(stop.minutes),
+                    blur(plan.time_to_surface)
+                ),
+                (None, None) => format!("TTS: {0:.0} min", blur(plan.time_to_surface)),
+            };
+            let safety_stop = match schedule {
+                Some(schedule) if schedule.safety_stop_due() => format!(
+                    ", Safety stop: {0:.0} m for {1:.0} s",
+                    SAFETY_STOP_DEPTH,
+                    blur(schedule.safety_stop_remaining)
+                ),
+                _ => String::new(),
+            };
+            let supply = match equipped {
+                Some((cylinder, regulator)) => format!(
+                    "SPG: {0:.0} bar ({1:.0} min){2}{3}",
+                    blur(cylinder.pressure()),
+                    blur(usage_rate.map_or(0., |usage_rate| {
+                        gas_time_remaining(
+                            &environment,
+                            cylinder.amount_remaining,
+                            usage_rate.0,
+                            depth.0,
+                        )
+                    })),
+                    match regulator.map(|regulator| (regulator.state, regulator.valve_open)) {
+                        Some((_, false)) => ", valve shut",
+                        Some((RegulatorState::FreeFlowing, true)) => ", free-flow",
+                        _ => "",
+                    },
+                    if sharing.is_some() {
+                        ", sharing air"
+                    } else {
+                        ""
+                    },
+                ),
+                None => format!(
+                    "Lungs: {0:.1} L",
+                    lung_volume.compressed(&environment, depth.0)
+                ),
+            };
+            text.sections[0].value = format!(
+                "Breath remaining: {0:.0}%, {1}, {2}{3}",
+                blur((bloodstream.amount_remaining / bloodstream.capacity) * 100.),
+                supply,
+                decompression,
+                safety_stop,
+            );
         }
     }
 }
 
+pub fn spawn_impairment_overlay(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            border_color: Srgba::rgb(0., 0., 0.).into(),
+            background_color: Srgba::new(0.5, 0.5, 0.5, 0.).into(),
+            z_index: ZIndex::Global(i32::MAX),
+            ..default()
+        },
+        ImpairmentOverlay,
+        Name::new("Impairment Overlay"),
+    ));
+}
+
+/// Narrows the diver's vision with narcosis and greys it out with hypoxia.
+pub fn update_impairment_overlay(
+    mut overlays: Query<(&mut Style, &mut BackgroundColor), With<ImpairmentOverlay>>,
+    impairments: Query<&Impairment, With<Diver>>,
+) {
+    let impairment = impairments.get_single().ok();
+    for (mut style, mut background_color) in &mut overlays {
+        let narcosis = impairment.map_or(0., |impairment| impairment.narcosis);
+        let hypoxia = impairment.map_or(0., |impairment| impairment.hypoxia);
+        style.border = UiRect::all(Val::Percent(MAX_TUNNEL_VISION_PERCENT * narcosis));
+        background_color.0 = Srgba::new(0.5, 0.5, 0.5, MAX_GREY_OUT * hypoxia).into();
+    }
+}
+
 pub fn spawn_equipment_ui(mut commands: Commands) {
     let container = NodeBundle {
         style: Style {
@@ -141,20 +359,74 @@ pub fn spawn_equipment_ui(mut commands: Commands) {
     commands.entity(container_id).push_children(&[text_id]);
 }
 
+/// Colour of the equipped gas on the HUD, for how safe it is at the current depth.
+fn suitability_colour(suitability: GasSuitability) -> Color {
+    match suitability {
+        GasSuitability::Breathable => Srgba::rgb(0.6, 1., 0.6),
+        GasSuitability::Marginal => Srgba::rgb(1., 0.8, 0.2),
+        GasSuitability::TooDeep | GasSuitability::TooShallow => Srgba::rgb(1., 0.3, 0.3),
+    }
+    .into()
+}
+
 pub fn update_equipment_ui(
+    environment: Res<DiveSiteEnvironment>,
     mut texts: Query<&mut Text, With<EquipmentText>>,
-    equipped_tanks: Query<&EquippedTank, With<Diver>>,
-    names: Query<&Name, With<DivingCylinder>>,
+    equipped_tanks: Query<(&EquippedTank, &Depth, Option<&PendingGasSwitch>), With<Diver>>,
+    equipped_rebreathers: Query<(&EquippedRebreather, &Depth), With<Diver>>,
+    rebreathers: Query<&Rebreather>,
+    cylinders: Query<(&Name, &DivingCylinder)>,
+    bcds: Query<(&Bcd, &Buoyancy, &Depth), With<Diver>>,
 ) {
     for mut text in &mut texts {
-        if let Ok(equipped_tank) = equipped_tanks.get_single() {
-            let cylinder_name = match names.get(equipped_tank.0) {
-                Ok(name) => name.as_str(),
-                _ => "",
+        let mut colour = Color::WHITE;
+        let supply = if let Some(rebreather) = equipped_rebreathers
+            .get_single()
+            .ok()
+            .and_then(|(equipped, depth)| Some((rebreathers.get(equipped.0).ok()?, depth)))
+        {
+            let (rebreather, depth) = rebreather;
+            format!(
+                "Rebreather PO2: {:.2}, Scrubber: {:.0}%",
+                rebreather.loop_po2(environment.ambient_pressure(depth.0)),
+                100. * rebreather.scrubber_remaining / DEFAULT_SCRUBBER_DURATION,
+            )
+        } else if let Ok((equipped_tank, depth, pending)) = equipped_tanks.get_single() {
+            let cylinder = match cylinders.get(equipped_tank.0) {
+                Ok((name, cylinder)) => {
+                    colour = suitability_colour(cylinder.suitability(&environment, depth.0));
+                    format!(
+                        "{} (MOD: {:.0} m)",
+                        name.as_str(),
+                        cylinder.maximum_operating_depth(&environment)
+                    )
+                }
+                _ => String::new(),
             };
-            text.sections[0].value = format!("Cylinder: {}", cylinder_name);
+            let warning = match pending.and_then(|pending| cylinders.get(pending.cylinder).ok()) {
+                Some((name, cylinder)) => format!(
+                    ", {} is {} here, switch again to confirm",
+                    name.as_str(),
+                    match cylinder.suitability(&environment, depth.0) {
+                        GasSuitability::TooShallow => "hypoxic",
+                        _ => "beyond its MOD",
+                    }
+                ),
+                None => String::new(),
+            };
+            format!("Cylinder: {}{}", cylinder, warning)
         } else {
-            text.sections[0].value = "".to_string();
-        }
+            "Freediving".to_string()
+        };
+        text.sections[0].value = match bcds.get_single() {
+            Ok((bcd, buoyancy, depth)) => format!(
+                "{}, BCD: {:.1} L, Buoyancy: {:+.1} kg",
+                supply,
+                bcd.volume(&environment, depth.0),
+                buoyancy.0,
+            ),
+            _ => supply,
+        };
+        text.sections[0].style.color = colour;
     }
 }